@@ -0,0 +1,92 @@
+use glam::Vec3;
+
+use crate::ray::Ray;
+
+// Axis-aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const fn new(min: Vec3, max: Vec3) -> Self {
+        return Self { min, max };
+    }
+
+    // A box that contains nothing. Merging anything into it yields the other box.
+    pub const fn empty() -> Self {
+        return Self {
+            min: Vec3::splat(f32::INFINITY),
+            max: Vec3::splat(f32::NEG_INFINITY),
+        };
+    }
+
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        return Aabb::new(self.min.min(other.min), self.max.max(other.max));
+    }
+
+    pub fn grow(&self, point: Vec3) -> Aabb {
+        return Aabb::new(self.min.min(point), self.max.max(point));
+    }
+
+    // Make sure no side is thinner than `delta`, so flat primitives (like rects) can still be hit
+    pub fn padded(&self, delta: f32) -> Aabb {
+        let size = self.max - self.min;
+        let padding = Vec3::new(
+            if size.x < delta { delta / 2.0 } else { 0.0 },
+            if size.y < delta { delta / 2.0 } else { 0.0 },
+            if size.z < delta { delta / 2.0 } else { 0.0 },
+        );
+
+        return Aabb::new(self.min - padding, self.max + padding);
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        return 0.5 * (self.min + self.max);
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+
+        if size.x < 0.0 || size.y < 0.0 || size.z < 0.0 {
+            return 0.0;
+        }
+
+        return 2.0 * (size.x * size.y + size.y * size.z + size.z * size.x);
+    }
+
+    /**
+     * Slab test. Returns true if the ray passes through the box somewhere in [t_min, t_max]
+     */
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        return self.intersect(ray, t_min, t_max).is_some();
+    }
+
+    // The part of [t_min, t_max] where the ray is inside the box, if any
+    pub fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_d;
+
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+
+            // Flat boxes are hit where both slab distances are equal. Padding can't be relied on for
+            // those, since it is lost to rounding far away from the origin
+            if t_max < t_min {
+                return None;
+            }
+        }
+
+        return Some((t_min, t_max));
+    }
+}
@@ -0,0 +1,905 @@
+use std::f32::consts::TAU;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use glam::Vec3;
+use rand::{Rng, RngCore};
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::integrator::{power_heuristic, sample_punctual_lights, Integrator};
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::vec3::Vec3Extension;
+
+/**
+ * Bidirectional path tracing, after E. Veach, "Robust Monte Carlo Methods for Light Transport Simulation"
+ * (1997). For every camera ray a second path is traced from a random point on the lights, and every vertex
+ * of one path is connected to every vertex of the other. Each connection is another way of finding the same
+ * light, and they are weighed against each other with multiple importance sampling. Light focused by glass
+ * onto a diffuse surface is found by the paths from the lights, where the camera path could only find it by
+ * chance.
+ *
+ * Paths from the lights that are connected straight to the camera land on whichever pixel sees them, so they
+ * are splatted onto the image rather than returned by li.
+ *
+ * Paths are only traced from the lights of the world. Point, spot and directional lights and the background
+ * are sampled from the camera path like the path integrator does. Bounces are limited by max_depth only, not
+ * by the depth of each lobe.
+ */
+pub struct BdptIntegrator {
+    max_depth: i32,
+    roulette_depth: i32,
+    width: usize,
+    height: usize,
+    // get_ray is called with s and t up to these, as the last pixel of a row or column starts at 1
+    s_max: f32,
+    t_max: f32,
+    /*
+    Light splatted onto each pixel, summed over all samples. Stored in fixed point so threads can add to it,
+    and so the sum doesn't depend on the order the threads add in, which keeps seeded renders reproducible
+    */
+    splats: Vec<[AtomicU64; 3]>,
+}
+
+// Splats are stored as multiples of 2⁻³², which leaves room for sums up to about 4 billion
+const SPLAT_SCALE: f64 = (1u64 << 32) as f64;
+
+impl BdptIntegrator {
+    pub fn new(max_depth: i32, roulette_depth: i32, width: usize, height: usize) -> Self {
+        return Self {
+            max_depth,
+            roulette_depth,
+            width,
+            height,
+            s_max: width as f32 / (width - 1) as f32,
+            t_max: height as f32 / (height - 1) as f32,
+            splats: (0..width * height)
+                .map(|_| std::array::from_fn(|_| AtomicU64::new(0)))
+                .collect(),
+        };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum VertexKind {
+    Camera,
+    // The point on a light a path from the lights starts at
+    Light,
+    Surface,
+}
+
+#[derive(Clone, Copy)]
+struct Vertex<'a> {
+    kind: VertexKind,
+    point: Vec3,
+    // None for the camera. Lights have their outward normal, surfaces face the previous vertex of the path
+    rec: Option<HitRecord<'a>>,
+    // Unit vector toward the previous vertex of the path, zero for the first one
+    wo: Vec3,
+    // Light carried to the vertex over the density of the path so far. On paths from the lights, it is
+    // the light given off instead of the light arriving
+    beta: Vec3,
+    // Density per unit of area of the previous vertex of the path picking this one...
+    pdf_fwd: f32,
+    // ...and of the next vertex picking it, had the path been traced the other way
+    pdf_rev: f32,
+    // Scattered by a mirror or glass, which can't be connected to
+    delta: bool,
+    // On a path from the lights, whose scattering is evaluated the other way around
+    from_light: bool,
+}
+
+impl<'a> Vertex<'a> {
+    fn camera(point: Vec3) -> Self {
+        return Self {
+            kind: VertexKind::Camera,
+            point,
+            rec: None,
+            wo: Vec3::ZERO,
+            beta: Vec3::ONE,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+            from_light: false,
+        };
+    }
+
+    fn light(rec: HitRecord<'a>, beta: Vec3, pdf_fwd: f32) -> Self {
+        return Self {
+            kind: VertexKind::Light,
+            point: rec.point,
+            rec: Some(rec),
+            wo: Vec3::ZERO,
+            beta,
+            pdf_fwd,
+            pdf_rev: 0.0,
+            delta: false,
+            from_light: true,
+        };
+    }
+
+    fn surface(rec: HitRecord<'a>, wo: Vec3, beta: Vec3, from_light: bool) -> Self {
+        return Self {
+            kind: VertexKind::Surface,
+            point: rec.point,
+            rec: Some(rec),
+            wo,
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+            from_light,
+        };
+    }
+
+    fn is_volumetric(&self) -> bool {
+        return self.rec.is_some_and(|rec| rec.material.is_volumetric());
+    }
+
+    /**
+     * Cosine between the unit vector and the normal at the vertex, which turns densities per unit of solid
+     * angle into ones per unit of area. At the camera it is taken to the direction the camera looks in
+     */
+    fn cosine(&self, direction: Vec3, scene: &Scene) -> f32 {
+        return match self.rec {
+            None => scene.camera.cos_theta(direction).abs(),
+            Some(_) if self.is_volumetric() => 1.0,
+            Some(rec) => rec.normal.dot(direction).abs(),
+        };
+    }
+
+    fn emitted(&self) -> Vec3 {
+        return match self.rec {
+            Some(rec) => rec.material.emitted(rec.u, rec.v, rec.point),
+            None => Vec3::ZERO,
+        };
+    }
+
+    /**
+     * Light scattered at the vertex between the previous vertex and the unit vector `toward`, including the
+     * cosine toward it. On paths from the lights the light arrives from the previous vertex instead of
+     * leaving toward it, so the material is evaluated with the directions swapped. Materials include the
+     * cosine of the direction the light arrives from, which is swapped for the one toward `toward`.
+     */
+    fn f(&self, toward: Vec3, time: f32) -> Vec3 {
+        let Some(rec) = self.rec else {
+            return Vec3::ZERO;
+        };
+
+        let (to_camera, to_light) = match self.from_light {
+            true => (toward, self.wo),
+            false => (self.wo, toward),
+        };
+
+        let rec = rec.facing(to_camera);
+        let ray_in = Ray::new(rec.point + to_camera, -to_camera, time);
+        let scattering = rec.material.eval(&ray_in, &rec, to_light);
+
+        if !self.from_light || rec.material.is_volumetric() {
+            return scattering;
+        }
+
+        let cos_light = rec.normal.dot(to_light).abs();
+        if cos_light <= 0.0 {
+            return Vec3::ZERO;
+        }
+
+        return scattering * rec.normal.dot(to_camera).abs() / cos_light;
+    }
+
+    // Density per unit of solid angle of the material picking `sampled` when arriving from `given`
+    fn pdf_direction(&self, given: Vec3, sampled: Vec3, time: f32) -> f32 {
+        let Some(rec) = self.rec else {
+            return 0.0;
+        };
+
+        let rec = rec.facing(given);
+        let ray_in = Ray::new(rec.point + given, -given, time);
+
+        return rec.material.pdf(&ray_in, &rec, sampled);
+    }
+}
+
+// Where a path leaves the scene without hitting anything
+struct Escape {
+    direction: Vec3,
+    beta: Vec3,
+    // Density of the bounce that sent the path away, or None for the camera and specular bounces
+    pdf: Option<f32>,
+}
+
+impl Integrator for BdptIntegrator {
+    fn li(&self, ray: Ray, scene: &Scene, sampler: &mut dyn RngCore) -> Vec3 {
+        let time = ray.time;
+        let max_depth = self.max_depth.max(0) as usize;
+
+        // The camera path has a vertex for every bounce and one more for the light it ends on
+        let mut camera_path = vec![Vertex::camera(ray.origin)];
+        let pdf = scene
+            .camera
+            .direction_pdf(ray.origin, ray.direction, self.s_max, self.t_max);
+        let escape = self.random_walk(
+            scene,
+            Ray::new(ray.origin, ray.direction.normalize(), time),
+            Vec3::ONE,
+            pdf,
+            max_depth + 2,
+            sampler,
+            &mut camera_path,
+        );
+
+        let light_path = self.light_path(scene, time, max_depth + 1, sampler);
+
+        let mut color = Vec3::ZERO;
+
+        if let Some(escape) = escape {
+            let radiance = scene.background.radiance(escape.direction);
+            let weight = match escape.pdf {
+                Some(pdf) => power_heuristic(pdf, scene.background.pdf_value(escape.direction)),
+                None => 1.0,
+            };
+
+            color += escape.beta * radiance * weight;
+        }
+
+        // Light from outside of the world is only found from the camera path
+        for vertex in camera_path.iter().skip(1).take(max_depth) {
+            if vertex.delta {
+                continue;
+            }
+
+            let rec = vertex.rec.expect("surface vertices have a hit record");
+            let ray_in = Ray::new(vertex.point + vertex.wo, -vertex.wo, time);
+
+            color += vertex.beta
+                * (sample_punctual_lights(&ray_in, &rec, scene)
+                    + sample_background(vertex, scene, time));
+        }
+
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len().max(1) {
+                let depth = (s + t) as i32 - 2;
+
+                if (s == 1 && t == 1) || depth < 0 || depth > max_depth as i32 {
+                    continue;
+                }
+
+                if t == 1 {
+                    self.connect_to_camera(scene, time, &light_path[..s]);
+                    continue;
+                }
+
+                color += self.connect(scene, time, &light_path, &camera_path[..t], s);
+            }
+        }
+
+        return color;
+    }
+
+    fn splatted(&self, i: usize, j: usize) -> Vec3 {
+        let pixel = &self.splats[j * self.width + i];
+        return Vec3::from(pixel.each_ref().map(|channel| {
+            return (channel.load(Ordering::Relaxed) as f64 / SPLAT_SCALE) as f32;
+        }));
+    }
+}
+
+impl BdptIntegrator {
+    /**
+     * Extend the path from its last vertex along `ray`, which was picked with density `pdf` per unit of solid
+     * angle, until it has max_vertices vertices or ends. Returns where it left the scene, if it did.
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk<'a>(
+        &self,
+        scene: &'a Scene,
+        ray: Ray,
+        beta: Vec3,
+        pdf: f32,
+        max_vertices: usize,
+        sampler: &mut dyn RngCore,
+        path: &mut Vec<Vertex<'a>>,
+    ) -> Option<Escape> {
+        let from_light = path[0].from_light;
+        let mut ray = ray;
+        let mut beta = beta;
+        let mut pdf_fwd = pdf;
+        let mut bsdf_pdf = None;
+        let mut bounces = 0;
+
+        loop {
+            let Some(rec) = scene.world.hit(&ray, 0.001, f32::INFINITY) else {
+                return Some(Escape {
+                    direction: ray.direction,
+                    beta,
+                    pdf: bsdf_pdf,
+                });
+            };
+
+            let mut vertex = Vertex::surface(rec, -ray.direction, beta, from_light);
+            vertex.pdf_fwd = to_area(pdf_fwd, path.last().unwrap(), &vertex, scene);
+            path.push(vertex);
+
+            if path.len() >= max_vertices {
+                return None;
+            }
+
+            let sample = rec.material.sample(&ray, &rec)?;
+            let direction = sample.scattered.direction.normalize();
+
+            let current = path.last_mut().unwrap();
+            let pdf_rev;
+
+            if rec.material.is_specular() {
+                current.delta = true;
+                pdf_fwd = 0.0;
+                pdf_rev = 0.0;
+                bsdf_pdf = None;
+
+                beta *= match from_light {
+                    true => sample.attenuation * rec.material.adjoint_scale(&rec, sample.lobe),
+                    false => sample.attenuation,
+                };
+            } else {
+                if sample.pdf <= 0.0 {
+                    return None;
+                }
+
+                pdf_fwd = sample.pdf;
+                pdf_rev = current.pdf_direction(direction, current.wo, ray.time);
+                bsdf_pdf = Some(sample.pdf);
+
+                // The attenuation is only eval over pdf in the direction light travels from the camera
+                beta *= match from_light {
+                    true => current.f(direction, ray.time) / sample.pdf,
+                    false => sample.attenuation,
+                };
+            }
+
+            let count = path.len();
+            path[count - 2].pdf_rev = to_area(pdf_rev, &path[count - 1], &path[count - 2], scene);
+
+            bounces += 1;
+            if bounces > self.roulette_depth {
+                let survival = beta.max_element().min(1.0);
+
+                if sampler.gen_range(0.0..1.0) >= survival {
+                    return None;
+                }
+
+                beta /= survival;
+            }
+
+            ray = Ray::new(rec.point, direction, ray.time);
+        }
+    }
+
+    /**
+     * A path from a random point on the lights of the world. Diffuse lights shine from both sides, so the
+     * first direction is picked proportionally to the cosine on a random side.
+     */
+    fn light_path<'a>(
+        &self,
+        scene: &'a Scene,
+        time: f32,
+        max_vertices: usize,
+        sampler: &mut dyn RngCore,
+    ) -> Vec<Vertex<'a>> {
+        let mut path = Vec::new();
+
+        if !scene.world.is_light() {
+            return path;
+        }
+
+        let Some((rec, pdf_position)) = scene.world.sample_surface(time) else {
+            return path;
+        };
+
+        let emitted = rec.material.emitted(rec.u, rec.v, rec.point);
+        if pdf_position <= 0.0 || emitted == Vec3::ZERO {
+            return path;
+        }
+
+        let side = match sampler.gen_bool(0.5) {
+            true => rec.normal,
+            false => -rec.normal,
+        };
+
+        let mut direction = side + Vec3::random_unit_vector();
+        if direction.is_near_zero() || direction.dot(side) <= 0.0 {
+            direction = side;
+        }
+        let direction = direction.normalize();
+
+        let light = Vertex::light(rec, emitted / pdf_position, pdf_position);
+        let pdf_direction = emission_pdf(&light, direction);
+        path.push(light);
+
+        let beta = emitted * side.dot(direction) / (pdf_position * pdf_direction);
+
+        self.random_walk(
+            scene,
+            Ray::new(rec.point, direction, time),
+            beta,
+            pdf_direction,
+            max_vertices,
+            sampler,
+            &mut path,
+        );
+
+        return path;
+    }
+
+    /**
+     * Light from connecting the first s vertices of the light path to the end of the camera path. Without
+     * any vertices from the light path, the camera path has to have ended on a light by itself. With one,
+     * a new point on the lights is picked by light sampling.
+     */
+    fn connect(
+        &self,
+        scene: &Scene,
+        time: f32,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+    ) -> Vec3 {
+        let pt = camera_path.last().unwrap();
+
+        if s == 0 {
+            let emitted = pt.emitted();
+            if emitted == Vec3::ZERO {
+                return Vec3::ZERO;
+            }
+
+            return pt.beta * emitted * self.mis_weight(scene, time, &[], camera_path, None);
+        }
+
+        if pt.delta {
+            return Vec3::ZERO;
+        }
+
+        if s == 1 {
+            if !scene.world.is_light() {
+                return Vec3::ZERO;
+            }
+
+            let direction = scene.world.random_toward(pt.point, time).normalize();
+
+            let scattering = pt.f(direction, time);
+            if scattering == Vec3::ZERO {
+                return Vec3::ZERO;
+            }
+
+            let light_pdf = scene.world.pdf_value(pt.point, direction, time);
+            if light_pdf <= 0.0 {
+                return Vec3::ZERO;
+            }
+
+            // Whatever the shadow ray hits first is where the light comes from
+            let shadow_ray = Ray::new(pt.point, direction, time);
+            let Some(light) = scene.world.hit(&shadow_ray, 0.001, f32::INFINITY) else {
+                return Vec3::ZERO;
+            };
+
+            let emitted = light.material.emitted(light.u, light.v, light.point);
+            if emitted == Vec3::ZERO {
+                return Vec3::ZERO;
+            }
+
+            let mut sampled = Vertex::light(light, emitted / light_pdf, 0.0);
+            sampled.pdf_fwd = light_pdf * sampled.cosine(direction, scene)
+                / (light.point - pt.point).length_squared();
+
+            let weight = self.mis_weight(scene, time, &[sampled], camera_path, None);
+
+            return pt.beta * scattering * sampled.beta * weight;
+        }
+
+        let qs = &light_path[s - 1];
+        if qs.delta {
+            return Vec3::ZERO;
+        }
+
+        let offset = qs.point - pt.point;
+        let distance_squared = offset.length_squared();
+        let direction = offset / distance_squared.sqrt();
+
+        let contribution =
+            qs.beta * qs.f(-direction, time) * pt.f(direction, time) * pt.beta / distance_squared;
+
+        if contribution == Vec3::ZERO {
+            return Vec3::ZERO;
+        }
+
+        let transmittance = transmittance(scene, pt.point, qs.point, time);
+        if transmittance == 0.0 {
+            return Vec3::ZERO;
+        }
+
+        let weight = self.mis_weight(scene, time, &light_path[..s], camera_path, None);
+        return contribution * transmittance * weight;
+    }
+
+    /**
+     * Connect the end of the light path to a random point on the lens, and splat the light onto the pixel
+     * the lens sees it in
+     */
+    fn connect_to_camera(&self, scene: &Scene, time: f32, light_path: &[Vertex]) {
+        let qs = light_path.last().unwrap();
+        if qs.delta {
+            return;
+        }
+
+        let lens_point = scene.camera.random_lens_point();
+        let offset = lens_point - qs.point;
+        let distance_squared = offset.length_squared();
+        let toward_camera = offset / distance_squared.sqrt();
+
+        let Some((s, t)) = scene.camera.film_coordinates(lens_point, -toward_camera) else {
+            return;
+        };
+
+        /*
+        The importance of the camera over the density of the lens point, times the cosine at the lens, comes
+        down to the density of the camera picking the direction. Together with the cosine at qs, included in
+        f, and the distance this is the light arriving at the pixel.
+        */
+        let pdf = scene
+            .camera
+            .direction_pdf(lens_point, -toward_camera, self.s_max, self.t_max);
+        if pdf <= 0.0 {
+            return;
+        }
+
+        let contribution = qs.beta * qs.f(toward_camera, time) * pdf / distance_squared;
+
+        if contribution == Vec3::ZERO {
+            return;
+        }
+
+        let transmittance = transmittance(scene, qs.point, lens_point, time);
+        if transmittance == 0.0 {
+            return;
+        }
+
+        let camera = Vertex::camera(lens_point);
+        let weight = self.mis_weight(scene, time, light_path, &[], Some(camera));
+
+        self.splat(s, t, contribution * transmittance * weight);
+    }
+
+    /**
+     * Weight of the path made of the given vertices of the light and camera paths, against all the other
+     * ways of splitting it between the two. `camera` stands in for an empty camera path when connecting to
+     * the camera. Uses the power heuristic, by walking along the path and multiplying up the ratios of the
+     * densities of picking each vertex from the other side over picking it from its own.
+     */
+    fn mis_weight(
+        &self,
+        scene: &Scene,
+        time: f32,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        camera: Option<Vertex>,
+    ) -> f32 {
+        let s = light_path.len();
+        let mut camera_path = camera_path.to_vec();
+        if let Some(camera) = camera {
+            camera_path.push(camera);
+        }
+        let t = camera_path.len();
+
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        let mut light_path = light_path.to_vec();
+
+        /*
+        The point on the light is picked by light sampling when connecting a single vertex from the lights,
+        but by sample_surface when paths start from the lights, so its density depends on the strategy.
+        Both are evaluated from the vertex next to it, so every strategy weighs the path the same.
+        */
+        let (origin, next) = match s {
+            0 => (&camera_path[t - 1], &camera_path[t - 2]),
+            1 => (&light_path[0], &camera_path[t - 1]),
+            _ => (&light_path[0], &light_path[1]),
+        };
+        let sampled_pdf = pdf_light_origin(scene, time, origin, next);
+        let emitted_pdf = pdf_emission_origin(scene, time, origin, next);
+        let origin_pdf = |strategy: usize| match strategy {
+            1 => sampled_pdf,
+            _ => emitted_pdf,
+        };
+
+        if s > 0 {
+            light_path[0].pdf_fwd = origin_pdf(s);
+        }
+
+        // The vertices at the connection were picked from the other side rather than from their neighbors
+        let pt = &camera_path[t - 1];
+        let pt_minus = t.checked_sub(2).map(|i| &camera_path[i]);
+        let qs = s.checked_sub(1).map(|i| &light_path[i]);
+        let qs_minus = s.checked_sub(2).map(|i| &light_path[i]);
+
+        let (pt_rev, pt_minus_rev, qs_rev, qs_minus_rev) = match qs {
+            Some(qs) => (
+                self.pdf(scene, time, qs, qs_minus, pt),
+                pt_minus.map(|pt_minus| self.pdf(scene, time, pt, Some(qs), pt_minus)),
+                Some(self.pdf(scene, time, pt, pt_minus, qs)),
+                qs_minus.map(|qs_minus| self.pdf(scene, time, qs, Some(pt), qs_minus)),
+            ),
+            // Without a light path, the camera path ended on a light by itself
+            None => {
+                let pt_minus = pt_minus.expect("a camera path ending on a light has two vertices");
+                let direction = (pt_minus.point - pt.point).normalize();
+
+                (
+                    pdf_light_origin(scene, time, pt, pt_minus),
+                    Some(to_area(emission_pdf(pt, direction), pt, pt_minus, scene)),
+                    None,
+                    None,
+                )
+            }
+        };
+
+        camera_path[t - 1].pdf_rev = pt_rev;
+        camera_path[t - 1].delta = false;
+        if let Some(pdf) = pt_minus_rev {
+            camera_path[t - 2].pdf_rev = pdf;
+        }
+        if let Some(pdf) = qs_rev {
+            light_path[s - 1].pdf_rev = pdf;
+            light_path[s - 1].delta = false;
+        }
+        if let Some(pdf) = qs_minus_rev {
+            light_path[s - 2].pdf_rev = pdf;
+        }
+
+        // A path reaching the light by itself has the point picked as if light sampling did it
+        let current_origin_pdf = match s {
+            0 => sampled_pdf,
+            _ => origin_pdf(s),
+        };
+        if current_origin_pdf <= 0.0 {
+            return 0.0;
+        }
+        let origin_ratio = |strategy: usize| (origin_pdf(strategy) / current_origin_pdf).powi(2);
+
+        let mut sum = 0.0;
+
+        // Strategies with fewer vertices from the camera. Delta vertices can't be connected at, so they end no strategy
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= squared_ratio(camera_path[i].pdf_rev, camera_path[i].pdf_fwd);
+
+            if !camera_path[i].delta && !camera_path[i - 1].delta {
+                sum += ratio * origin_ratio(s + t - i);
+            }
+        }
+
+        // Strategies with fewer vertices from the lights. Without any, the point on the light isn't picked
+        let mut ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= squared_ratio(light_path[i].pdf_rev, light_path[i].pdf_fwd);
+
+            let previous_delta = i > 0 && light_path[i - 1].delta;
+            if !light_path[i].delta && !previous_delta {
+                sum += match i {
+                    0 => ratio,
+                    _ => ratio * origin_ratio(i),
+                };
+            }
+        }
+
+        return 1.0 / (1.0 + sum);
+    }
+
+    // Density per unit of area of `vertex` picking `next`, when the path arrived at it from `previous`
+    fn pdf(
+        &self,
+        scene: &Scene,
+        time: f32,
+        vertex: &Vertex,
+        previous: Option<&Vertex>,
+        next: &Vertex,
+    ) -> f32 {
+        let direction = (next.point - vertex.point).normalize();
+
+        let pdf = match vertex.kind {
+            VertexKind::Camera => {
+                scene
+                    .camera
+                    .direction_pdf(vertex.point, direction, self.s_max, self.t_max)
+            }
+            VertexKind::Light => emission_pdf(vertex, direction),
+            VertexKind::Surface => match previous {
+                Some(previous) => {
+                    let given = (previous.point - vertex.point).normalize();
+                    vertex.pdf_direction(given, direction, time)
+                }
+                None => 0.0,
+            },
+        };
+
+        return to_area(pdf, vertex, next, scene);
+    }
+
+    // Add light to the pixel the camera sees at (s, t)
+    fn splat(&self, s: f32, t: f32, color: Vec3) {
+        let i = ((s * (self.width - 1) as f32) as usize).min(self.width - 1);
+        let j = ((t * (self.height - 1) as f32) as usize).min(self.height - 1);
+
+        let pixel = &self.splats[j * self.width + i];
+
+        for (channel, value) in pixel.iter().zip(color.to_array()) {
+            // NaNs and negative light come from broken samples, which shouldn't darken the pixel
+            if !value.is_finite() || value <= 0.0 {
+                continue;
+            }
+
+            // Sums past the fixed point range stay at its top instead of wrapping around
+            let amount = (value as f64 * SPLAT_SCALE).round() as u64;
+            let _ = channel.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                return Some(sum.saturating_add(amount));
+            });
+        }
+    }
+}
+
+// Turn a density per unit of solid angle at `from` into one per unit of area at `to`
+fn to_area(pdf: f32, from: &Vertex, to: &Vertex, scene: &Scene) -> f32 {
+    let offset = to.point - from.point;
+    let distance_squared = offset.length_squared();
+
+    if distance_squared <= 0.0 {
+        return 0.0;
+    }
+
+    let direction = offset / distance_squared.sqrt();
+    return pdf * to.cosine(direction, scene) / distance_squared;
+}
+
+// Density per unit of solid angle of light_path picking the unit vector `direction` from a point on a light
+fn emission_pdf(light: &Vertex, direction: Vec3) -> f32 {
+    return match light.rec {
+        Some(rec) => rec.normal.dot(direction).abs() / TAU,
+        None => 0.0,
+    };
+}
+
+// Density per unit of area of light sampling from `from` picking the point `light`
+fn pdf_light_origin(scene: &Scene, time: f32, light: &Vertex, from: &Vertex) -> f32 {
+    let direction = (light.point - from.point).normalize();
+    let pdf = scene.world.pdf_value(from.point, direction, time);
+
+    return to_area(pdf, from, light, scene);
+}
+
+// Density per unit of area of sample_surface picking the point `light`, found from `from`
+fn pdf_emission_origin(scene: &Scene, time: f32, light: &Vertex, from: &Vertex) -> f32 {
+    let direction = (light.point - from.point).normalize();
+    return scene.world.surface_pdf(from.point, direction, time);
+}
+
+/**
+ * Light from the background arriving at the vertex, when the background is sampled as a light, weighed
+ * against the camera path leaving the scene in the same direction
+ */
+fn sample_background(vertex: &Vertex, scene: &Scene, time: f32) -> Vec3 {
+    if !scene.background.is_light() {
+        return Vec3::ZERO;
+    }
+
+    let direction = scene.background.random_direction().normalize();
+
+    let background_pdf = scene.background.pdf_value(direction);
+    if background_pdf <= 0.0 {
+        return Vec3::ZERO;
+    }
+
+    let scattering = vertex.f(direction, time);
+    if scattering == Vec3::ZERO {
+        return Vec3::ZERO;
+    }
+
+    let shadow_ray = Ray::new(vertex.point, direction, time);
+    let transmittance = scene.world.transmittance(&shadow_ray, 0.001, f32::INFINITY);
+    if transmittance == 0.0 {
+        return Vec3::ZERO;
+    }
+
+    let weight = power_heuristic(
+        background_pdf,
+        vertex.pdf_direction(vertex.wo, direction, time),
+    );
+
+    let radiance = scene.background.radiance(direction);
+    return scattering * radiance * transmittance * weight / background_pdf;
+}
+
+// Fraction of the light that gets from one point to the other, zero if something solid lies between
+fn transmittance(scene: &Scene, from: Vec3, to: Vec3, time: f32) -> f32 {
+    let offset = to - from;
+    let distance = offset.length();
+    let ray = Ray::new(from, offset / distance, time);
+
+    return scene.world.transmittance(&ray, 0.001, distance - 0.001);
+}
+
+// (numerator / denominator)², where densities of zero, as at specular vertices, count as 1
+fn squared_ratio(numerator: f32, denominator: f32) -> f32 {
+    let remap = |pdf: f32| if pdf != 0.0 { pdf } else { 1.0 };
+    let ratio = remap(numerator) / remap(denominator);
+
+    return ratio * ratio;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splats_sum_the_same_in_any_order() {
+        let values = [0.1, 1e-6, 3.7, 0.25, 12.0, 1.0 / 3.0];
+        let forward = BdptIntegrator::new(5, 3, 4, 3);
+        let backward = BdptIntegrator::new(5, 3, 4, 3);
+
+        for value in values {
+            forward.splat(0.5, 0.5, Vec3::new(value, 2.0 * value, 0.0));
+        }
+
+        for value in values.iter().rev() {
+            backward.splat(0.5, 0.5, Vec3::new(*value, 2.0 * *value, 0.0));
+        }
+
+        let sum: f32 = values.iter().sum();
+        assert_eq!(forward.splatted(1, 1), backward.splatted(1, 1));
+        assert!(
+            (forward.splatted(1, 1) - Vec3::new(sum, 2.0 * sum, 0.0))
+                .abs()
+                .max_element()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn splats_land_on_the_pixel_the_camera_sees() {
+        let integrator = BdptIntegrator::new(5, 3, 4, 3);
+
+        integrator.splat(0.0, 0.0, Vec3::X);
+        integrator.splat(integrator.s_max, integrator.t_max, Vec3::Y);
+        integrator.splat(0.4, 0.6, Vec3::Z);
+
+        assert_eq!(integrator.splatted(0, 0), Vec3::X);
+        assert_eq!(integrator.splatted(3, 2), Vec3::Y);
+        assert_eq!(integrator.splatted(1, 1), Vec3::Z);
+    }
+
+    #[test]
+    fn invalid_splats_add_nothing() {
+        let integrator = BdptIntegrator::new(5, 3, 2, 2);
+        integrator.splat(0.0, 0.0, Vec3::new(f32::NAN, -1.0, 0.5));
+        integrator.splat(0.0, 0.0, Vec3::new(f32::INFINITY, f32::NEG_INFINITY, 0.0));
+
+        assert_eq!(integrator.splatted(0, 0), Vec3::new(0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn huge_splats_saturate_instead_of_wrapping_around() {
+        let integrator = BdptIntegrator::new(5, 3, 2, 2);
+        let limit = (u64::MAX as f64 / SPLAT_SCALE) as f32;
+
+        integrator.splat(0.0, 0.0, Vec3::new(1e30, 3e9, 1.0));
+        integrator.splat(0.0, 0.0, Vec3::new(1.0, 3e9, 1.0));
+
+        assert_eq!(integrator.splatted(0, 0), Vec3::new(limit, limit, 2.0));
+    }
+
+    #[test]
+    fn zero_densities_count_as_one_in_ratios() {
+        assert_eq!(squared_ratio(2.0, 4.0), 0.25);
+        assert_eq!(squared_ratio(0.0, 4.0), 1.0 / 16.0);
+        assert_eq!(squared_ratio(3.0, 0.0), 9.0);
+        assert_eq!(squared_ratio(0.0, 0.0), 1.0);
+    }
+}
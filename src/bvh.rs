@@ -0,0 +1,501 @@
+use std::sync::Arc;
+
+use glam::Vec3;
+use rand::Rng;
+
+use crate::aabb::Aabb;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::random;
+use crate::ray::Ray;
+
+// Relative cost of visiting a node compared to intersecting a primitive, used by the SAH
+const TRAVERSAL_COST: f32 = 0.125;
+
+// Nodes with this many objects (or fewer) may become leaves if the SAH says splitting isn't worth it
+const MAX_LEAF_SIZE: usize = 4;
+
+pub enum BvhNode {
+    Leaf {
+        bbox: Aabb,
+        objects: Vec<Box<dyn Hittable>>,
+    },
+    Branch {
+        bbox: Aabb,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+/**
+ * Bounding volume hierarchy built with the surface area heuristic.
+ * Can be used anywhere a HittableList is used, but intersects in logarithmic time.
+ */
+pub struct Bvh {
+    root: Option<BvhNode>,
+    // Objects without a bounding box can't be placed in the tree, so they are tested separately
+    unbounded: HittableList,
+    // The objects that give off light, shared with the tree so they can be sampled directly
+    lights: Vec<Arc<dyn Hittable>>,
+    // The bounded lights again, in a tree of their own so densities only look at lights near the ray
+    light_tree: Option<BvhNode>,
+    unbounded_lights: Vec<Arc<dyn Hittable>>,
+}
+
+impl Bvh {
+    pub fn new(list: HittableList) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = HittableList::new();
+        let mut lights = Vec::new();
+        let mut bounded_lights = Vec::new();
+        let mut unbounded_lights = Vec::new();
+
+        for mut object in list.into_objects() {
+            if object.is_light() {
+                let light: Arc<dyn Hittable> = Arc::from(object);
+                lights.push(Arc::clone(&light));
+
+                match light.bounding_box() {
+                    Some(bbox) => bounded_lights.push((bbox, Box::new(Arc::clone(&light)) as _)),
+                    None => unbounded_lights.push(Arc::clone(&light)),
+                }
+
+                object = Box::new(light);
+            }
+
+            match object.bounding_box() {
+                Some(bbox) => bounded.push((bbox, object)),
+                None => unbounded.add(object),
+            }
+        }
+
+        return Self {
+            root: BvhNode::build_if_any(bounded),
+            unbounded,
+            lights,
+            light_tree: BvhNode::build_if_any(bounded_lights),
+            unbounded_lights,
+        };
+    }
+
+    fn random_light(&self) -> Option<&dyn Hittable> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let index = random::rng().gen_range(0..self.lights.len());
+        return Some(self.lights[index].as_ref());
+    }
+
+    // Calls `visit` with every light the ray could reach, skipping those whose bounding boxes it misses
+    fn lights_along<'a>(&'a self, ray: &Ray, visit: &mut dyn FnMut(&'a dyn Hittable)) {
+        if let Some(light_tree) = &self.light_tree {
+            light_tree.for_each_along(ray, visit);
+        }
+
+        for light in &self.unbounded_lights {
+            visit(light.as_ref());
+        }
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest_so_far = t_max;
+        let mut temp_rec = None;
+
+        if let Some(root) = &self.root {
+            if let Some(rec) = root.hit(ray, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                temp_rec = Some(rec);
+            }
+        }
+
+        if let Some(rec) = self.unbounded.hit(ray, t_min, closest_so_far) {
+            temp_rec = Some(rec);
+        }
+
+        return temp_rec;
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let transmittance = match &self.root {
+            Some(root) => root.transmittance(ray, t_min, t_max),
+            None => 1.0,
+        };
+
+        if transmittance == 0.0 {
+            return 0.0;
+        }
+
+        return transmittance * self.unbounded.transmittance(ray, t_min, t_max);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+
+        return self.root.as_ref().map(|root| *root.bbox());
+    }
+
+    fn is_light(&self) -> bool {
+        return !self.lights.is_empty();
+    }
+
+    // The lights are picked with equal probability, so the density is their average
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+
+        let mut sum = 0.0;
+        self.lights_along(&Ray::new(origin, direction, time), &mut |light| {
+            sum += light.pdf_value(origin, direction, time);
+        });
+
+        return sum / self.lights.len() as f32;
+    }
+
+    fn random_toward(&self, origin: Vec3, time: f32) -> Vec3 {
+        return match self.random_light() {
+            Some(light) => light.random_toward(origin, time),
+            None => Vec3::Y,
+        };
+    }
+
+    fn sample_surface(&self, time: f32) -> Option<(HitRecord<'_>, f32)> {
+        let (rec, pdf) = self.random_light()?.sample_surface(time)?;
+
+        return Some((rec, pdf / self.lights.len() as f32));
+    }
+
+    // Only the light the ray reaches first could have been sampled at that point
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        let ray = Ray::new(origin, direction, time);
+
+        let mut nearest: Option<(f32, &dyn Hittable)> = None;
+        self.lights_along(&ray, &mut |light| {
+            let closest_so_far = nearest.map_or(f32::INFINITY, |(t, _)| t);
+            if let Some(rec) = light.hit(&ray, 0.001, closest_so_far) {
+                nearest = Some((rec.t, light));
+            }
+        });
+
+        return match nearest {
+            Some((_, light)) => {
+                light.surface_pdf(origin, direction, time) / self.lights.len() as f32
+            }
+            None => 0.0,
+        };
+    }
+}
+
+impl BvhNode {
+    fn build(mut objects: Vec<(Aabb, Box<dyn Hittable>)>) -> BvhNode {
+        let bbox = objects
+            .iter()
+            .fold(Aabb::empty(), |acc, (bbox, _)| acc.surrounding(bbox));
+
+        let count = objects.len();
+
+        if count == 1 {
+            return BvhNode::leaf(bbox, objects);
+        }
+
+        let centroid_bounds = objects
+            .iter()
+            .fold(Aabb::empty(), |acc, (bbox, _)| acc.grow(bbox.centroid()));
+
+        // Find the cheapest split along any axis by sweeping over the objects sorted by centroid
+        let mut best_split: Option<(usize, usize, f32)> = None;
+
+        for axis in 0..3 {
+            if centroid_bounds.max[axis] <= centroid_bounds.min[axis] {
+                continue;
+            }
+
+            BvhNode::sort_by_axis(&mut objects, axis);
+
+            // right_areas[i] is the area of the box around objects[i..]
+            let mut right_areas = vec![0.0; count];
+            let mut right_bbox = Aabb::empty();
+            for i in (1..count).rev() {
+                right_bbox = right_bbox.surrounding(&objects[i].0);
+                right_areas[i] = right_bbox.surface_area();
+            }
+
+            let mut left_bbox = Aabb::empty();
+            for i in 1..count {
+                left_bbox = left_bbox.surrounding(&objects[i - 1].0);
+
+                let cost =
+                    left_bbox.surface_area() * i as f32 + right_areas[i] * (count - i) as f32;
+
+                if best_split.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best_split = Some((axis, i, cost));
+                }
+            }
+        }
+
+        let parent_area = bbox.surface_area();
+
+        let (axis, split_index) = match best_split {
+            Some((axis, split_index, cost)) => {
+                let split_cost = TRAVERSAL_COST + cost / parent_area.max(f32::EPSILON);
+
+                if count <= MAX_LEAF_SIZE && split_cost >= count as f32 {
+                    return BvhNode::leaf(bbox, objects);
+                }
+
+                (axis, split_index)
+            }
+            // All centroids coincide, so no split can separate them
+            None if count <= MAX_LEAF_SIZE => return BvhNode::leaf(bbox, objects),
+            None => (0, count / 2),
+        };
+
+        BvhNode::sort_by_axis(&mut objects, axis);
+        let right_objects = objects.split_off(split_index);
+
+        return BvhNode::Branch {
+            bbox,
+            left: Box::new(BvhNode::build(objects)),
+            right: Box::new(BvhNode::build(right_objects)),
+        };
+    }
+
+    fn build_if_any(objects: Vec<(Aabb, Box<dyn Hittable>)>) -> Option<BvhNode> {
+        return match objects.is_empty() {
+            true => None,
+            false => Some(BvhNode::build(objects)),
+        };
+    }
+
+    // Calls `visit` with every object in the leaves whose bounding boxes the ray passes through
+    fn for_each_along<'a>(&'a self, ray: &Ray, visit: &mut dyn FnMut(&'a dyn Hittable)) {
+        if !self.bbox().hit(ray, 0.0, f32::INFINITY) {
+            return;
+        }
+
+        match self {
+            BvhNode::Leaf { objects, .. } => {
+                for object in objects {
+                    visit(object.as_ref());
+                }
+            }
+            BvhNode::Branch { left, right, .. } => {
+                left.for_each_along(ray, visit);
+                right.for_each_along(ray, visit);
+            }
+        }
+    }
+
+    fn leaf(bbox: Aabb, objects: Vec<(Aabb, Box<dyn Hittable>)>) -> BvhNode {
+        return BvhNode::Leaf {
+            bbox,
+            objects: objects.into_iter().map(|(_, object)| object).collect(),
+        };
+    }
+
+    fn sort_by_axis(objects: &mut [(Aabb, Box<dyn Hittable>)], axis: usize) {
+        objects.sort_by(|(a, _), (b, _)| {
+            a.centroid()[axis]
+                .partial_cmp(&b.centroid()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+
+    fn bbox(&self) -> &Aabb {
+        return match self {
+            BvhNode::Leaf { bbox, .. } => bbox,
+            BvhNode::Branch { bbox, .. } => bbox,
+        };
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        if !self.bbox().hit(ray, t_min, t_max) {
+            return None;
+        }
+
+        match self {
+            BvhNode::Leaf { objects, .. } => {
+                let mut temp_rec = None;
+                let mut closest_so_far = t_max;
+
+                for object in objects {
+                    if let Some(rec) = object.hit(ray, t_min, closest_so_far) {
+                        closest_so_far = rec.t;
+                        temp_rec = Some(rec);
+                    }
+                }
+
+                return temp_rec;
+            }
+            BvhNode::Branch { left, right, .. } => {
+                let hit_left = left.hit(ray, t_min, t_max);
+
+                let closest_so_far = hit_left.as_ref().map_or(t_max, |rec| rec.t);
+
+                return match right.hit(ray, t_min, closest_so_far) {
+                    Some(rec) => Some(rec),
+                    None => hit_left,
+                };
+            }
+        }
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        if !self.bbox().hit(ray, t_min, t_max) {
+            return 1.0;
+        }
+
+        match self {
+            BvhNode::Leaf { objects, .. } => {
+                let mut transmittance = 1.0;
+
+                for object in objects {
+                    transmittance *= object.transmittance(ray, t_min, t_max);
+
+                    if transmittance == 0.0 {
+                        break;
+                    }
+                }
+
+                return transmittance;
+            }
+            BvhNode::Branch { left, right, .. } => {
+                let transmittance = left.transmittance(ray, t_min, t_max);
+
+                if transmittance == 0.0 {
+                    return 0.0;
+                }
+
+                return transmittance * right.transmittance(ray, t_min, t_max);
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return Some(*self.bbox());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::vec3::Vec3Extension;
+
+    // Overlapping spheres of all sizes, every fifth of them a light. The same seed gives the same spheres.
+    fn random_spheres(seed: u64) -> HittableList {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut list = HittableList::new();
+
+        for i in 0..300 {
+            let center = Vec3::new(
+                rng.gen_range(-10.0..10.0),
+                rng.gen_range(-10.0..10.0),
+                rng.gen_range(-10.0..10.0),
+            );
+            let radius = rng.gen_range(0.05..2.0);
+
+            let sphere = match i % 5 {
+                0 => Sphere::new(
+                    center,
+                    radius,
+                    Box::new(DiffuseLight::from_color(Vec3::ONE)),
+                ),
+                _ => Sphere::new(
+                    center,
+                    radius,
+                    Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::ONE)))),
+                ),
+            };
+
+            list.add(Box::new(sphere));
+        }
+
+        return list;
+    }
+
+    // Rays from around the spheres toward points among them, so most of them hit something
+    fn random_rays(count: usize) -> Vec<Ray> {
+        return (0..count)
+            .map(|_| {
+                let origin = Vec3::rand_range(-15.0, 15.0);
+                let target = Vec3::rand_range(-10.0, 10.0);
+                Ray::new(origin, target - origin, 0.0)
+            })
+            .collect();
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        return (a - b).abs() <= 1e-5 * a.abs().max(b.abs());
+    }
+
+    #[test]
+    fn hits_match_a_plain_list() {
+        let list = random_spheres(1);
+        let bvh = Bvh::new(random_spheres(1));
+        let mut hits = 0;
+
+        for ray in random_rays(5000) {
+            for t_max in [f32::INFINITY, 8.0] {
+                let expected = list.hit(&ray, 0.001, t_max);
+                let found = bvh.hit(&ray, 0.001, t_max);
+
+                match (expected, found) {
+                    (None, None) => {}
+                    (Some(expected), Some(found)) => {
+                        assert_eq!(expected.t, found.t);
+                        assert_eq!(expected.normal, found.normal);
+                        hits += 1;
+                    }
+                    _ => panic!(
+                        "only one of the list and the BVH was hit from {} toward {}",
+                        ray.origin, ray.direction
+                    ),
+                }
+
+                let transmittance = bvh.transmittance(&ray, 0.001, t_max);
+                assert_eq!(transmittance, list.transmittance(&ray, 0.001, t_max));
+            }
+        }
+
+        // Make sure the test isn't passing because everything misses
+        assert!(hits > 5000);
+    }
+
+    #[test]
+    fn light_densities_match_a_plain_list() {
+        let list = random_spheres(2);
+        let bvh = Bvh::new(random_spheres(2));
+        assert!(bvh.is_light());
+        let mut lit = 0;
+
+        for ray in random_rays(5000) {
+            let (origin, direction) = (ray.origin, ray.direction);
+
+            let expected = list.pdf_value(origin, direction, 0.0);
+            assert!(close(expected, bvh.pdf_value(origin, direction, 0.0)));
+
+            let expected = list.surface_pdf(origin, direction, 0.0);
+            assert!(close(expected, bvh.surface_pdf(origin, direction, 0.0)));
+
+            if expected > 0.0 {
+                lit += 1;
+            }
+        }
+
+        assert!(lit > 1000);
+    }
+}
@@ -1,5 +1,7 @@
 use glam::Vec3;
+use rand::Rng;
 
+use crate::random;
 use crate::ray::Ray;
 use crate::vec3::Vec3Extension;
 
@@ -10,7 +12,14 @@ pub struct Camera {
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
+    // Points from where the camera looks back toward the camera
+    w: Vec3,
     lens_radius: f32,
+    // Distance from the lens to the plane in focus, which horizontal and vertical span
+    focus_distance: f32,
+    // Rays are spread over the time between the shutter opening and closing
+    shutter_open: f32,
+    shutter_close: f32,
 }
 
 impl Camera {
@@ -46,17 +55,87 @@ impl Camera {
             lower_left_corner,
             u,
             v,
+            w,
             lens_radius,
+            focus_distance,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
+    // Keep the shutter open from time0 to time1, so objects moving in that interval get blurred
+    pub fn with_shutter(mut self, time0: f32, time1: f32) -> Self {
+        self.shutter_open = time0;
+        self.shutter_close = time1;
+        return self;
+    }
+
     pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
-        let offset = self.u * rd.x + self.v * rd.y;
+        let lens_point = self.random_lens_point();
 
         let direction =
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset;
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - lens_point;
+
+        let time = match self.shutter_close > self.shutter_open {
+            true => random::rng().gen_range(self.shutter_open..self.shutter_close),
+            false => self.shutter_open,
+        };
+
+        return Ray::new(lens_point, direction, time);
+    }
+
+    // A random point on the lens, which is a disk around the origin of the camera
+    pub fn random_lens_point(&self) -> Vec3 {
+        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+        return self.origin + self.u * rd.x + self.v * rd.y;
+    }
+
+    /**
+     * Where the ray from `lens_point` along `direction` crosses the plane in focus, as the (s, t) that
+     * get_ray takes for it. None if the direction points away from where the camera looks
+     */
+    pub fn film_coordinates(&self, lens_point: Vec3, direction: Vec3) -> Option<(f32, f32)> {
+        let toward_film = -direction.dot(self.w);
+
+        if toward_film <= 0.0 {
+            return None;
+        }
+
+        // The lens lies in a plane parallel to the one in focus, so the distance between them is the same
+        let on_film = lens_point + direction * (self.focus_distance / toward_film);
+        let offset = on_film - self.lower_left_corner;
+
+        return Some((
+            offset.dot(self.horizontal) / self.horizontal.length_squared(),
+            offset.dot(self.vertical) / self.vertical.length_squared(),
+        ));
+    }
+
+    /**
+     * Density per unit of solid angle of get_ray picking `direction` from `lens_point`, when s and t are
+     * spread evenly over [0, s_max) and [0, t_max). Zero outside of that part of the plane in focus.
+     *
+     * The plane is sampled by area, which covers less solid angle away from the middle: a patch of it at
+     * angle θ from where the camera looks is focus_distance / cos θ away and seen at cos θ, so the density
+     * grows with focus_distance² / cos³ θ.
+     */
+    pub fn direction_pdf(&self, lens_point: Vec3, direction: Vec3, s_max: f32, t_max: f32) -> f32 {
+        let Some((s, t)) = self.film_coordinates(lens_point, direction) else {
+            return 0.0;
+        };
+
+        if !(0.0..s_max).contains(&s) || !(0.0..t_max).contains(&t) {
+            return 0.0;
+        }
+
+        let cos_theta = self.cos_theta(direction);
+        let film_area = self.horizontal.length() * self.vertical.length() * s_max * t_max;
+
+        return self.focus_distance * self.focus_distance / (cos_theta.powi(3) * film_area);
+    }
 
-        return Ray::new(self.origin + offset, direction);
+    // Cosine of the angle between `direction` and where the camera looks, which is also the normal of the lens
+    pub fn cos_theta(&self, direction: Vec3) -> f32 {
+        return -direction.normalize().dot(self.w);
     }
 }
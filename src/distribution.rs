@@ -0,0 +1,201 @@
+/**
+ * Piecewise constant distribution over [0, 1), with one piece per value of the function it is built
+ * from. Pieces are picked proportionally to their value, for importance sampling.
+ */
+pub struct Distribution1D {
+    function: Vec<f32>,
+    // cdf[i] is the probability of picking a piece before piece i, so it has one more entry than function
+    cdf: Vec<f32>,
+    // Integral of the function over [0, 1)
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(function: Vec<f32>) -> Self {
+        let count = function.len();
+        let mut cdf = vec![0.0; count + 1];
+
+        for i in 0..count {
+            cdf[i + 1] = cdf[i] + function[i].max(0.0) / count as f32;
+        }
+
+        let integral = cdf[count];
+
+        // A function that is zero everywhere can't be sampled proportionally, so it is sampled uniformly
+        for (i, value) in cdf.iter_mut().enumerate() {
+            *value = match integral > 0.0 {
+                true => *value / integral,
+                false => i as f32 / count as f32,
+            };
+        }
+
+        return Self {
+            function,
+            cdf,
+            integral,
+        };
+    }
+
+    pub fn integral(&self) -> f32 {
+        return self.integral;
+    }
+
+    // Turn a uniform random number in [0, 1) into a point in [0, 1), its density and the piece it is in
+    pub fn sample(&self, random: f32) -> (f32, f32, usize) {
+        let count = self.function.len();
+
+        // The last piece whose cdf doesn't exceed the random number
+        let index = (self.cdf.partition_point(|&value| value <= random) - 1).min(count - 1);
+
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = match width > 0.0 {
+            true => (random - self.cdf[index]) / width,
+            false => 0.0,
+        };
+
+        let point = ((index as f32 + offset) / count as f32).min(1.0 - f32::EPSILON);
+
+        return (point, self.pdf(index), index);
+    }
+
+    // Density of sample picking a point in the given piece
+    pub fn pdf(&self, index: usize) -> f32 {
+        return match self.integral > 0.0 {
+            true => self.function[index].max(0.0) / self.integral,
+            false => 1.0,
+        };
+    }
+}
+
+/**
+ * Piecewise constant distribution over [0, 1)², built from a grid of values stored row by row.
+ * A row is picked first by how much its values add up to, then a column within it.
+ */
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(function: &[f32], width: usize, height: usize) -> Self {
+        let rows: Vec<Distribution1D> = function
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+
+        return Self { rows, marginal };
+    }
+
+    // Turn two uniform random numbers into a point (u, v) in [0, 1)² and its density
+    pub fn sample(&self, random_u: f32, random_v: f32) -> (f32, f32, f32) {
+        let (v, pdf_v, row) = self.marginal.sample(random_v);
+        let (u, pdf_u, _) = self.rows[row].sample(random_u);
+
+        return (u, v, pdf_u * pdf_v);
+    }
+
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let height = self.rows.len();
+        let row = ((v * height as f32) as usize).min(height - 1);
+
+        let width = self.rows[row].function.len();
+        let column = ((u * width as f32) as usize).min(width - 1);
+
+        return match self.marginal.integral > 0.0 {
+            true => self.rows[row].function[column].max(0.0) / self.marginal.integral,
+            false => 1.0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Evenly spread numbers in [0, 1), so the tests don't depend on a random generator
+    fn stratified(count: usize) -> impl Iterator<Item = f32> {
+        return (0..count).map(move |i| (i as f32 + 0.5) / count as f32);
+    }
+
+    #[test]
+    fn pieces_are_picked_in_proportion_to_their_value() {
+        let distribution = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+        let mut counts = [0; 4];
+
+        for random in stratified(8000) {
+            let (point, pdf, index) = distribution.sample(random);
+
+            assert_eq!(index, (point * 4.0) as usize);
+            assert_eq!(pdf, distribution.pdf(index));
+            counts[index] += 1;
+        }
+
+        assert_eq!(counts, [1000, 3000, 0, 4000]);
+        assert_eq!(distribution.integral(), 2.0);
+    }
+
+    #[test]
+    fn densities_are_the_values_over_the_integral() {
+        let distribution = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+        let pdfs: Vec<f32> = (0..4).map(|index| distribution.pdf(index)).collect();
+
+        assert_eq!(pdfs, [0.5, 1.5, 0.0, 2.0]);
+        // Each piece is a quarter of [0, 1) wide
+        assert_eq!(pdfs.iter().sum::<f32>() / 4.0, 1.0);
+    }
+
+    #[test]
+    fn zero_functions_are_sampled_uniformly() {
+        let distribution = Distribution1D::new(vec![0.0; 5]);
+
+        for random in stratified(100) {
+            let (point, pdf, _) = distribution.sample(random);
+
+            assert!((point - random).abs() < 1e-5);
+            assert_eq!(pdf, 1.0);
+        }
+    }
+
+    #[test]
+    fn samples_in_2d_have_the_density_of_their_cell() {
+        #[rustfmt::skip]
+        let function = [
+            0.0, 1.0, 2.0,
+            4.0, 0.0, 1.0,
+        ];
+        let distribution = Distribution2D::new(&function, 3, 2);
+        let mut counts = [0; 6];
+
+        // Counts that split evenly at the bounds of the rows (3/8) and the columns (1/3, 4/5)
+        for random_v in stratified(400) {
+            for random_u in stratified(120) {
+                let (u, v, pdf) = distribution.sample(random_u, random_v);
+
+                assert!((pdf - distribution.pdf(u, v)).abs() < 1e-6);
+                counts[(v * 2.0) as usize * 3 + (u * 3.0) as usize] += 1;
+            }
+        }
+
+        // The values add up to 8, so each unit of value gets an eighth of the samples
+        assert_eq!(counts, [0, 6000, 12000, 24000, 0, 6000]);
+    }
+
+    #[test]
+    fn densities_in_2d_integrate_to_one() {
+        let function: Vec<f32> = (0..12).map(|i| ((i * 7) % 5) as f32).collect();
+        let distribution = Distribution2D::new(&function, 4, 3);
+
+        let integral: f32 = (0..3)
+            .flat_map(|row| (0..4).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let (u, v) = ((column as f32 + 0.5) / 4.0, (row as f32 + 0.5) / 3.0);
+                distribution.pdf(u, v) / 12.0
+            })
+            .sum();
+
+        assert!((integral - 1.0).abs() < 1e-5);
+    }
+}
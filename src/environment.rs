@@ -0,0 +1,254 @@
+use std::f32::consts::{PI, TAU};
+use std::sync::Arc;
+
+use glam::{Quat, Vec3};
+use rand::Rng;
+
+use crate::distribution::Distribution2D;
+use crate::random;
+use crate::sky::Sky;
+use crate::texture::ImageData;
+
+/**
+ * What rays that leave the scene see
+ */
+pub enum Background {
+    // The same color in every direction. It is only found by chance, never sampled as a light.
+    Color(Vec3),
+    Environment(EnvironmentMap),
+    Sky(Sky),
+}
+
+impl Background {
+    // Light arriving from infinitely far away in the given direction
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        return match self {
+            Background::Color(color) => *color,
+            Background::Environment(map) => map.radiance(direction),
+            Background::Sky(sky) => sky.radiance(direction),
+        };
+    }
+
+    // Whether the background is sampled directly like the lights of the world
+    pub fn is_light(&self) -> bool {
+        return matches!(self, Background::Environment(_) | Background::Sky(_));
+    }
+
+    // Density of random_direction picking `direction`, per unit of solid angle
+    pub fn pdf_value(&self, direction: Vec3) -> f32 {
+        return match self {
+            Background::Color(_) => 0.0,
+            Background::Environment(map) => map.pdf_value(direction),
+            Background::Sky(sky) => sky.pdf_value(direction),
+        };
+    }
+
+    pub fn random_direction(&self) -> Vec3 {
+        return match self {
+            Background::Color(_) => Vec3::Y,
+            Background::Environment(map) => map.random_direction(),
+            Background::Sky(sky) => sky.random_direction(),
+        };
+    }
+}
+
+/**
+ * Light from all directions, read from an equirectangular (latitude-longitude) image.
+ * The middle of the image lies toward -Z and the top row straight up along +Y.
+ *
+ * Directions are importance sampled by the luminance of the pixels, so small bright features like the
+ * sun are found by light sampling rather than by chance.
+ */
+pub struct EnvironmentMap {
+    image: Arc<ImageData>,
+    // Turns directions in the world into directions in the map
+    world_to_map: Quat,
+    intensity: f32,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Arc<ImageData>) -> Self {
+        let (width, height) = (image.width(), image.height());
+
+        // Rows near the poles are squeezed into less solid angle, so they are picked less often
+        let mut function = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+
+            for x in 0..width {
+                function.push(luminance(image.pixel(x, y)) * sin_theta);
+            }
+        }
+
+        return Self {
+            distribution: Distribution2D::new(&function, width, height),
+            image,
+            world_to_map: Quat::IDENTITY,
+            intensity: 1.0,
+        };
+    }
+
+    // Turn the map around in the world
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.world_to_map = rotation.inverse();
+        return self;
+    }
+
+    // Scale the brightness of the map
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        return self;
+    }
+
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        let (u, v) = direction_to_uv(self.world_to_map * direction.normalize());
+
+        let x = ((u * self.image.width() as f32) as usize).min(self.image.width() - 1);
+        let y = ((v * self.image.height() as f32) as usize).min(self.image.height() - 1);
+
+        return self.intensity * self.image.pixel(x, y);
+    }
+
+    /**
+     * The distribution picks points in the image, which are spread over the sphere of directions by
+     * the equirectangular mapping. Its density is turned into one per unit of solid angle by
+     * dividing by the area the mapping stretches a unit square of the image over, 2π² sin θ
+     */
+    fn pdf_value(&self, direction: Vec3) -> f32 {
+        let (u, v) = direction_to_uv(self.world_to_map * direction.normalize());
+        let sin_theta = (PI * v).sin();
+
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        return self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta);
+    }
+
+    fn random_direction(&self) -> Vec3 {
+        let mut rng = random::rng();
+        let (u, v, _) = self
+            .distribution
+            .sample(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+
+        return self.world_to_map.inverse() * uv_to_direction(u, v);
+    }
+}
+
+// Perceived brightness of a linear color
+fn luminance(color: Vec3) -> f32 {
+    return color.dot(Vec3::new(0.2126, 0.7152, 0.0722));
+}
+
+fn direction_to_uv(direction: Vec3) -> (f32, f32) {
+    let u = 0.5 + direction.x.atan2(-direction.z) / TAU;
+    let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+    return (u, v);
+}
+
+fn uv_to_direction(u: f32, v: f32) -> Vec3 {
+    let phi = TAU * (u - 0.5);
+    let theta = PI * v;
+
+    return Vec3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // A map with a dim gradient and one bright pixel, loaded from an EXR file like scene files do
+    fn test_map(name: &str) -> EnvironmentMap {
+        let (width, height) = (16, 8);
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "raytracer_environment_{}_{}.exr",
+            name,
+            std::process::id()
+        ));
+
+        exr::prelude::write_rgb_file(&path, width, height, |x, y| match (x, y) {
+            (11, 2) => (50.0f32, 40.0f32, 30.0f32),
+            _ => (0.1 + 0.05 * x as f32, 0.2, 0.1 * y as f32),
+        })
+        .unwrap();
+
+        let image = ImageData::load(&path, false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        return EnvironmentMap::new(Arc::new(image));
+    }
+
+    // Integrate a function of the direction over the sphere, with the midpoint rule in θ and φ
+    fn integrate(function: impl Fn(Vec3) -> f32) -> f32 {
+        let (steps_theta, steps_phi) = (400, 800);
+        let (d_theta, d_phi) = (PI / steps_theta as f32, TAU / steps_phi as f32);
+        let mut sum = 0.0f64;
+
+        for i in 0..steps_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+
+            for j in 0..steps_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+
+                sum += (function(direction) * theta.sin() * d_theta * d_phi) as f64;
+            }
+        }
+
+        return sum as f32;
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let map = test_map("pdf");
+        assert!((integrate(|direction| map.pdf_value(direction)) - 1.0).abs() < 1e-3);
+
+        let rotated = test_map("rotated").with_rotation(Quat::from_rotation_x(0.7));
+        assert!((integrate(|direction| rotated.pdf_value(direction)) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn random_directions_have_the_density_of_pdf_value() {
+        random::reseed(7);
+        let map = test_map("sampling").with_rotation(Quat::from_rotation_y(1.2));
+        let brightness = |direction: Vec3| luminance(map.radiance(direction));
+
+        // Importance sampling the light of the map only works if the samples have the density
+        // pdf_value says, otherwise this estimate of its total is off. Directions that round onto a
+        // pole have no density and are skipped, as the integrators do.
+        let count = 100_000;
+        let estimate = (0..count)
+            .map(|_| {
+                let direction = map.random_direction();
+                match map.pdf_value(direction) {
+                    pdf if pdf > 0.0 => (brightness(direction) / pdf) as f64,
+                    _ => 0.0,
+                }
+            })
+            .sum::<f64>()
+            / count as f64;
+
+        let expected = integrate(brightness) as f64;
+        assert!((estimate / expected - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn directions_and_image_coordinates_round_trip() {
+        for (u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7), (0.3, 0.95)] {
+            let (u2, v2) = direction_to_uv(uv_to_direction(u, v));
+            assert!((u - u2).abs() < 1e-5 && (v - v2).abs() < 1e-5);
+        }
+    }
+}
@@ -1,8 +1,12 @@
+use std::sync::Arc;
+
 use glam::Vec3;
 
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub point: Vec3,
     pub normal: Vec3,
@@ -10,7 +14,7 @@ pub struct HitRecord<'a> {
     pub u: f32,
     pub v: f32,
     pub front_face: bool,
-    pub material: &'a Box<dyn Material>,
+    pub material: &'a dyn Material,
 }
 
 impl<'a> HitRecord<'a> {
@@ -21,7 +25,7 @@ impl<'a> HitRecord<'a> {
         t: f32,
         u: f32,
         v: f32,
-        material: &'a Box<dyn Material>,
+        material: &'a dyn Material,
     ) -> Self {
         let front_face = ray.direction.dot(outward_normal) < 0.0;
 
@@ -41,8 +45,141 @@ impl<'a> HitRecord<'a> {
             material,
         };
     }
+
+    // A point on a surface that wasn't found by a ray, like one picked on a light. The normal points outward
+    pub fn on_surface(
+        point: Vec3,
+        outward_normal: Vec3,
+        u: f32,
+        v: f32,
+        material: &'a dyn Material,
+    ) -> Self {
+        return Self {
+            point,
+            normal: outward_normal,
+            t: 0.0,
+            u,
+            v,
+            front_face: true,
+            material,
+        };
+    }
+
+    // The same point, with the normal turned toward `direction`
+    pub fn facing(&self, direction: Vec3) -> Self {
+        let mut rec = *self;
+
+        if rec.normal.dot(direction) < 0.0 {
+            rec.normal = -rec.normal;
+            rec.front_face = !rec.front_face;
+        }
+
+        return rec;
+    }
 }
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    // Box enclosing the object, or None if it is unbounded
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /**
+     * Whether the object gives off light. Lights are sampled directly by the renderer, so objects
+     * that return true should implement pdf_value and random_toward as well
+     */
+    fn is_light(&self) -> bool {
+        return false;
+    }
+
+    /**
+     * Probability density, per unit of solid angle, of random_toward picking `direction` from `origin`.
+     * Zero if the direction misses the object.
+     */
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3, _time: f32) -> f32 {
+        return 0.0;
+    }
+
+    // A vector from `origin` to a random point on the object, which doesn't need to be normalized
+    fn random_toward(&self, _origin: Vec3, _time: f32) -> Vec3 {
+        return Vec3::Y;
+    }
+
+    /**
+     * A random point on the lights of the object, to trace light from, and its density per unit of area.
+     * The normal of the hit record points outward. None if the object has no lights
+     */
+    fn sample_surface(&self, _time: f32) -> Option<(HitRecord<'_>, f32)> {
+        return None;
+    }
+
+    /**
+     * Density per unit of area of sample_surface picking the point a ray from `origin` along `direction`
+     * hits on the object. Zero if the direction misses the lights of the object.
+     */
+    fn surface_pdf(&self, _origin: Vec3, _direction: Vec3, _time: f32) -> f32 {
+        return 0.0;
+    }
+
+    /**
+     * Fraction of the light along the ray between t_min and t_max that gets past the object, for shadow
+     * rays. Solid objects either block the ray or don't, volumes may estimate how much gets through.
+     */
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        return match self.hit(ray, t_min, t_max) {
+            Some(_) => 0.0,
+            None => 1.0,
+        };
+    }
+}
+
+/**
+ * Density per unit of solid angle of a direction that hits a surface with the given normal at
+ * distance t along it, for a surface of the given area whose points are all equally likely to be sampled
+ */
+pub fn area_pdf(direction: Vec3, t: f32, normal: Vec3, area: f32) -> f32 {
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = normal.dot(direction).abs() / direction.length();
+
+    // Seen edge-on, the surface covers no solid angle
+    if cosine < 1e-6 {
+        return 0.0;
+    }
+
+    return distance_squared / (cosine * area);
+}
+
+// Lets an object be both part of the world and in a list of lights
+impl<H: Hittable + ?Sized> Hittable for Arc<H> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        return self.as_ref().hit(ray, t_min, t_max);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return self.as_ref().bounding_box();
+    }
+
+    fn is_light(&self) -> bool {
+        return self.as_ref().is_light();
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return self.as_ref().pdf_value(origin, direction, time);
+    }
+
+    fn random_toward(&self, origin: Vec3, time: f32) -> Vec3 {
+        return self.as_ref().random_toward(origin, time);
+    }
+
+    fn sample_surface(&self, time: f32) -> Option<(HitRecord<'_>, f32)> {
+        return self.as_ref().sample_surface(time);
+    }
+
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return self.as_ref().surface_pdf(origin, direction, time);
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        return self.as_ref().transmittance(ray, t_min, t_max);
+    }
 }
@@ -1,13 +1,20 @@
+use glam::Vec3;
+use rand::Rng;
+
+use crate::aabb::Aabb;
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
+use crate::random;
 use crate::ray::Ray;
 
 pub struct HittableList {
-    objects: Vec<Box<dyn Hittable + Send + Sync>>,
+    objects: Vec<Box<dyn Hittable>>,
+    // Indices of the objects that give off light, kept up to date by add
+    lights: Vec<usize>,
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut temp_rec: Option<HitRecord> = None;
 
         let mut closest_so_far = t_max;
@@ -21,16 +28,131 @@ impl Hittable for HittableList {
 
         return temp_rec;
     }
+
+    // Light gets through the list if it gets past every object in it
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let mut transmittance = 1.0;
+
+        for object in &self.objects {
+            transmittance *= object.transmittance(ray, t_min, t_max);
+
+            if transmittance == 0.0 {
+                break;
+            }
+        }
+
+        return transmittance;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut result: Option<Aabb> = None;
+
+        for object in &self.objects {
+            let bbox = object.bounding_box()?;
+
+            result = match result {
+                Some(acc) => Some(acc.surrounding(&bbox)),
+                None => Some(bbox),
+            };
+        }
+
+        return result;
+    }
+
+    fn is_light(&self) -> bool {
+        return self.objects.iter().any(|object| object.is_light());
+    }
+
+    // The lights in the list are picked with equal probability, so the density is their average
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f32 = self
+            .lights()
+            .map(|light| light.pdf_value(origin, direction, time))
+            .sum();
+
+        return sum / self.lights.len() as f32;
+    }
+
+    fn random_toward(&self, origin: Vec3, time: f32) -> Vec3 {
+        return match self.random_light() {
+            Some(light) => light.random_toward(origin, time),
+            None => Vec3::Y,
+        };
+    }
+
+    fn sample_surface(&self, time: f32) -> Option<(HitRecord<'_>, f32)> {
+        let (rec, pdf) = self.random_light()?.sample_surface(time)?;
+
+        return Some((rec, pdf / self.lights.len() as f32));
+    }
+
+    // Only the light the ray reaches first could have been sampled at that point
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        let ray = Ray::new(origin, direction, time);
+
+        return match nearest_light(self.lights(), &ray) {
+            Some(light) => light.surface_pdf(origin, direction, time) / self.lights.len() as f32,
+            None => 0.0,
+        };
+    }
 }
 
 impl HittableList {
     pub fn new() -> Self {
         return Self {
             objects: Vec::new(),
+            lights: Vec::new(),
         };
     }
 
-    pub fn add(&mut self, object: Box<dyn Hittable + Send + Sync>) {
+    pub fn add(&mut self, object: Box<dyn Hittable>) {
+        if object.is_light() {
+            self.lights.push(self.objects.len());
+        }
+
         self.objects.push(object);
     }
+
+    pub fn is_empty(&self) -> bool {
+        return self.objects.is_empty();
+    }
+
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable>> {
+        return self.objects;
+    }
+
+    fn lights(&self) -> impl Iterator<Item = &dyn Hittable> {
+        return self
+            .lights
+            .iter()
+            .map(|&index| self.objects[index].as_ref());
+    }
+
+    fn random_light(&self) -> Option<&dyn Hittable> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let index = random::rng().gen_range(0..self.lights.len());
+        return Some(self.objects[self.lights[index]].as_ref());
+    }
+}
+
+// The light the ray hits first, out of the given ones. Other objects may still block it.
+fn nearest_light<'a>(
+    lights: impl Iterator<Item = &'a dyn Hittable>,
+    ray: &Ray,
+) -> Option<&'a dyn Hittable> {
+    return lights
+        .filter_map(|light| {
+            light
+                .hit(ray, 0.001, f32::INFINITY)
+                .map(|rec| (rec.t, light))
+        })
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, light)| light);
 }
@@ -0,0 +1,676 @@
+use glam::Vec3;
+use rand::{Rng, RngCore};
+
+use crate::bdpt::BdptIntegrator;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::settings::{Bounces, RenderSettings};
+use crate::vec3::Vec3Extension;
+
+/**
+ * An algorithm for finding the light arriving at the camera. The pixel loop traces camera rays and
+ * averages what the integrator finds along them, so integrators can be swapped without touching it.
+ */
+pub trait Integrator: Sync + Send {
+    // Light arriving along the ray. `sampler` is the random number generator of the pixel being rendered
+    fn li(&self, ray: Ray, scene: &Scene, sampler: &mut dyn RngCore) -> Vec3;
+
+    /**
+     * Light the integrator sent to the pixel in column i and row j, counting rows from the bottom like get_ray,
+     * summed over all samples. Paths traced from the lights land on whichever pixel sees them rather than on
+     * the one being rendered, so their light is added to the image after rendering
+     */
+    fn splatted(&self, _i: usize, _j: usize) -> Vec3 {
+        return Vec3::ZERO;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum IntegratorKind {
+    // Full global illumination by following paths of bounces
+    Path,
+    // Paths traced from both the camera and the lights and connected, for caustics and hard to reach lights
+    Bdpt,
+    // How much of the sky above each surface isn't blocked by nearby objects
+    AmbientOcclusion,
+    // Light arriving straight from the lights, through mirrors and glass, without any bounces in between
+    Direct,
+    // Classic ray tracing with hard shadows from point, spot and directional lights only
+    Whitted,
+    // Surface normals, mapped from [-1, 1] to [0, 1]
+    Normals,
+    // Texture coordinates, u in red and v in green
+    Uv,
+}
+
+// Build the integrator picked on the command line
+pub fn from_settings(settings: &RenderSettings) -> Box<dyn Integrator> {
+    return match settings.integrator {
+        IntegratorKind::Path => Box::new(PathIntegrator {
+            max_depth: settings.max_depth,
+            roulette_depth: settings.roulette_depth,
+        }),
+        IntegratorKind::Bdpt => Box::new(BdptIntegrator::new(
+            settings.max_depth.total,
+            settings.roulette_depth,
+            settings.image_width as usize,
+            settings.image_height as usize,
+        )),
+        IntegratorKind::AmbientOcclusion => Box::new(AmbientOcclusion {
+            distance: settings.ao_distance,
+        }),
+        IntegratorKind::Direct => Box::new(DirectLighting {
+            max_depth: settings.max_depth.total,
+        }),
+        IntegratorKind::Whitted => Box::new(Whitted {
+            max_depth: settings.max_depth.total,
+        }),
+        IntegratorKind::Normals => Box::new(DebugView::Normals),
+        IntegratorKind::Uv => Box::new(DebugView::Uv),
+    };
+}
+
+/**
+ * Gathers light by following a path of bounces from the ray. Lights are sampled directly at every
+ * bounce as well as found by the scattered rays, and the two are weighed against each other with
+ * multiple importance sampling.
+ *
+ * Past roulette_depth bounces, paths carrying little light are ended at random, and the ones that go
+ * on carry more to make up for it. This keeps the result unbiased while spending less time on paths
+ * that hardly add anything.
+ */
+pub struct PathIntegrator {
+    max_depth: Bounces,
+    roulette_depth: i32,
+}
+
+impl Integrator for PathIntegrator {
+    fn li(&self, ray: Ray, scene: &Scene, sampler: &mut dyn RngCore) -> Vec3 {
+        let mut color = Vec3::ZERO;
+        // Fraction of the light found further along the path that makes it back to the camera
+        let mut throughput = Vec3::ONE;
+        let mut ray = ray;
+        // Density the last bounce picked the ray with, or None if light found along it isn't weighed
+        // against light sampling (camera rays and specular bounces)
+        let mut bsdf_pdf = None;
+        let mut bounces = Bounces::default();
+
+        loop {
+            let Some(rec) = scene.world.hit(&ray, 0.001, f32::INFINITY) else {
+                let radiance = scene.background.radiance(ray.direction);
+                color += throughput * mis_weight(&ray, scene, bsdf_pdf, radiance);
+                break;
+            };
+
+            let emitted = rec.material.emitted(rec.u, rec.v, rec.point);
+            color += throughput * mis_weight(&ray, scene, bsdf_pdf, emitted);
+
+            let specular = rec.material.is_specular();
+
+            /*
+            Lights are sampled even if the scattered ray ends up absorbed. The light bounces off the
+            surface toward the camera, so it is only counted if one more bounce is within the limits,
+            the same as for the light the scattered ray finds.
+            */
+            if !specular {
+                let mut lit = bounces;
+                lit.add(rec.material.eval_lobe());
+
+                if !lit.exceed(&self.max_depth) {
+                    color += throughput
+                        * (sample_lights(&ray, &rec, scene)
+                            + sample_punctual_lights(&ray, &rec, scene));
+                }
+            }
+
+            let Some(sample) = rec.material.sample(&ray, &rec) else {
+                break;
+            };
+
+            bounces.add(sample.lobe);
+            if bounces.exceed(&self.max_depth) {
+                break;
+            }
+
+            throughput *= sample.attenuation;
+
+            if bounces.total > self.roulette_depth {
+                let survival = throughput.max_element().min(1.0);
+
+                if sampler.gen_range(0.0..1.0) >= survival {
+                    break;
+                }
+
+                throughput /= survival;
+            }
+
+            bsdf_pdf = (!specular).then_some(sample.pdf);
+            ray = sample.scattered;
+        }
+
+        return color;
+    }
+}
+
+/**
+ * Shades the first surface the ray hits by the fraction of rays from it that don't hit anything within
+ * `distance`. Rays are picked proportionally to the cosine, so the fraction is weighed like diffuse light.
+ */
+pub struct AmbientOcclusion {
+    distance: f32,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn li(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn RngCore) -> Vec3 {
+        let Some(rec) = scene.world.hit(&ray, 0.001, f32::INFINITY) else {
+            return Vec3::ZERO;
+        };
+
+        let mut direction = rec.normal + Vec3::random_unit_vector();
+        if direction.is_near_zero() {
+            direction = rec.normal;
+        }
+
+        let occlusion_ray = Ray::new(rec.point, direction.normalize(), ray.time);
+
+        return match scene.world.hit(&occlusion_ray, 0.001, self.distance) {
+            Some(_) => Vec3::ZERO,
+            None => Vec3::ONE,
+        };
+    }
+}
+
+/**
+ * Light arriving straight from the lights and the background, without bouncing off anything in between.
+ * Mirrors and glass are followed, as they are seen through rather than lit.
+ */
+pub struct DirectLighting {
+    max_depth: i32,
+}
+
+impl Integrator for DirectLighting {
+    fn li(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn RngCore) -> Vec3 {
+        let mut color = Vec3::ZERO;
+        let mut throughput = Vec3::ONE;
+        let mut ray = ray;
+
+        for _ in 0..=self.max_depth {
+            let Some(rec) = scene.world.hit(&ray, 0.001, f32::INFINITY) else {
+                return color + throughput * scene.background.radiance(ray.direction);
+            };
+
+            color += throughput * rec.material.emitted(rec.u, rec.v, rec.point);
+
+            let sample = rec.material.sample(&ray, &rec);
+
+            if rec.material.is_specular() {
+                let Some(sample) = sample else {
+                    break;
+                };
+
+                throughput *= sample.attenuation;
+                ray = sample.scattered;
+                continue;
+            }
+
+            color += throughput
+                * (sample_lights(&ray, &rec, scene) + sample_punctual_lights(&ray, &rec, scene));
+
+            let Some(sample) = sample else {
+                break;
+            };
+
+            // The scattered ray finds the lights the light sampling above shares with it
+            let emitted = match scene.world.hit(&sample.scattered, 0.001, f32::INFINITY) {
+                Some(light) => light.material.emitted(light.u, light.v, light.point),
+                None => scene.background.radiance(sample.scattered.direction),
+            };
+            let weighed = mis_weight(&sample.scattered, scene, Some(sample.pdf), emitted);
+
+            color += throughput * sample.attenuation * weighed;
+            break;
+        }
+
+        return color;
+    }
+}
+
+/**
+ * Classic ray tracing. Surfaces are only lit by the point, spot and directional lights, with hard
+ * shadows, and mirrors and glass are followed. Emissive objects and the background can be seen, but
+ * don't light anything.
+ */
+pub struct Whitted {
+    max_depth: i32,
+}
+
+impl Integrator for Whitted {
+    fn li(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn RngCore) -> Vec3 {
+        let mut color = Vec3::ZERO;
+        let mut throughput = Vec3::ONE;
+        let mut ray = ray;
+
+        for _ in 0..=self.max_depth {
+            let Some(rec) = scene.world.hit(&ray, 0.001, f32::INFINITY) else {
+                return color + throughput * scene.background.radiance(ray.direction);
+            };
+
+            color += throughput * rec.material.emitted(rec.u, rec.v, rec.point);
+
+            if !rec.material.is_specular() {
+                return color + throughput * sample_punctual_lights(&ray, &rec, scene);
+            }
+
+            let Some(sample) = rec.material.sample(&ray, &rec) else {
+                break;
+            };
+
+            throughput *= sample.attenuation;
+            ray = sample.scattered;
+        }
+
+        return color;
+    }
+}
+
+/**
+ * Shows a property of the first surface the ray hits, to check the geometry of a scene
+ */
+pub enum DebugView {
+    Normals,
+    Uv,
+}
+
+impl Integrator for DebugView {
+    fn li(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn RngCore) -> Vec3 {
+        let Some(rec) = scene.world.hit(&ray, 0.001, f32::INFINITY) else {
+            return Vec3::ZERO;
+        };
+
+        return match self {
+            DebugView::Normals => {
+                // The normal of the hit record faces the ray, but the outward one is more useful to see
+                let outward = match rec.front_face {
+                    true => rec.normal,
+                    false => -rec.normal,
+                };
+
+                0.5 * (outward.normalize() + Vec3::ONE)
+            }
+            DebugView::Uv => Vec3::new(rec.u, rec.v, 0.0),
+        };
+    }
+}
+
+/**
+ * Share of light found along the ray that is kept, as the bounce the ray left from sampled the lights
+ * too and found it with that sample as well
+ */
+fn mis_weight(ray: &Ray, scene: &Scene, bsdf_pdf: Option<f32>, emitted: Vec3) -> Vec3 {
+    return match bsdf_pdf {
+        Some(bsdf_pdf) if emitted != Vec3::ZERO => {
+            let light_pdf = scene.light_pdf(ray.origin, ray.direction, ray.time);
+            emitted * power_heuristic(bsdf_pdf, light_pdf)
+        }
+        _ => emitted,
+    };
+}
+
+/**
+ * Light from a randomly picked light of the scene that scatters back along the ray, found by casting
+ * a shadow ray toward it and weighed against the material sampling the same direction
+ */
+fn sample_lights(ray: &Ray, rec: &HitRecord, scene: &Scene) -> Vec3 {
+    if !scene.has_lights() {
+        return Vec3::ZERO;
+    }
+
+    // Normalized so the shadow ray starts the same tiny distance away from the surface as other rays
+    let direction = scene.random_toward_light(rec.point, ray.time).normalize();
+
+    let scattering = rec.material.eval(ray, rec, direction);
+    if scattering == Vec3::ZERO {
+        return Vec3::ZERO;
+    }
+
+    let light_pdf = scene.light_pdf(rec.point, direction, ray.time);
+    if light_pdf <= 0.0 {
+        return Vec3::ZERO;
+    }
+
+    /*
+    The first surface the shadow ray hits is where the light comes from, so anything solid in the way
+    blocks it. Volumes let part of the light through, so collisions in them are stepped over, and what
+    they let through on the way to the surface is estimated separately.
+    */
+    let shadow_ray = Ray::new(rec.point, direction, ray.time);
+    let mut t_min = 0.001;
+    let (emitted, distance) = loop {
+        match scene.world.hit(&shadow_ray, t_min, f32::INFINITY) {
+            Some(collision) if collision.material.is_volumetric() => t_min = collision.t,
+            Some(light) => {
+                let emitted = light.material.emitted(light.u, light.v, light.point);
+                break (emitted, light.t - 0.001);
+            }
+            None => break (scene.background.radiance(direction), f32::INFINITY),
+        }
+    };
+
+    if emitted == Vec3::ZERO {
+        return Vec3::ZERO;
+    }
+
+    let incoming = emitted * scene.world.transmittance(&shadow_ray, 0.001, distance);
+
+    let weight = power_heuristic(light_pdf, rec.material.pdf(ray, rec, direction));
+
+    return scattering * incoming * weight / light_pdf;
+}
+
+/**
+ * Light from the point, spot and directional lights of the scene that scatters back along the ray.
+ * Random bounces never find them, so every one of them gets a shadow ray and no weighing is needed
+ */
+pub fn sample_punctual_lights(ray: &Ray, rec: &HitRecord, scene: &Scene) -> Vec3 {
+    let mut total = Vec3::ZERO;
+
+    for light in &scene.lights {
+        let Some(sample) = light.illuminate(rec.point) else {
+            continue;
+        };
+
+        let scattering = rec.material.eval(ray, rec, sample.direction);
+        if scattering == Vec3::ZERO {
+            continue;
+        }
+
+        let shadow_ray = Ray::new(rec.point, sample.direction, ray.time);
+        let transmittance = scene
+            .world
+            .transmittance(&shadow_ray, 0.001, sample.distance);
+
+        total += scattering * sample.irradiance * transmittance;
+    }
+
+    return total;
+}
+
+/**
+ * Weight of a sample taken with density `pdf` when `other_pdf` could have produced it as well.
+ * Favors whichever of the two was more likely to find it
+ */
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    if pdf <= 0.0 {
+        return 0.0;
+    }
+
+    // pdf² / (pdf² + other_pdf²), written so densities of small far away lights don't overflow
+    let ratio = other_pdf / pdf;
+    return 1.0 / (1.0 + ratio * ratio);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use crate::bvh::Bvh;
+    use crate::camera::Camera;
+    use crate::environment::Background;
+    use crate::hittable_list::HittableList;
+    use crate::light::PointLight;
+    use crate::material::{DiffuseLight, Lambertian, Material, Metal};
+    use crate::random;
+    use crate::rect::AxisRect;
+    use crate::texture::SolidColor;
+
+    #[test]
+    fn power_heuristic_weights_add_up_to_one() {
+        for (a, b) in [
+            (1.0, 1.0),
+            (0.2, 3.0),
+            (5.0, 0.01),
+            (1e-30, 1e-29),
+            (1e30, 2e30),
+        ] {
+            let sum = power_heuristic(a, b) + power_heuristic(b, a);
+            assert!((sum - 1.0).abs() < 1e-6);
+        }
+
+        assert_eq!(power_heuristic(2.0, 2.0), 0.5);
+        assert_eq!(power_heuristic(3.0, 1.0), 0.9);
+    }
+
+    #[test]
+    fn power_heuristic_gives_all_weight_to_the_only_strategy() {
+        assert_eq!(power_heuristic(0.5, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 0.5), 0.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+
+        // Densities too large to square, like those of tiny far away lights
+        assert_eq!(power_heuristic(f32::MAX, 0.0), 1.0);
+        assert!(power_heuristic(1e30, 1.0) > 0.999);
+    }
+
+    // A floor lit from above by a small lamp, with a wall beside it that reflects light onto it
+    fn lit_floor(floor: Box<dyn Material>, wall_albedo: f32) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(AxisRect::xz(-5.0, 5.0, -5.0, 5.0, 0.0, floor)));
+        world.add(Box::new(AxisRect::xz(
+            -0.5,
+            0.5,
+            -0.5,
+            0.5,
+            2.0,
+            Box::new(DiffuseLight::from_color(Vec3::splat(10.0))),
+        )));
+        world.add(Box::new(AxisRect::yz(
+            0.0,
+            2.0,
+            -2.0,
+            2.0,
+            1.0,
+            Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::splat(
+                wall_albedo,
+            ))))),
+        )));
+
+        let camera = Camera::new(Vec3::Z, Vec3::ZERO, Vec3::Y, 40.0, 1.0, 0.0, 1.0);
+
+        return Scene {
+            world: Bvh::new(world),
+            camera,
+            background: Background::Color(Vec3::ZERO),
+            lights: Vec::new(),
+        };
+    }
+
+    fn gray() -> Box<dyn Material> {
+        return Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::splat(0.5)))));
+    }
+
+    fn limits(total: i32, diffuse: i32, specular: i32) -> Bounces {
+        return Bounces {
+            total,
+            diffuse,
+            specular,
+            transmission: total,
+        };
+    }
+
+    // Average light the integrator finds along the ray, over many samples
+    fn mean_li(integrator: &dyn Integrator, scene: &Scene, ray: &Ray, count: usize) -> f32 {
+        random::reseed(1);
+        let mut sampler = random::rng();
+
+        let sum: f64 = (0..count)
+            .map(|_| {
+                integrator
+                    .li(
+                        Ray::new(ray.origin, ray.direction, ray.time),
+                        scene,
+                        &mut sampler,
+                    )
+                    .x as f64
+            })
+            .sum();
+
+        return (sum / count as f64) as f32;
+    }
+
+    // Straight down onto the floor, next to the wall
+    fn toward_floor() -> Ray {
+        return Ray::new(Vec3::new(0.5, 1.0, 0.0), Vec3::NEG_Y, 0.0);
+    }
+
+    #[test]
+    fn lights_are_not_sampled_past_the_depth_limits() {
+        let scene = lit_floor(gray(), 0.8);
+
+        let no_bounces = PathIntegrator {
+            max_depth: limits(0, 0, 0),
+            roulette_depth: 100,
+        };
+        assert_eq!(mean_li(&no_bounces, &scene, &toward_floor(), 100), 0.0);
+
+        let up = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::Y, 0.0);
+        assert_eq!(mean_li(&no_bounces, &scene, &up, 100), 10.0);
+
+        let no_diffuse = PathIntegrator {
+            max_depth: limits(10, 0, 10),
+            roulette_depth: 100,
+        };
+        assert_eq!(mean_li(&no_diffuse, &scene, &toward_floor(), 100), 0.0);
+    }
+
+    #[test]
+    fn light_at_the_depth_limit_is_counted_once() {
+        let direct = PathIntegrator {
+            max_depth: limits(10, 1, 10),
+            roulette_depth: 100,
+        };
+        let count = 200_000;
+
+        // With one diffuse bounce, the floor only gets light from the lamp, however bright the wall is
+        let dark_wall = mean_li(&direct, &lit_floor(gray(), 0.0), &toward_floor(), count);
+        let bright_wall = mean_li(&direct, &lit_floor(gray(), 0.8), &toward_floor(), count);
+        assert!((bright_wall / dark_wall - 1.0).abs() < 0.01);
+
+        // While a second bounce lets the wall light the floor
+        let indirect = PathIntegrator {
+            max_depth: limits(10, 2, 10),
+            roulette_depth: 100,
+        };
+        let lit_by_wall = mean_li(&indirect, &lit_floor(gray(), 0.8), &toward_floor(), count);
+        assert!(lit_by_wall / dark_wall > 1.1);
+    }
+
+    #[test]
+    fn specular_and_diffuse_bounces_have_separate_limits() {
+        // A mirror floor showing the lamp
+        let scene = lit_floor(Box::new(Metal::new(Vec3::splat(0.5), 0.0)), 0.8);
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.1), Vec3::new(0.0, -1.0, -0.1), 0.0);
+
+        let mirrors_only = PathIntegrator {
+            max_depth: limits(10, 0, 1),
+            roulette_depth: 100,
+        };
+        assert_eq!(mean_li(&mirrors_only, &scene, &ray, 100), 5.0);
+
+        let no_mirrors = PathIntegrator {
+            max_depth: limits(10, 10, 0),
+            roulette_depth: 100,
+        };
+        assert_eq!(mean_li(&no_mirrors, &scene, &ray, 100), 0.0);
+    }
+
+    #[test]
+    fn russian_roulette_leaves_the_mean_unchanged() {
+        let scene = lit_floor(gray(), 0.8);
+        let count = 200_000;
+
+        let without_roulette = PathIntegrator {
+            max_depth: limits(50, 50, 50),
+            roulette_depth: 100,
+        };
+        let with_roulette = PathIntegrator {
+            max_depth: limits(50, 50, 50),
+            roulette_depth: 0,
+        };
+
+        let expected = mean_li(&without_roulette, &scene, &toward_floor(), count);
+        let found = mean_li(&with_roulette, &scene, &toward_floor(), count);
+        assert!((found / expected - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn direct_lighting_matches_a_single_diffuse_bounce() {
+        let direct = DirectLighting { max_depth: 10 };
+        let one_bounce = PathIntegrator {
+            max_depth: limits(10, 1, 10),
+            roulette_depth: 100,
+        };
+        let count = 200_000;
+
+        // Light bounced off the wall never reaches the floor
+        let dark_wall = mean_li(&direct, &lit_floor(gray(), 0.0), &toward_floor(), count);
+        let bright_wall = mean_li(&direct, &lit_floor(gray(), 0.8), &toward_floor(), count);
+        assert_eq!(bright_wall, dark_wall);
+
+        let expected = mean_li(&one_bounce, &lit_floor(gray(), 0.8), &toward_floor(), count);
+        assert!((bright_wall / expected - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn whitted_only_lights_with_punctual_lights() {
+        let mut scene = lit_floor(gray(), 0.8);
+        let whitted = Whitted { max_depth: 10 };
+
+        // The lamp can be seen, but doesn't light the floor
+        let up = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::Y, 0.0);
+        assert_eq!(mean_li(&whitted, &scene, &up, 1), 10.0);
+        assert_eq!(mean_li(&whitted, &scene, &toward_floor(), 1), 0.0);
+
+        // While a point light 1.5 above the floor does, by the albedo over π and the inverse square
+        scene.lights.push(Box::new(PointLight::new(
+            Vec3::new(0.5, 1.5, 0.0),
+            Vec3::splat(9.0),
+        )));
+        let expected = 0.5 / PI * 9.0 / (1.5 * 1.5);
+        let found = mean_li(&whitted, &scene, &toward_floor(), 1);
+        assert!((found - expected).abs() < 1e-5);
+    }
+
+    #[test]
+    fn ambient_occlusion_only_counts_surfaces_within_the_distance() {
+        let scene = lit_floor(gray(), 0.8);
+
+        let nearby = AmbientOcclusion { distance: 0.1 };
+        assert_eq!(mean_li(&nearby, &scene, &toward_floor(), 1000), 1.0);
+
+        // Far enough to reach the wall and the lamp, which hide part of the sky
+        let far = AmbientOcclusion { distance: 100.0 };
+        let open = mean_li(&far, &scene, &toward_floor(), 1000);
+        assert!(open > 0.5 && open < 1.0, "{}", open);
+
+        let sky = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 1.0), 0.0);
+        assert_eq!(mean_li(&far, &scene, &sky, 1), 0.0);
+    }
+
+    #[test]
+    fn debug_views_show_outward_normals_and_uvs() {
+        let scene = lit_floor(gray(), 0.8);
+        let mut sampler = random::rng();
+        let from_below = Ray::new(Vec3::new(0.5, -1.0, 0.0), Vec3::Y, 0.0);
+
+        // The floor faces up, whichever side it is seen from
+        for ray in [toward_floor(), from_below] {
+            let normal = DebugView::Normals.li(ray, &scene, &mut sampler);
+            assert!((normal - Vec3::new(0.5, 1.0, 0.5)).length() < 1e-6);
+        }
+
+        let uv = DebugView::Uv.li(toward_floor(), &scene, &mut sampler);
+        assert!((uv - Vec3::new(0.55, 0.5, 0.0)).length() < 1e-6);
+    }
+}
@@ -0,0 +1,243 @@
+use glam::Vec3;
+
+/**
+ * A light that sits at a single point or shines from a single direction. Rays can never hit such a light
+ * by chance, so it is only found by casting shadow rays toward it.
+ */
+pub trait Light: Sync + Send {
+    // Light arriving at `point`, or None if none of it reaches it
+    fn illuminate(&self, point: Vec3) -> Option<LightSample>;
+}
+
+pub struct LightSample {
+    // Unit vector from the lit point toward the light
+    pub direction: Vec3,
+    // How far away the light is, along `direction`
+    pub distance: f32,
+    // Light arriving from `direction`, which is all of it as it comes from a single direction
+    pub irradiance: Vec3,
+}
+
+/**
+ * Light spreading equally in all directions from a single point, which falls off with the square of
+ * the distance
+ */
+pub struct PointLight {
+    position: Vec3,
+    // Light given off per unit of solid angle
+    intensity: Vec3,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> Self {
+        return Self {
+            position,
+            intensity,
+        };
+    }
+}
+
+impl Light for PointLight {
+    fn illuminate(&self, point: Vec3) -> Option<LightSample> {
+        return toward_position(self.position, point, self.intensity);
+    }
+}
+
+/**
+ * A point light that only shines within a cone. Toward the edge of the cone the light can fade out
+ * smoothly instead of stopping abruptly.
+ */
+pub struct SpotLight {
+    position: Vec3,
+    // Unit vector along the middle of the cone
+    direction: Vec3,
+    intensity: Vec3,
+    // Cosine of the half angle of the cone, outside of which there is no light
+    cos_total_width: f32,
+    // Cosine of the half angle at which the light starts fading out
+    cos_falloff_start: f32,
+}
+
+impl SpotLight {
+    /**
+     * A spot light at `position` shining along `direction`, within a cone with the given half angle in
+     * degrees
+     */
+    pub fn new(position: Vec3, direction: Vec3, intensity: Vec3, angle: f32) -> Self {
+        let cos_total_width = angle.to_radians().cos();
+
+        return Self {
+            position,
+            direction: direction.normalize(),
+            intensity,
+            cos_total_width,
+            cos_falloff_start: cos_total_width,
+        };
+    }
+
+    // Let the light fade out over the outermost degrees of the cone
+    pub fn with_falloff(mut self, falloff: f32) -> Self {
+        let angle = self.cos_total_width.acos().to_degrees();
+        self.cos_falloff_start = (angle - falloff).max(0.0).to_radians().cos();
+        return self;
+    }
+
+    // How much of the intensity shines from the light at the given cosine from the middle of the cone
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta <= self.cos_total_width {
+            return 0.0;
+        }
+
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+
+        let t =
+            (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        return t * t * (3.0 - 2.0 * t);
+    }
+}
+
+impl Light for SpotLight {
+    fn illuminate(&self, point: Vec3) -> Option<LightSample> {
+        let falloff = self.falloff((point - self.position).normalize().dot(self.direction));
+
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        return toward_position(self.position, point, falloff * self.intensity);
+    }
+}
+
+/**
+ * Light from so far away, like the sun, that it arrives from the same direction everywhere and doesn't
+ * fall off with distance
+ */
+pub struct DirectionalLight {
+    // Unit vector in the direction the light travels
+    direction: Vec3,
+    irradiance: Vec3,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Vec3) -> Self {
+        return Self {
+            direction: direction.normalize(),
+            irradiance,
+        };
+    }
+}
+
+impl Light for DirectionalLight {
+    fn illuminate(&self, _point: Vec3) -> Option<LightSample> {
+        return Some(LightSample {
+            direction: -self.direction,
+            distance: f32::INFINITY,
+            irradiance: self.irradiance,
+        });
+    }
+}
+
+// Light given off by a point with the given intensity, as it arrives at `point`
+fn toward_position(position: Vec3, point: Vec3, intensity: Vec3) -> Option<LightSample> {
+    let offset = position - point;
+    let distance_squared = offset.length_squared();
+
+    if distance_squared <= 0.0 {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+
+    return Some(LightSample {
+        direction: offset / distance,
+        distance,
+        irradiance: intensity / distance_squared,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Light arriving one unit from the spot, at the given angle in degrees from the middle of its cone
+    fn spot_irradiance(spot: &SpotLight, angle: f32) -> f32 {
+        let angle = angle.to_radians();
+        let point = Vec3::new(angle.sin(), 0.0, -angle.cos());
+
+        return spot
+            .illuminate(point)
+            .map_or(0.0, |sample| sample.irradiance.x);
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_the_square_of_the_distance() {
+        let light = PointLight::new(Vec3::new(1.0, 2.0, 3.0), Vec3::splat(8.0));
+
+        for distance in [0.5, 1.0, 2.0, 10.0] {
+            let offset = Vec3::new(2.0, -1.0, 2.0).normalize() * distance;
+            let sample = light.illuminate(Vec3::new(1.0, 2.0, 3.0) - offset).unwrap();
+
+            assert!((sample.direction - offset.normalize()).length() < 1e-5);
+            assert!((sample.distance - distance).abs() < 1e-5);
+            assert!((sample.irradiance.x - 8.0 / (distance * distance)).abs() < 1e-4);
+        }
+
+        assert!(light.illuminate(Vec3::new(1.0, 2.0, 3.0)).is_none());
+    }
+
+    #[test]
+    fn spot_lights_are_full_inside_the_falloff_and_dark_outside_the_cone() {
+        let spot = SpotLight::new(Vec3::ZERO, -Vec3::Z, Vec3::ONE, 30.0).with_falloff(10.0);
+
+        for angle in [0.0, 10.0, 19.9] {
+            assert!(
+                (spot_irradiance(&spot, angle) - 1.0).abs() < 1e-4,
+                "{}",
+                angle
+            );
+        }
+
+        for angle in [30.1, 45.0, 90.0, 180.0] {
+            assert_eq!(spot_irradiance(&spot, angle), 0.0, "{}", angle);
+        }
+    }
+
+    #[test]
+    fn spot_lights_fade_smoothly_across_the_falloff() {
+        let spot = SpotLight::new(Vec3::ZERO, -Vec3::Z, Vec3::ONE, 30.0).with_falloff(10.0);
+
+        let edges: Vec<f32> = (0..=100)
+            .map(|i| spot_irradiance(&spot, 20.0 + i as f32 / 10.0))
+            .collect();
+
+        assert!(edges.windows(2).all(|pair| pair[1] <= pair[0]));
+
+        // Smoothstep starts and ends flat, so the light changes least at either end of the falloff
+        let steps: Vec<f32> = edges.windows(2).map(|pair| pair[0] - pair[1]).collect();
+        assert!(steps[0] < 0.1 * steps[50]);
+        assert!(steps[99] < 0.1 * steps[50]);
+    }
+
+    #[test]
+    fn spot_lights_without_falloff_stop_at_the_edge() {
+        let spot = SpotLight::new(Vec3::ZERO, -Vec3::Z, Vec3::ONE, 30.0);
+
+        assert!((spot_irradiance(&spot, 29.9) - 1.0).abs() < 1e-4);
+        assert_eq!(spot_irradiance(&spot, 30.1), 0.0);
+    }
+
+    #[test]
+    fn directional_lights_are_the_same_everywhere() {
+        let light = DirectionalLight::new(Vec3::new(0.0, -2.0, 0.0), Vec3::splat(3.0));
+
+        for point in [Vec3::ZERO, Vec3::new(100.0, -5.0, 7.0)] {
+            let sample = light.illuminate(point).unwrap();
+
+            assert_eq!(sample.direction, Vec3::Y);
+            assert_eq!(sample.distance, f32::INFINITY);
+            assert_eq!(sample.irradiance, Vec3::splat(3.0));
+        }
+    }
+}
@@ -1,6 +1,9 @@
+// Explicit returns are used throughout the codebase
+#![allow(clippy::needless_return)]
+
 use glam::Vec3;
 use indicatif::{ParallelProgressIterator, ProgressStyle};
-use rand::{rngs::ThreadRng, Rng};
+use rand::Rng;
 use rayon::prelude::*;
 use std::sync::Arc;
 use std::time::Instant;
@@ -8,66 +11,77 @@ use term_table::{
     row::Row, table_cell::Alignment, table_cell::TableCell, TableBuilder, TableStyle,
 };
 
+mod aabb;
+mod bdpt;
+mod bvh;
 mod camera;
+mod distribution;
+mod environment;
 mod hittable;
 mod hittable_list;
+mod integrator;
+mod light;
 mod material;
+mod medium;
+mod obj;
+mod output;
+mod perlin;
+mod planar;
+mod random;
 mod ray;
 mod rect;
 mod scene;
+mod scene_file;
+mod settings;
+mod sky;
 mod sphere;
 mod texture;
+mod tonemap;
+mod transform;
+mod triangle;
 mod vec3;
+mod volume;
 
-use camera::Camera;
-use hittable::Hittable;
-use hittable_list::HittableList;
-use material::ScatterResult;
-use ray::Ray;
-
-// Image
-const ASPECT_RATIO: f32 = 16.0 / 9.0;
-// const IMAGE_WIDTH: i32 = 400;
-// const IMAGE_WIDTH: i32 = 600;
-// const IMAGE_WIDTH: i32 = 1200;
-const IMAGE_WIDTH: i32 = 1920;
-const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f32 / ASPECT_RATIO) as i32;
-
-// const SAMPLES_PER_PIXEL: i32 = 3;
-// const SAMPLES_PER_PIXEL: i32 = 5;
-// const SAMPLES_PER_PIXEL: i32 = 10;
-// const SAMPLES_PER_PIXEL: i32 = 50;
-// const SAMPLES_PER_PIXEL: i32 = 100;
-// const SAMPLES_PER_PIXEL: i32 = 200;
-const SAMPLES_PER_PIXEL: i32 = 2000;
-
-// const MAX_DEPTH: i32 = 3;
-// const MAX_DEPTH: i32 = 6;
-// const MAX_DEPTH: i32 = 12;
-// const MAX_DEPTH: i32 = 50;
-const MAX_DEPTH: i32 = 200;
-
-/**
- * COMPUTED CONSTANTS
- */
-// The average color of multiple sampels per pixels is computed, so this factor decides what to divide the cumulative sum of all the samples with
-const COLOR_SCALE: f32 = 1.0 / SAMPLES_PER_PIXEL as f32;
+use integrator::Integrator;
+use scene::Scene;
+use settings::RenderSettings;
 
 fn main() {
-    // First of all, print the relavant rendering constants to the user
-    print_rendering_info();
+    let settings = match RenderSettings::from_args() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(threads) = settings.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
 
-    // let selected_scene = scene::random_spheres();
-    // let selected_scene = scene::random_scene();
-    let selected_scene = scene::simple_light();
-    // let selected_scene = scene::glowing_sphere();
+    // First of all, print the relavant rendering settings to the user
+    print_rendering_info(&settings);
 
-    // Put scene in an ARC to share it across threads
-    let shared_world = Arc::new(selected_scene.world);
+    // Randomly generated scenes should be reproducible as well
+    if let Some(seed) = settings.seed {
+        random::reseed(seed);
+    }
 
-    let background = selected_scene.background;
+    let selected_scene = match scene::load(&settings.scene, settings.aspect_ratio()) {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let integrator = integrator::from_settings(&settings);
 
-    let camera = selected_scene.camera;
+    // Put scene in an ARC to share it across threads
+    let shared_scene = Arc::new(selected_scene);
 
     // Start timer to figure out how long the render took
     let start = Instant::now();
@@ -78,19 +92,39 @@ fn main() {
         .unwrap();
 
     // Render all pixels. Render each row in parallel
-    let pixels: Vec<Vec<Vec3>> = (0..IMAGE_HEIGHT)
+    let pixels: Vec<Vec<Vec3>> = (0..settings.image_height)
         .into_par_iter()
         .rev()
         .progress_with_style(progress_bar)
         .map(|j| {
+            // Seed per row rather than per thread, since rows can end up on any thread
+            if let Some(seed) = settings.seed {
+                random::reseed(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ j as u64);
+            }
+
             // random number generator
-            let mut rng = rand::thread_rng();
+            let mut rng = random::rng();
+
+            // Grap thread-safe reference to the scene
+            let scene = Arc::clone(&shared_scene);
 
-            // Grap thread-safe reference to the world
-            let world = Arc::clone(&shared_world);
+            return (0..settings.image_width)
+                .map(|i| {
+                    compute_pixel_color(i, j, &scene, integrator.as_ref(), &settings, &mut rng)
+                })
+                .collect();
+        })
+        .collect();
 
-            return (0..IMAGE_WIDTH)
-                .map(|i| compute_pixel_color(i, j, background, &camera, &world, &mut rng))
+    // Add the light the integrator sent to pixels other than the one it was rendering
+    let pixels: Vec<Vec<Vec3>> = pixels
+        .into_iter()
+        .zip((0..settings.image_height as usize).rev())
+        .map(|(row, j)| {
+            return row
+                .into_iter()
+                .enumerate()
+                .map(|(i, color)| color + integrator.splatted(i, j) * settings.color_scale())
                 .collect();
         })
         .collect();
@@ -99,13 +133,30 @@ fn main() {
     let duration = start.elapsed();
     eprintln!("Rendering completed in {:?}", duration);
 
-    // Write pixels to stdout
-    write_image(pixels);
+    let result = output::write_image(
+        &pixels,
+        settings.image_width as usize,
+        settings.image_height as usize,
+        settings.output_format,
+        &settings.post_process,
+        &settings.output,
+    );
+
+    if let Err(err) = result {
+        eprintln!(
+            "error: could not write image to {}: {}",
+            settings.output.display(),
+            err
+        );
+        std::process::exit(1);
+    }
+
+    eprintln!("Image written to {}", settings.output.display());
 
     eprintln!("Done!");
 }
 
-fn print_rendering_info() {
+fn print_rendering_info(settings: &RenderSettings) {
     let table = TableBuilder::new()
         .style(TableStyle::extended())
         .rows(vec![
@@ -116,15 +167,46 @@ fn print_rendering_info() {
             )]),
             Row::new(vec![
                 TableCell::new("Image resolution"),
-                TableCell::new(format!("{}x{}", IMAGE_WIDTH, IMAGE_HEIGHT)),
+                TableCell::new(format!(
+                    "{}x{}",
+                    settings.image_width, settings.image_height
+                )),
             ]),
             Row::new(vec![
                 TableCell::new("Number of samples per pixel"),
-                TableCell::new(format!("{}", SAMPLES_PER_PIXEL)),
+                TableCell::new(format!("{}", settings.samples_per_pixel)),
             ]),
             Row::new(vec![
                 TableCell::new("Maximum amount of light bounces per ray"),
-                TableCell::new(format!("{}", MAX_DEPTH)),
+                TableCell::new(format!("{}", settings.max_depth.total)),
+            ]),
+            Row::new(vec![
+                TableCell::new("Diffuse, specular and transmission bounces"),
+                TableCell::new(format!(
+                    "{}, {}, {}",
+                    settings.max_depth.diffuse,
+                    settings.max_depth.specular,
+                    settings.max_depth.transmission
+                )),
+            ]),
+            Row::new(vec![
+                TableCell::new("Russian roulette after bounce"),
+                TableCell::new(format!("{}", settings.roulette_depth)),
+            ]),
+            Row::new(vec![
+                TableCell::new("Integrator"),
+                TableCell::new(format!("{:?}", settings.integrator)),
+            ]),
+            Row::new(vec![
+                TableCell::new("Scene"),
+                TableCell::new(&settings.scene),
+            ]),
+            Row::new(vec![
+                TableCell::new("Threads"),
+                TableCell::new(format!(
+                    "{}",
+                    settings.threads.unwrap_or_else(rayon::current_num_threads)
+                )),
             ]),
         ])
         .build();
@@ -138,66 +220,21 @@ fn print_rendering_info() {
 fn compute_pixel_color(
     y: i32,
     x: i32,
-    background: Vec3,
-    camera: &Camera,
-    world: &HittableList,
-    rng: &mut ThreadRng,
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    rng: &mut impl Rng,
 ) -> Vec3 {
     let mut pixel_color = Vec3::ZERO;
 
-    for _s in 0..SAMPLES_PER_PIXEL {
-        let u = ((y as f32) + rng.gen_range(0.0..1.0)) / (IMAGE_WIDTH - 1) as f32;
-        let v = ((x as f32) + rng.gen_range(0.0..1.0)) / (IMAGE_HEIGHT - 1) as f32;
+    for _s in 0..settings.samples_per_pixel {
+        let u = ((y as f32) + rng.gen_range(0.0..1.0)) / (settings.image_width - 1) as f32;
+        let v = ((x as f32) + rng.gen_range(0.0..1.0)) / (settings.image_height - 1) as f32;
 
-        let ray = camera.get_ray(u, v);
-        pixel_color += compute_ray_color(ray, background, &world, MAX_DEPTH);
+        let ray = scene.camera.get_ray(u, v);
+        pixel_color += integrator.li(ray, scene, rng);
     }
 
-    return Vec3::new(
-        (pixel_color.x * COLOR_SCALE).sqrt(),
-        (pixel_color.y * COLOR_SCALE).sqrt(),
-        (pixel_color.z * COLOR_SCALE).sqrt(),
-    );
-}
-
-fn compute_ray_color(ray: Ray, background: Vec3, world: &HittableList, depth: i32) -> Vec3 {
-    // If we've exceeded the ray bounce limit, no more light is gathered.
-    if depth <= 0 {
-        return Vec3::new(0.0, 0.0, 0.0);
-    }
-
-    let hit_record = world.hit(&ray, 0.001, f32::INFINITY);
-
-    match hit_record {
-        Some(rec) => {
-            let emitted = rec.material.emitted(rec.u, rec.v, rec.point);
-            match rec.material.scatter(&ray, &rec) {
-                Some(ScatterResult {
-                    scattered,
-                    attenuation,
-                }) => {
-                    emitted
-                        + attenuation * compute_ray_color(scattered, background, &world, depth - 1)
-                }
-                None => emitted,
-            }
-        }
-        None => background,
-    }
-}
-
-fn write_image(pixels: Vec<Vec<Vec3>>) {
-    println!("P3");
-    println!("{0} {1}", IMAGE_WIDTH, IMAGE_HEIGHT);
-    println!("{0}", 255);
-
-    for row in pixels {
-        for color in row {
-            let ir = (255.999 * color.x) as i32;
-            let ig = (255.999 * color.y) as i32;
-            let ib = (255.999 * color.z) as i32;
-
-            println!("{} {} {}", ir, ig, ib);
-        }
-    }
+    // Average of the samples. Gamma correction happens when the image is written
+    return pixel_color * settings.color_scale();
 }
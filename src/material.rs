@@ -1,22 +1,133 @@
+use std::sync::Arc;
+
+use std::f32::consts::PI;
+
 use glam::Vec3;
 use rand::Rng;
 
 use crate::hittable::HitRecord;
+use crate::random;
 use crate::ray::Ray;
 use crate::texture::{SolidColor, Texture};
 use crate::vec3::Vec3Extension;
 
 pub struct ScatterResult {
+    // What the light arriving along the scattered ray is multiplied by: eval over pdf
     pub attenuation: Vec3,
     pub scattered: Ray,
+    // Density of picking the scattered direction, per unit of solid angle. Unused for specular materials
+    pub pdf: f32,
+    pub lobe: Lobe,
 }
 
-pub trait Material {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult>;
+/**
+ * The kind of scattering a sample picked, so paths can limit how often they bounce off each kind
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lobe {
+    // Diffuse surfaces, and volumes
+    Diffuse,
+    // Mirror-like and glossy reflection
+    Specular,
+    // Refraction into or out of a transparent material
+    Transmission,
+}
+
+pub trait Material: Send + Sync {
+    // Pick a direction to scatter the ray in, or None if it is absorbed
+    fn sample(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult>;
+
+    /**
+     * Fraction of the light arriving from `direction` that scatters back along ray_in, per unit of
+     * solid angle and including the cosine term. Zero for specular materials, which only scatter into
+     * the directions sample picks
+     */
+    fn eval(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> Vec3 {
+        return Vec3::ZERO;
+    }
+
+    // Density of sample picking `direction`, per unit of solid angle. Zero for specular materials
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> f32 {
+        return 0.0;
+    }
+
+    /**
+     * Mirrors and glass scatter into exactly one direction, which has no density to weigh against
+     * sampling the lights, so lights are only found by the scattered rays
+     */
+    fn is_specular(&self) -> bool {
+        return false;
+    }
+
+    /**
+     * The kind of scattering eval describes. Light sampled directly at the surface bounces off it this
+     * way, which counts toward the depth limit of the lobe
+     */
+    fn eval_lobe(&self) -> Lobe {
+        return Lobe::Diffuse;
+    }
 
     fn emitted(&self, _u: f32, _v: f32, _point: Vec3) -> Vec3 {
         return Vec3::ZERO; // Black
     }
+
+    // Objects with an emissive material are sampled as lights
+    fn is_emissive(&self) -> bool {
+        return false;
+    }
+
+    // Volumes scatter light inside them rather than off a surface, so there is no cosine to the normal
+    fn is_volumetric(&self) -> bool {
+        return false;
+    }
+
+    /**
+     * Extra factor for light traced from the lights rather than from the camera, when sample scattered it
+     * as `lobe`. Radiance keeps its value through glass here, so the light going the other way has to make up
+     * for the solid angle refraction squeezes it into
+     */
+    fn adjoint_scale(&self, _hit_record: &HitRecord, _lobe: Lobe) -> f32 {
+        return 1.0;
+    }
+}
+
+// Lets several objects share one material, e.g. the sides of a box
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn sample(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
+        return self.as_ref().sample(ray_in, hit_record);
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Vec3 {
+        return self.as_ref().eval(ray_in, hit_record, direction);
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f32 {
+        return self.as_ref().pdf(ray_in, hit_record, direction);
+    }
+
+    fn is_specular(&self) -> bool {
+        return self.as_ref().is_specular();
+    }
+
+    fn eval_lobe(&self) -> Lobe {
+        return self.as_ref().eval_lobe();
+    }
+
+    fn emitted(&self, u: f32, v: f32, point: Vec3) -> Vec3 {
+        return self.as_ref().emitted(u, v, point);
+    }
+
+    fn is_emissive(&self) -> bool {
+        return self.as_ref().is_emissive();
+    }
+
+    fn is_volumetric(&self) -> bool {
+        return self.as_ref().is_volumetric();
+    }
+
+    fn adjoint_scale(&self, hit_record: &HitRecord, lobe: Lobe) -> f32 {
+        return self.as_ref().adjoint_scale(hit_record, lobe);
+    }
 }
 
 pub struct Lambertian {
@@ -30,28 +141,44 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
-        let mut scatter_direction = hit_record.normal;
+    fn sample(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
+        let mut scatter_direction = hit_record.normal + Vec3::random_unit_vector();
 
         /*
         If the random unit vector we generate is exactly opposite the normal vector,
         the two will sum to zero, which will result in a zero scatter direction vector.
-        This leads to bad scenarios later on (infinities and NaNs), so we only add the
-        random component if it is not near zero
+        This leads to bad scenarios later on (infinities and NaNs), so we fall back
+        to the normal if the sum is near zero. Rounding can also leave a nearly opposite
+        sum pointing just below the surface, where the density would be zero
         */
-        if !scatter_direction.is_near_zero() {
-            scatter_direction += Vec3::random_unit_vector();
+        if scatter_direction.is_near_zero() || scatter_direction.dot(hit_record.normal) <= 0.0 {
+            scatter_direction = hit_record.normal;
         }
 
+        // Directions are picked proportionally to the cosine, which cancels out against eval
         let result = ScatterResult {
-            scattered: Ray::new(hit_record.point, scatter_direction),
+            scattered: Ray::new(hit_record.point, scatter_direction, ray_in.time),
             attenuation: self
                 .albedo
                 .value(hit_record.u, hit_record.v, &hit_record.point),
+            pdf: self.pdf(ray_in, hit_record, scatter_direction),
+            lobe: Lobe::Diffuse,
         };
 
         return Some(result);
     }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Vec3 {
+        let albedo = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+
+        return albedo * self.pdf(ray_in, hit_record, direction);
+    }
+
+    fn pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f32 {
+        return hit_record.normal.dot(direction.normalize()).max(0.0) / PI;
+    }
 }
 
 pub struct Metal {
@@ -65,13 +192,51 @@ impl Metal {
     }
 }
 
+impl Metal {
+    /**
+     * Density of the fuzzed reflection picking `direction`. The direction points at a random point
+     * in a ball of radius fuzz around the tip of the reflected direction, so its density is the
+     * volume of the ball along it, ∫ t² dt over the part of the ray inside, divided by the volume of the ball
+     */
+    fn fuzz_pdf(&self, reflected: Vec3, direction: Vec3) -> f32 {
+        let direction = direction.normalize();
+
+        /*
+        Where the ray along the direction enters and leaves the ball. The distances along and across
+        the ray are used rather than the cosine, which rounds to 1 for small amounts of fuzz. The
+        reflected direction isn't quite unit length either, since normals aren't.
+        */
+        let along = direction.dot(reflected);
+        let across_squared = direction.cross(reflected).length_squared();
+        let discriminant = self.fuzz * self.fuzz - across_squared;
+
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+
+        let t_exit = along + discriminant.sqrt();
+        let t_enter = (along - discriminant.sqrt()).max(0.0);
+
+        if t_exit <= 0.0 {
+            return 0.0;
+        }
+
+        // t_exit³ - t_enter³, factored so it doesn't cancel out when the ball is small
+        let volume_along =
+            (t_exit - t_enter) * (t_exit * t_exit + t_exit * t_enter + t_enter * t_enter) / 3.0;
+
+        return volume_along / (4.0 / 3.0 * PI * self.fuzz.powi(3));
+    }
+}
+
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
+    fn sample(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
         let reflected = ray_in.direction.normalize().reflect_in(hit_record.normal);
 
         let scattered = Ray::new(
             hit_record.point,
             reflected + self.fuzz * Vec3::random_in_unit_sphere(),
+            ray_in.time,
         );
 
         if scattered.direction.dot(hit_record.normal) <= 0.0 {
@@ -79,12 +244,41 @@ impl Material for Metal {
         }
 
         let result = ScatterResult {
+            pdf: self.pdf(ray_in, hit_record, scattered.direction),
             scattered,
             attenuation: self.albedo,
+            lobe: Lobe::Specular,
         };
 
         return Some(result);
     }
+
+    // Fuzzed directions that end up below the surface are absorbed
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Vec3 {
+        if direction.dot(hit_record.normal) <= 0.0 {
+            return Vec3::ZERO;
+        }
+
+        return self.albedo * self.pdf(ray_in, hit_record, direction);
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f32 {
+        if self.fuzz == 0.0 {
+            return 0.0;
+        }
+
+        let reflected = ray_in.direction.normalize().reflect_in(hit_record.normal);
+        return self.fuzz_pdf(reflected, direction);
+    }
+
+    // Without fuzz, the reflection is a perfect mirror
+    fn is_specular(&self) -> bool {
+        return self.fuzz == 0.0;
+    }
+
+    fn eval_lobe(&self) -> Lobe {
+        return Lobe::Specular;
+    }
 }
 
 pub struct Dielectric {
@@ -107,34 +301,59 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
+    fn sample(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
         let unit_direction = ray_in.direction.normalize();
         let cos_theta = (-unit_direction).dot(hit_record.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let refraction_ratio = match hit_record.front_face {
-            true => (1.0 / self.index_of_refraction),
+            true => 1.0 / self.index_of_refraction,
             false => self.index_of_refraction,
         };
 
         let can_refract = refraction_ratio * sin_theta <= 1.0;
 
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
 
-        let direction = match can_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > rng.gen_range(0.0..1.0)
-        {
-            true => unit_direction.refract_off(hit_record.normal, refraction_ratio),
-            false => unit_direction.reflect_in(hit_record.normal),
+        // Past the critical angle all of the light is reflected, otherwise the Fresnel term decides
+        let reflects = !can_refract
+            || Dielectric::reflectance(cos_theta, refraction_ratio) > rng.gen_range(0.0..1.0);
+
+        let (direction, lobe) = match reflects {
+            true => (unit_direction.reflect_in(hit_record.normal), Lobe::Specular),
+            false => (
+                unit_direction.refract_off(hit_record.normal, refraction_ratio),
+                Lobe::Transmission,
+            ),
         };
 
         let result = ScatterResult {
-            scattered: Ray::new(hit_record.point, direction),
+            scattered: Ray::new(hit_record.point, direction, ray_in.time),
             attenuation: Vec3::ONE,
+            pdf: 0.0,
+            lobe,
         };
 
         return Some(result);
     }
+
+    fn is_specular(&self) -> bool {
+        return true;
+    }
+
+    // The ratio of the indices of refraction squared, from the side the light arrives on to the other
+    fn adjoint_scale(&self, hit_record: &HitRecord, lobe: Lobe) -> f32 {
+        if lobe != Lobe::Transmission {
+            return 1.0;
+        }
+
+        let ratio = match hit_record.front_face {
+            true => 1.0 / self.index_of_refraction,
+            false => self.index_of_refraction,
+        };
+
+        return ratio * ratio;
+    }
 }
 
 pub struct DiffuseLight {
@@ -143,7 +362,7 @@ pub struct DiffuseLight {
 
 impl DiffuseLight {
     pub fn new(texture: Box<dyn Texture>) -> Self {
-        return Self { texture: texture };
+        return Self { texture };
     }
 
     pub fn from_color(color: Vec3) -> Self {
@@ -152,11 +371,217 @@ impl DiffuseLight {
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
+    fn sample(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Option<ScatterResult> {
         return None;
     }
 
     fn emitted(&self, u: f32, v: f32, point: Vec3) -> Vec3 {
         return self.texture.value(u, v, &point);
     }
+
+    fn is_emissive(&self) -> bool {
+        return true;
+    }
+}
+
+/**
+ * Scatters light equally in all directions, for use inside volumes
+ */
+pub struct Isotropic {
+    albedo: Box<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Box<dyn Texture>) -> Self {
+        return Self { albedo };
+    }
+}
+
+impl Material for Isotropic {
+    fn sample(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
+        let result = ScatterResult {
+            scattered: Ray::new(hit_record.point, Vec3::random_unit_vector(), ray_in.time),
+            attenuation: self
+                .albedo
+                .value(hit_record.u, hit_record.v, &hit_record.point),
+            pdf: 1.0 / (4.0 * PI),
+            lobe: Lobe::Diffuse,
+        };
+
+        return Some(result);
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Vec3 {
+        let albedo = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+
+        return albedo * self.pdf(ray_in, hit_record, direction);
+    }
+
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> f32 {
+        return 1.0 / (4.0 * PI);
+    }
+
+    fn is_volumetric(&self) -> bool {
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::*;
+
+    // A ray arriving at the origin on a tilted surface
+    fn hit_record(material: &dyn Material) -> (Ray, HitRecord<'_>) {
+        let normal = Vec3::new(0.3, 1.0, -0.2).normalize();
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.5), Vec3::new(1.0, -1.0, -0.5), 0.0);
+        let rec = HitRecord::from_ray(&ray, Vec3::ZERO, normal, 1.0, 0.0, 0.0, material);
+
+        return (ray, rec);
+    }
+
+    /**
+     * Integrate a function of the direction over the cap of directions within max_angle of the axis,
+     * with the midpoint rule in the angles around it
+     */
+    fn integrate_cap(axis: Vec3, max_angle: f32, function: impl Fn(Vec3) -> f32) -> f32 {
+        let (x, y) = axis.any_orthonormal_pair();
+        let (steps_theta, steps_phi) = (400, 400);
+        let (d_theta, d_phi) = (max_angle / steps_theta as f32, TAU / steps_phi as f32);
+        let mut sum = 0.0f64;
+
+        for i in 0..steps_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+
+            for j in 0..steps_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let direction = theta.sin() * (phi.cos() * x + phi.sin() * y) + theta.cos() * axis;
+
+                sum += (function(direction) * theta.sin() * d_theta * d_phi) as f64;
+            }
+        }
+
+        return sum as f32;
+    }
+
+    // Fraction of the samples landing within max_angle of the axis, counting absorbed ones
+    fn fraction_in_cap(material: &dyn Material, axis: Vec3, max_angle: f32) -> f32 {
+        let (ray, rec) = hit_record(material);
+        let count = 200_000;
+
+        let inside = (0..count)
+            .filter_map(|_| material.sample(&ray, &rec))
+            .filter(|sample| sample.scattered.direction.normalize().dot(axis) > max_angle.cos())
+            .count();
+
+        return inside as f32 / count as f32;
+    }
+
+    #[test]
+    fn lambertian_pdf_is_normalized_and_matches_sampling() {
+        random::reseed(1);
+        let material = Lambertian::new(Box::new(SolidColor::new(Vec3::splat(0.5))));
+        let (ray, rec) = hit_record(&material);
+        let pdf = |direction| material.pdf(&ray, &rec, direction);
+
+        assert!((integrate_cap(rec.normal, PI, pdf) - 1.0).abs() < 1e-3);
+
+        let axis = (rec.normal + Vec3::X).normalize();
+        let expected = integrate_cap(axis, 0.5, pdf);
+        assert!((fraction_in_cap(&material, axis, 0.5) - expected).abs() < 0.005);
+    }
+
+    #[test]
+    fn metal_pdf_is_normalized_and_matches_sampling() {
+        random::reseed(2);
+
+        for fuzz in [0.05, 0.3, 0.9] {
+            let material = Metal::new(Vec3::splat(0.8), fuzz);
+            let (ray, rec) = hit_record(&material);
+            let reflected = ray.direction.normalize().reflect_in(rec.normal);
+            let pdf = |direction| material.pdf(&ray, &rec, direction);
+
+            // The fuzz ball subtends asin(fuzz) around the reflection, including the part below the
+            // surface, whose samples are absorbed
+            let support = fuzz.asin();
+            assert!((integrate_cap(reflected, support, pdf) - 1.0).abs() < 1e-3);
+
+            let above = |direction: Vec3| match direction.dot(rec.normal) > 0.0 {
+                true => pdf(direction),
+                false => 0.0,
+            };
+            let expected = integrate_cap(reflected, support / 2.0, above);
+            let sampled = fraction_in_cap(&material, reflected, support / 2.0);
+            assert!((sampled - expected).abs() < 0.005);
+        }
+    }
+
+    #[test]
+    fn eval_over_pdf_is_the_attenuation_of_samples() {
+        random::reseed(3);
+        let lambertian = Lambertian::new(Box::new(SolidColor::new(Vec3::new(0.2, 0.4, 0.6))));
+        let metal = Metal::new(Vec3::new(0.9, 0.8, 0.7), 0.4);
+
+        for material in [&lambertian as &dyn Material, &metal] {
+            let (ray, rec) = hit_record(material);
+
+            for _ in 0..100 {
+                let Some(sample) = material.sample(&ray, &rec) else {
+                    continue;
+                };
+
+                let direction = sample.scattered.direction;
+                assert!((sample.pdf - material.pdf(&ray, &rec, direction)).abs() < 1e-6);
+
+                let ratio = material.eval(&ray, &rec, direction) / sample.pdf;
+                assert!((ratio - sample.attenuation).abs().max_element() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn glass_reflects_by_the_fresnel_term_and_totally_past_the_critical_angle() {
+        random::reseed(4);
+        let glass = Dielectric::new(1.5);
+        let normal = Vec3::Y;
+        let count = 100_000;
+
+        // Entering the glass at a grazing angle, where Schlick's approximation reflects about 60%
+        let direction = Vec3::new(1.0, -0.1, 0.0).normalize();
+        let ray = Ray::new(-direction, direction, 0.0);
+        let rec = HitRecord::from_ray(&ray, Vec3::ZERO, normal, 1.0, 0.0, 0.0, &glass);
+        let expected = Dielectric::reflectance(-direction.dot(normal), 1.0 / 1.5);
+
+        let mut reflected = 0;
+        for _ in 0..count {
+            let sample = glass.sample(&ray, &rec).unwrap();
+
+            match sample.lobe {
+                Lobe::Specular => {
+                    reflected += 1;
+                    assert!(sample.scattered.direction.y > 0.0);
+                }
+                _ => assert!(sample.scattered.direction.y < 0.0),
+            }
+        }
+
+        assert!((reflected as f32 / count as f32 - expected).abs() < 0.005);
+
+        // Leaving the glass at 60°, past the critical angle of about 42°
+        let direction = Vec3::new(60f32.to_radians().sin(), 60f32.to_radians().cos(), 0.0);
+        let ray = Ray::new(-direction, direction, 0.0);
+        let rec = HitRecord::from_ray(&ray, Vec3::ZERO, normal, 1.0, 0.0, 0.0, &glass);
+        assert!(!rec.front_face);
+
+        for _ in 0..1000 {
+            let sample = glass.sample(&ray, &rec).unwrap();
+
+            assert_eq!(sample.lobe, Lobe::Specular);
+            let mirrored = Vec3::new(direction.x, -direction.y, 0.0);
+            assert!((sample.scattered.direction - mirrored).length() < 1e-5);
+        }
+    }
 }
@@ -0,0 +1,145 @@
+use glam::Vec3;
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    random,
+    ray::Ray,
+};
+
+/**
+ * Volume of uniform density filling a closed boundary, like smoke or fog.
+ * Rays passing through are scattered at a random distance, which gets shorter the denser the medium is.
+ * The material decides how light scatters inside, normally `Isotropic`.
+ */
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    neg_inv_density: f32,
+    phase_function: Box<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Box<dyn Hittable>,
+        density: f32,
+        phase_function: Box<dyn Material>,
+    ) -> Self {
+        return Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        };
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // Find where the ray enters and leaves the boundary, even if it starts inside
+        let t_enter = self.boundary.hit(ray, f32::NEG_INFINITY, f32::INFINITY)?.t;
+        let t_exit = self.boundary.hit(ray, t_enter + 0.0001, f32::INFINITY)?.t;
+
+        let t_enter = t_enter.max(t_min).max(0.0);
+        let t_exit = t_exit.min(t_max);
+
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = ray.direction.length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+
+        let hit_distance =
+            self.neg_inv_density * random::rng().gen_range(f32::EPSILON..1.0f32).ln();
+
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+
+        // There is no surface inside a volume, so the normal and the side are arbitrary
+        return Some(HitRecord {
+            point: ray.at(t),
+            normal: Vec3::X,
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            material: self.phase_function.as_ref(),
+        });
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return self.boundary.bounding_box();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Isotropic;
+    use crate::rect::BoxShape;
+    use crate::texture::SolidColor;
+
+    // Fog filling the cube from -1 to 1
+    fn fog(density: f32) -> ConstantMedium {
+        let white = || -> Box<dyn Material> {
+            return Box::new(Isotropic::new(Box::new(SolidColor::new(Vec3::ONE))));
+        };
+
+        return ConstantMedium::new(
+            Box::new(BoxShape::new(Vec3::splat(-1.0), Vec3::ONE, white())),
+            density,
+            white(),
+        );
+    }
+
+    // Fraction of the rays that get through the fog between t_min and t_max without scattering
+    fn fraction_through(medium: &ConstantMedium, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let count = 100_000;
+        let through = (0..count)
+            .filter(|_| medium.hit(ray, t_min, t_max).is_none())
+            .count();
+
+        return through as f32 / count as f32;
+    }
+
+    #[test]
+    fn transmittance_falls_off_exponentially_with_distance() {
+        random::reseed(1);
+        let medium = fog(0.5);
+
+        // Right through the cube, with a direction that isn't normalized
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 4.0), 0.0);
+        let expected = (-0.5f32 * 2.0).exp();
+        assert!((fraction_through(&medium, &ray, 0.001, f32::INFINITY) - expected).abs() < 0.01);
+
+        // From the middle, and stopping half way out
+        let ray = Ray::new(Vec3::ZERO, Vec3::X, 0.0);
+        let expected = (-0.5f32 * 1.0).exp();
+        assert!((fraction_through(&medium, &ray, 0.001, f32::INFINITY) - expected).abs() < 0.01);
+
+        let expected = (-0.5f32 * 0.5).exp();
+        assert!((fraction_through(&medium, &ray, 0.001, 0.5) - expected).abs() < 0.01);
+
+        // Rays that miss the boundary always get through
+        let ray = Ray::new(Vec3::new(0.0, 2.0, -5.0), Vec3::Z, 0.0);
+        assert_eq!(fraction_through(&medium, &ray, 0.001, f32::INFINITY), 1.0);
+    }
+
+    #[test]
+    fn scattering_happens_inside_the_boundary() {
+        random::reseed(2);
+        let medium = fog(2.0);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::Z, 0.0);
+
+        for _ in 0..1000 {
+            if let Some(rec) = medium.hit(&ray, 0.001, f32::INFINITY) {
+                assert!((4.0..=6.0).contains(&rec.t));
+                assert!(rec.point.abs().max_element() <= 1.0 + 1e-5);
+            }
+        }
+    }
+}
@@ -0,0 +1,750 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3};
+
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::texture::{ImageData, ImageTexture, SolidColor, TextureFilter, TextureWrap};
+use crate::triangle::TriangleMesh;
+
+/**
+ * Error while loading an OBJ or MTL file. Points at the offending line when there is one.
+ */
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        };
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// Material properties as described in an MTL file
+#[derive(Clone)]
+struct MtlMaterial {
+    diffuse: Vec3,
+    specular: Vec3,
+    emission: Vec3,
+    shininess: f32,
+    index_of_refraction: f32,
+    dissolve: f32,
+    illumination_model: Option<u32>,
+    diffuse_map: Option<PathBuf>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        return Self {
+            diffuse: Vec3::splat(0.8),
+            specular: Vec3::ZERO,
+            emission: Vec3::ZERO,
+            shininess: 0.0,
+            index_of_refraction: 1.5,
+            dissolve: 1.0,
+            illumination_model: None,
+            diffuse_map: None,
+        };
+    }
+}
+
+// One (v, vt, vn) corner of a face, as zero based indices
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+// Faces sharing the same material
+struct FaceGroup {
+    material: Option<String>,
+    triangles: Vec<[FaceVertex; 3]>,
+}
+
+/**
+ * Load a Wavefront OBJ file (and the MTL files it references) into a list of triangle meshes.
+ * A mesh is created per material used in the file.
+ */
+pub fn load_obj(path: &Path) -> Result<HittableList, ObjError> {
+    let source = read_file(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<Vec2> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+
+    let mut materials: HashMap<String, MtlMaterial> = HashMap::new();
+    let mut groups: Vec<FaceGroup> = vec![FaceGroup {
+        material: None,
+        triangles: Vec::new(),
+    }];
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| ObjError {
+            path: path.to_path_buf(),
+            line: Some(line_number),
+            message,
+        };
+
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+
+        let arguments: Vec<&str> = tokens.take_while(|t| !t.starts_with('#')).collect();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&arguments, 3, 4).map_err(error)?),
+            "vn" => normals.push(parse_vec3(&arguments, 3, 3).map_err(error)?),
+            "vt" => {
+                let uv = parse_floats(&arguments, 1, 3).map_err(error)?;
+                uvs.push(Vec2::new(uv[0], *uv.get(1).unwrap_or(&0.0)));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        arguments.len()
+                    )));
+                }
+
+                let corners = arguments
+                    .iter()
+                    .map(|argument| {
+                        parse_face_vertex(argument, positions.len(), uvs.len(), normals.len())
+                    })
+                    .collect::<Result<Vec<FaceVertex>, String>>()
+                    .map_err(error)?;
+
+                let group = groups.last_mut().unwrap();
+
+                // Triangulate polygons as a fan around the first vertex
+                for i in 1..corners.len() - 1 {
+                    group
+                        .triangles
+                        .push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "usemtl" => {
+                let name = arguments.join(" ");
+
+                if !materials.contains_key(&name) {
+                    return Err(error(format!("unknown material '{}'", name)));
+                }
+
+                groups.push(FaceGroup {
+                    material: Some(name),
+                    triangles: Vec::new(),
+                });
+            }
+            "mtllib" => {
+                if arguments.is_empty() {
+                    return Err(error("expected a file name after 'mtllib'".to_string()));
+                }
+
+                for file_name in arguments {
+                    let mtl_path = directory.join(file_name);
+
+                    // A missing library is reported on the line of the OBJ file that names it
+                    let mtl_source = fs::read_to_string(&mtl_path).map_err(|err| {
+                        error(format!("could not read '{}': {}", mtl_path.display(), err))
+                    })?;
+
+                    materials.extend(load_mtl(&mtl_path, &mtl_source)?);
+                }
+            }
+            // Grouping, smoothing groups, lines and points don't affect the rendered triangles
+            _ => {}
+        }
+    }
+
+    let mut textures: HashMap<PathBuf, Arc<ImageData>> = HashMap::new();
+    let mut world = HittableList::new();
+
+    for group in groups {
+        if group.triangles.is_empty() {
+            continue;
+        }
+
+        let description = match &group.material {
+            Some(name) => materials[name].clone(),
+            None => MtlMaterial::default(),
+        };
+
+        let material = build_material(&description, &mut textures)?;
+
+        let mesh = build_mesh(&group, &positions, &uvs, &normals, material);
+
+        for triangle in mesh.into_triangles() {
+            world.add(Box::new(triangle));
+        }
+    }
+
+    return Ok(world);
+}
+
+fn load_mtl(path: &Path, source: &str) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| ObjError {
+            path: path.to_path_buf(),
+            line: Some(line_number),
+            message,
+        };
+
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+
+        let arguments: Vec<&str> = tokens.take_while(|t| !t.starts_with('#')).collect();
+
+        if keyword == "newmtl" {
+            if arguments.is_empty() {
+                return Err(error("expected a material name after 'newmtl'".to_string()));
+            }
+
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+
+            current = Some((arguments.join(" "), MtlMaterial::default()));
+            continue;
+        }
+
+        let material = match current.as_mut() {
+            Some((_, material)) => material,
+            None => {
+                return Err(error(format!(
+                    "'{}' appears before any 'newmtl' statement",
+                    keyword
+                )))
+            }
+        };
+
+        match keyword {
+            "Kd" => material.diffuse = parse_vec3(&arguments, 3, 3).map_err(error)?,
+            "Ks" => material.specular = parse_vec3(&arguments, 3, 3).map_err(error)?,
+            "Ke" => material.emission = parse_vec3(&arguments, 3, 3).map_err(error)?,
+            "Ns" => material.shininess = parse_floats(&arguments, 1, 1).map_err(error)?[0],
+            "Ni" => {
+                material.index_of_refraction = parse_floats(&arguments, 1, 1).map_err(error)?[0]
+            }
+            "d" => material.dissolve = parse_floats(&arguments, 1, 1).map_err(error)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats(&arguments, 1, 1).map_err(error)?[0],
+            "illum" => {
+                let model = arguments
+                    .first()
+                    .and_then(|argument| argument.parse::<u32>().ok())
+                    .ok_or_else(|| error("expected an integer illumination model".to_string()))?;
+
+                material.illumination_model = Some(model);
+            }
+            "map_Kd" => {
+                let rest = &line.trim_start()[keyword.len()..];
+                let file_name = map_file_name(rest).map_err(error)?;
+
+                material.diffuse_map = Some(directory.join(file_name));
+            }
+            // Other properties have no equivalent in the available materials
+            _ => {}
+        }
+    }
+
+    if let Some((name, material)) = current.take() {
+        materials.insert(name, material);
+    }
+
+    return Ok(materials);
+}
+
+/**
+ * Map an MTL description onto the closest material the renderer supports
+ */
+fn build_material(
+    description: &MtlMaterial,
+    textures: &mut HashMap<PathBuf, Arc<ImageData>>,
+) -> Result<Box<dyn Material>, ObjError> {
+    if description.emission.max_element() > 0.0 {
+        return Ok(Box::new(DiffuseLight::from_color(description.emission)));
+    }
+
+    // Illumination models 4, 6, 7 and 9 are the transparent ones
+    let is_transparent =
+        description.dissolve < 1.0 || matches!(description.illumination_model, Some(4 | 6 | 7 | 9));
+
+    if is_transparent {
+        return Ok(Box::new(Dielectric::new(description.index_of_refraction)));
+    }
+
+    // Illumination model 3 turns on ray traced reflections
+    let is_reflective = description.illumination_model == Some(3)
+        || (description.diffuse.max_element() <= 0.0
+            && description.diffuse_map.is_none()
+            && description.specular.max_element() > 0.0);
+
+    if is_reflective {
+        // Convert the Phong exponent into a roughness
+        let fuzz = (2.0 / (description.shininess.max(0.0) + 2.0)).sqrt();
+        return Ok(Box::new(Metal::new(description.specular, fuzz)));
+    }
+
+    if let Some(map_path) = &description.diffuse_map {
+        let image = match textures.get(map_path) {
+            Some(image) => Arc::clone(image),
+            None => {
+                // Diffuse maps are color data, so they are stored as sRGB
+                let image = ImageData::load(map_path, true).map_err(|err| ObjError {
+                    path: map_path.clone(),
+                    line: None,
+                    message: format!("could not load texture: {}", err),
+                })?;
+
+                let image = Arc::new(image);
+                textures.insert(map_path.clone(), Arc::clone(&image));
+                image
+            }
+        };
+
+        let texture = ImageTexture::new(image, TextureFilter::Bilinear, TextureWrap::Repeat);
+        return Ok(Box::new(Lambertian::new(Box::new(texture))));
+    }
+
+    return Ok(Box::new(Lambertian::new(Box::new(SolidColor::new(
+        description.diffuse,
+    )))));
+}
+
+/**
+ * OBJ files index positions, uvs and normals separately, while meshes share one index for all of them.
+ * Every distinct combination becomes its own mesh vertex.
+ */
+fn build_mesh(
+    group: &FaceGroup,
+    positions: &[Vec3],
+    uvs: &[Vec2],
+    normals: &[Vec3],
+    material: Box<dyn Material>,
+) -> TriangleMesh {
+    let has_uvs = group
+        .triangles
+        .iter()
+        .flatten()
+        .any(|(_, vt, _)| vt.is_some());
+    let has_normals = group
+        .triangles
+        .iter()
+        .flatten()
+        .any(|(_, _, vn)| vn.is_some());
+
+    let mut vertex_indices: HashMap<FaceVertex, usize> = HashMap::new();
+    let mut mesh_positions = Vec::new();
+    let mut mesh_uvs = Vec::new();
+    let mut mesh_normals = Vec::new();
+    let mut indices = Vec::with_capacity(group.triangles.len());
+
+    for triangle in &group.triangles {
+        let mut triangle_indices = [0; 3];
+
+        for (corner, vertex) in triangle.iter().enumerate() {
+            triangle_indices[corner] = *vertex_indices.entry(*vertex).or_insert_with(|| {
+                let (v, vt, vn) = *vertex;
+
+                mesh_positions.push(positions[v]);
+                // Missing normals become zero, which makes the triangle fall back to flat shading
+                mesh_normals.push(vn.map_or(Vec3::ZERO, |vn| normals[vn]));
+                mesh_uvs.push(vt.map_or(Vec2::ZERO, |vt| uvs[vt]));
+
+                mesh_positions.len() - 1
+            });
+        }
+
+        indices.push(triangle_indices);
+    }
+
+    return TriangleMesh::new(
+        mesh_positions,
+        if has_normals {
+            Some(mesh_normals)
+        } else {
+            None
+        },
+        if has_uvs { Some(mesh_uvs) } else { None },
+        indices,
+        material,
+    );
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    return fs::read_to_string(path).map_err(|err| ObjError {
+        path: path.to_path_buf(),
+        line: None,
+        message: err.to_string(),
+    });
+}
+
+/**
+ * The file name of a texture map, which is the rest of the line after any options and may contain
+ * spaces. None of the options (like -s to scale or -bm for bump strength) apply to the materials here,
+ * so they are skipped.
+ */
+fn map_file_name(rest: &str) -> Result<&str, String> {
+    let mut rest = strip_comment(rest).trim();
+
+    while rest.starts_with('-') {
+        let (option, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+        // The least and most values the option takes. Options with several only take numbers.
+        let (min, max) = match option {
+            "-blendu" | "-blendv" | "-bm" | "-boost" | "-cc" | "-clamp" | "-imfchan"
+            | "-texres" => (1, 1),
+            "-mm" => (2, 2),
+            "-o" | "-s" | "-t" => (1, 3),
+            _ => return Err(format!("unknown texture option '{}'", option)),
+        };
+
+        rest = after.trim_start();
+
+        for count in 0..max {
+            let (value, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+            if value.is_empty() || (count >= min && value.parse::<f32>().is_err()) {
+                break;
+            }
+
+            rest = after.trim_start();
+        }
+    }
+
+    if rest.is_empty() {
+        return Err("expected a file name after 'map_Kd'".to_string());
+    }
+
+    return Ok(rest);
+}
+
+// Comments start at a # at the start of a word, and run to the end of the line
+fn strip_comment(line: &str) -> &str {
+    let start = line
+        .char_indices()
+        .find(|&(i, c)| c == '#' && (i == 0 || line[..i].ends_with(char::is_whitespace)))
+        .map_or(line.len(), |(i, _)| i);
+
+    return &line[..start];
+}
+
+fn parse_floats(arguments: &[&str], min: usize, max: usize) -> Result<Vec<f32>, String> {
+    if arguments.len() < min || arguments.len() > max {
+        let expected = match min == max {
+            true => format!("{}", min),
+            false => format!("{} to {}", min, max),
+        };
+
+        return Err(format!(
+            "expected {} numbers, found {}",
+            expected,
+            arguments.len()
+        ));
+    }
+
+    return arguments
+        .iter()
+        .map(|argument| {
+            argument
+                .parse::<f32>()
+                .map_err(|_| format!("'{}' is not a valid number", argument))
+        })
+        .collect();
+}
+
+fn parse_vec3(arguments: &[&str], min: usize, max: usize) -> Result<Vec3, String> {
+    let values = parse_floats(arguments, min, max)?;
+    return Ok(Vec3::new(values[0], values[1], values[2]));
+}
+
+// Parse one of `v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn parse_face_vertex(
+    argument: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<FaceVertex, String> {
+    let mut parts = argument.split('/');
+
+    let v = match parts.next() {
+        Some(part) if !part.is_empty() => resolve_index(part, position_count, "vertex")?,
+        _ => return Err(format!("'{}' is missing a vertex index", argument)),
+    };
+
+    let vt = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, uv_count, "texture")?),
+        _ => None,
+    };
+
+    let vn = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, normal_count, "normal")?),
+        _ => None,
+    };
+
+    if parts.next().is_some() {
+        return Err(format!("'{}' is not a valid face vertex", argument));
+    }
+
+    return Ok((v, vt, vn));
+}
+
+// OBJ indices start at 1, and negative indices count backwards from the latest element
+fn resolve_index(part: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index = part
+        .parse::<i64>()
+        .map_err(|_| format!("'{}' is not a valid {} index", part, kind))?;
+
+    let resolved = match index {
+        i if i > 0 => i - 1,
+        i if i < 0 => count as i64 + i,
+        _ => -1,
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} is out of range ({} defined so far)",
+            kind, index, count
+        ));
+    }
+
+    return Ok(resolved as usize);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::ray::Ray;
+
+    // Write the files into a directory of their own, so tests running at the same time don't collide
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("raytracer_obj_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        for (file_name, contents) in files {
+            fs::write(directory.join(file_name), contents).unwrap();
+        }
+
+        return directory;
+    }
+
+    fn load(name: &str, files: &[(&str, &str)]) -> Result<HittableList, ObjError> {
+        let directory = write_files(name, files);
+        let result = load_obj(&directory.join(files[0].0));
+        fs::remove_dir_all(&directory).unwrap();
+
+        return result;
+    }
+
+    // Two unit squares side by side on the ground, the second one using the other material
+    const SQUARES: &str = "\
+mtllib lights.mtl
+v 0 0 0
+v 1 0 0
+v 1 0 1
+v 0 0 1
+usemtl glow
+f 1 2 3 4   # a quad, split in two
+
+v 2 0 0
+v 3 0 0
+v 3 0 1
+v 2 0 1
+usemtl {material}
+f -4 -3 -2
+f -4 -2 -1
+";
+
+    const LIGHTS: &str = "\
+# Materials for the squares
+newmtl glow
+Kd 0 0 0
+Ke 4 3 2
+
+newmtl mirror
+Ks 0.9 0.9 0.9
+Ns 200
+illum 3
+";
+
+    fn squares(material: &str) -> String {
+        return SQUARES.replace("{material}", material);
+    }
+
+    fn hit_down(world: &HittableList, x: f32, z: f32) -> Option<HitRecord<'_>> {
+        let ray = Ray::new(Vec3::new(x, 1.0, z), Vec3::NEG_Y, 0.0);
+        return world.hit(&ray, 0.001, f32::INFINITY);
+    }
+
+    #[test]
+    fn polygons_are_triangulated_and_negative_indices_count_back() {
+        let world = load(
+            "faces",
+            &[("squares.obj", &squares("mirror")), ("lights.mtl", LIGHTS)],
+        )
+        .unwrap();
+
+        for (x, z) in [(0.2, 0.7), (0.8, 0.3), (2.2, 0.7), (2.8, 0.3)] {
+            let rec = hit_down(&world, x, z).unwrap();
+            assert!((rec.t - 1.0).abs() < 1e-5);
+        }
+
+        assert!(hit_down(&world, 1.5, 0.5).is_none());
+        assert_eq!(world.into_objects().len(), 4);
+    }
+
+    #[test]
+    fn mtl_materials_are_mapped_onto_the_closest_material() {
+        let world = load(
+            "materials",
+            &[("squares.obj", &squares("mirror")), ("lights.mtl", LIGHTS)],
+        )
+        .unwrap();
+
+        let glow = hit_down(&world, 0.5, 0.5).unwrap();
+        assert_eq!(
+            glow.material.emitted(glow.u, glow.v, glow.point),
+            Vec3::new(4.0, 3.0, 2.0)
+        );
+        assert!(world.is_light());
+
+        // Illumination model 3 is a metal, glossy enough here to reflect nothing off to the side
+        let mirror = hit_down(&world, 2.5, 0.5).unwrap();
+        let ray = Ray::new(Vec3::new(2.5, 1.0, 0.5), Vec3::NEG_Y, 0.0);
+        assert!(!mirror.material.is_emissive() && !mirror.material.is_specular());
+        assert!(mirror.material.eval(&ray, &mirror, Vec3::Y).x > 0.0);
+        assert_eq!(
+            mirror
+                .material
+                .eval(&ray, &mirror, Vec3::new(1.0, 0.2, 0.0)),
+            Vec3::ZERO
+        );
+    }
+
+    #[test]
+    fn unknown_materials_point_at_the_line() {
+        let error = load(
+            "unknown",
+            &[("squares.obj", &squares("missing")), ("lights.mtl", LIGHTS)],
+        )
+        .err()
+        .unwrap();
+
+        assert!(error.path.ends_with("squares.obj"));
+        assert_eq!(error.line, Some(13));
+        assert_eq!(error.message, "unknown material 'missing'");
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let error = load("short_vertex", &[("bad.obj", "v 0 0 0\n\nv 1 2\n")])
+            .err()
+            .unwrap();
+        assert_eq!(error.line, Some(3));
+        assert_eq!(error.message, "expected 3 to 4 numbers, found 2");
+        assert!(error
+            .to_string()
+            .ends_with("bad.obj:3: expected 3 to 4 numbers, found 2"));
+
+        let error = load(
+            "range",
+            &[("bad.obj", "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4\n")],
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.line, Some(4));
+        assert_eq!(
+            error.message,
+            "vertex index 4 is out of range (3 defined so far)"
+        );
+
+        let error = load("zero", &[("bad.obj", "v 0 0 0\nf 0 1 1\n")])
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.message,
+            "vertex index 0 is out of range (1 defined so far)"
+        );
+
+        let error = load(
+            "mtl",
+            &[
+                ("bad.obj", "mtllib bad.mtl\n"),
+                ("bad.mtl", "# no name yet\nKd 1 1 1\n"),
+            ],
+        )
+        .err()
+        .unwrap();
+        assert!(error.path.ends_with("bad.mtl"));
+        assert_eq!(error.line, Some(2));
+        assert_eq!(error.message, "'Kd' appears before any 'newmtl' statement");
+
+        let error = load("missing_mtl", &[("bad.obj", "mtllib nowhere.mtl\n")])
+            .err()
+            .unwrap();
+        assert!(error.path.ends_with("bad.obj"));
+        assert_eq!(error.line, Some(1));
+        assert!(error.message.starts_with("could not read '"));
+        assert!(error.message.contains("nowhere.mtl"));
+    }
+
+    #[test]
+    fn map_file_names_come_after_the_options() {
+        assert_eq!(map_file_name(" wood.png"), Ok("wood.png"));
+        assert_eq!(
+            map_file_name(" -s 2 2 1 -bm 0.5 old wood.png  # scaled"),
+            Ok("old wood.png")
+        );
+        assert_eq!(map_file_name(" -o 0.5 -clamp on wood.png"), Ok("wood.png"));
+        assert_eq!(map_file_name(" -mm 0 1 wood#1.png"), Ok("wood#1.png"));
+
+        assert_eq!(
+            map_file_name(" -s 2 2"),
+            Err("expected a file name after 'map_Kd'".to_string())
+        );
+        assert_eq!(
+            map_file_name(" -scale 2 wood.png"),
+            Err("unknown texture option '-scale'".to_string())
+        );
+    }
+
+    #[test]
+    fn face_vertices_may_leave_out_uvs_and_normals() {
+        assert_eq!(parse_face_vertex("3", 5, 5, 5), Ok((2, None, None)));
+        assert_eq!(parse_face_vertex("3/1", 5, 5, 5), Ok((2, Some(0), None)));
+        assert_eq!(parse_face_vertex("3//-1", 5, 5, 5), Ok((2, None, Some(4))));
+        assert_eq!(
+            parse_face_vertex("-5/2/4", 5, 5, 5),
+            Ok((0, Some(1), Some(3)))
+        );
+
+        assert!(parse_face_vertex("/2/4", 5, 5, 5).is_err());
+        assert!(parse_face_vertex("1/2/3/4", 5, 5, 5).is_err());
+        assert!(parse_face_vertex("1/x", 5, 5, 5).is_err());
+        assert!(parse_face_vertex("-6", 5, 5, 5).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use exr::prelude::f16;
+use glam::Vec3;
+
+use crate::tonemap::PostProcess;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// Binary (P6) portable pixmap
+    Ppm,
+    /// OpenEXR, storing the linear radiance without clamping
+    Exr(ExrPrecision),
+    /// Portable float map, storing the linear radiance as 32 bit floats
+    Pfm,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExrPrecision {
+    /// 16 bit floats
+    Half,
+    /// 32 bit floats
+    Float,
+}
+
+impl ImageFormat {
+    // Pick the format from the file extension
+    pub fn from_path(path: &Path, exr_precision: ExrPrecision) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        return match extension.as_deref() {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("ppm") => Ok(ImageFormat::Ppm),
+            Some("exr") => Ok(ImageFormat::Exr(exr_precision)),
+            Some("pfm") => Ok(ImageFormat::Pfm),
+            _ => Err(format!(
+                "can't tell the image format of '{}', use a .png, .ppm, .exr or .pfm extension",
+                path.display()
+            )),
+        };
+    }
+}
+
+/**
+ * Write the linear radiance of each pixel to a file. Rows are ordered from top to bottom.
+ * High dynamic range formats get the values as is, others go through the post process first.
+ */
+pub fn write_image(
+    pixels: &[Vec<Vec3>],
+    width: usize,
+    height: usize,
+    format: ImageFormat,
+    post_process: &PostProcess,
+    path: &Path,
+) -> io::Result<()> {
+    // The EXR writer creates the file itself, the other formats are written through a buffer
+    return match format {
+        ImageFormat::Png => write_file(path, |writer| {
+            write_png(&to_rgb8(pixels, post_process), width, height, writer)
+        }),
+        ImageFormat::Ppm => write_file(path, |writer| {
+            write_ppm(&to_rgb8(pixels, post_process), width, height, writer)
+        }),
+        ImageFormat::Pfm => write_file(path, |writer| write_pfm(pixels, width, height, writer)),
+        ImageFormat::Exr(precision) => write_exr(pixels, width, height, precision, path),
+    };
+}
+
+fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+
+    return writer.flush();
+}
+
+fn to_rgb8(pixels: &[Vec<Vec3>], post_process: &PostProcess) -> Vec<u8> {
+    return pixels
+        .iter()
+        .flatten()
+        .flat_map(|radiance| {
+            let color = post_process.apply(*radiance);
+
+            [
+                (255.999 * color.x) as u8,
+                (255.999 * color.y) as u8,
+                (255.999 * color.z) as u8,
+            ]
+        })
+        .collect();
+}
+
+fn write_png(data: &[u8], width: usize, height: usize, writer: &mut impl Write) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    let mut png_writer = encoder.write_header()?;
+    png_writer.write_image_data(data)?;
+    png_writer.finish()?;
+
+    return Ok(());
+}
+
+fn write_ppm(data: &[u8], width: usize, height: usize, writer: &mut impl Write) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+    return writer.write_all(data);
+}
+
+fn write_pfm(
+    pixels: &[Vec<Vec3>],
+    width: usize,
+    height: usize,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    // A negative scale means the data is little endian
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    // PFM stores the rows from bottom to top
+    for row in pixels.iter().rev() {
+        for color in row {
+            for channel in color.to_array() {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+fn write_exr(
+    pixels: &[Vec<Vec3>],
+    width: usize,
+    height: usize,
+    precision: ExrPrecision,
+    path: &Path,
+) -> io::Result<()> {
+    let result = match precision {
+        ExrPrecision::Half => exr::prelude::write_rgb_file(path, width, height, |x, y| {
+            let color = pixels[y][x];
+            (
+                f16::from_f32(color.x),
+                f16::from_f32(color.y),
+                f16::from_f32(color.z),
+            )
+        }),
+        ExrPrecision::Float => exr::prelude::write_rgb_file(path, width, height, |x, y| {
+            let color = pixels[y][x];
+            (color.x, color.y, color.z)
+        }),
+    };
+
+    return result.map_err(|err| match err {
+        exr::error::Error::Io(err) => err,
+        err => io::Error::other(err.to_string()),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tonemap::ToneMapping;
+
+    const PLAIN: PostProcess = PostProcess {
+        exposure: 0.0,
+        tone_mapping: ToneMapping::None,
+    };
+
+    // Two rows of three pixels: black, white and a gray that sRGB encodes as 188, then pure colors
+    fn test_pixels() -> Vec<Vec<Vec3>> {
+        return vec![
+            vec![Vec3::ZERO, Vec3::ONE, Vec3::splat(0.5)],
+            vec![Vec3::X, Vec3::Y, Vec3::new(0.0, 0.0, 2.0)],
+        ];
+    }
+
+    #[test]
+    fn ppm_has_a_header_and_rows_from_top_to_bottom() {
+        let mut bytes = Vec::new();
+        write_ppm(&to_rgb8(&test_pixels(), &PLAIN), 3, 2, &mut bytes).unwrap();
+
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(
+            &bytes[header.len()..],
+            [0, 0, 0, 255, 255, 255, 188, 188, 188, 255, 0, 0, 0, 255, 0, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn png_decodes_to_the_same_pixels() {
+        let data = to_rgb8(&test_pixels(), &PLAIN);
+        let mut bytes = Vec::new();
+        write_png(&data, 3, 2, &mut bytes).unwrap();
+
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (3, 2));
+        assert_eq!(decoded.into_raw(), data);
+    }
+
+    #[test]
+    fn pfm_has_a_header_and_rows_from_bottom_to_top() {
+        let mut bytes = Vec::new();
+        write_pfm(&test_pixels(), 3, 2, &mut bytes).unwrap();
+
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+
+        let values: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        // Unlike the 8 bit formats, values above 1 are kept
+        let expected: Vec<f32> = test_pixels()
+            .iter()
+            .rev()
+            .flatten()
+            .flat_map(|color| color.to_array())
+            .collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn exr_reads_back_the_radiance() {
+        for precision in [ExrPrecision::Float, ExrPrecision::Half] {
+            let path = std::env::temp_dir().join(format!(
+                "raytracer_output_{:?}_{}.exr",
+                precision,
+                std::process::id()
+            ));
+            let format = ImageFormat::from_path(&path, precision).unwrap();
+            write_image(&test_pixels(), 3, 2, format, &PLAIN, &path).unwrap();
+
+            let image = crate::texture::ImageData::load(&path, false).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!((image.width(), image.height()), (3, 2));
+            for (y, row) in test_pixels().iter().enumerate() {
+                for (x, color) in row.iter().enumerate() {
+                    // 0.5, 1 and 2 are exact as 16 bit floats too
+                    assert_eq!(image.pixel(x, y), *color);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn formats_are_picked_by_extension() {
+        let format = |path: &str| ImageFormat::from_path(Path::new(path), ExrPrecision::Half);
+
+        assert_eq!(format("out.png"), Ok(ImageFormat::Png));
+        assert_eq!(format("renders/out.PPM"), Ok(ImageFormat::Ppm));
+        assert_eq!(format("out.exr"), Ok(ImageFormat::Exr(ExrPrecision::Half)));
+        assert_eq!(format("out.pfm"), Ok(ImageFormat::Pfm));
+        assert!(format("out.jpg").is_err());
+        assert!(format("out").is_err());
+    }
+}
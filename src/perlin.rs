@@ -0,0 +1,162 @@
+use glam::Vec3;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+/**
+ * Perlin gradient noise. The same seed always produces the same noise.
+ */
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                Vec3::new(
+                    rng.gen_range(-1.0..=1.0),
+                    rng.gen_range(-1.0..=1.0),
+                    rng.gen_range(-1.0..=1.0),
+                )
+                .normalize_or_zero()
+            })
+            .collect();
+
+        return Self {
+            gradients,
+            perm_x: Perlin::generate_perm(&mut rng),
+            perm_y: Perlin::generate_perm(&mut rng),
+            perm_z: Perlin::generate_perm(&mut rng),
+        };
+    }
+
+    fn generate_perm(rng: &mut StdRng) -> Vec<usize> {
+        let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+        perm.shuffle(rng);
+        return perm;
+    }
+
+    // Noise value in roughly [-1, 1]
+    pub fn noise(&self, point: Vec3) -> f32 {
+        let floor = point.floor();
+        let fraction = point - floor;
+
+        let i = floor.x as i64;
+        let j = floor.y as i64;
+        let k = floor.z as i64;
+
+        let mut corners = [[[Vec3::ZERO; 2]; 2]; 2];
+
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize];
+
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+
+        return Perlin::interpolate(&corners, fraction);
+    }
+
+    // Trilinear interpolation of the gradients, smoothed with a Hermite cubic
+    fn interpolate(corners: &[[[Vec3; 2]; 2]; 2], fraction: Vec3) -> f32 {
+        let smooth = fraction * fraction * (Vec3::splat(3.0) - 2.0 * fraction);
+
+        let mut accumulated = 0.0;
+
+        for (i, plane) in corners.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, gradient) in row.iter().enumerate() {
+                    let weight = Vec3::new(i as f32, j as f32, k as f32);
+                    let offset = fraction - weight;
+
+                    let blend = (weight * smooth + (Vec3::ONE - weight) * (Vec3::ONE - smooth))
+                        .to_array()
+                        .iter()
+                        .product::<f32>();
+
+                    accumulated += blend * gradient.dot(offset);
+                }
+            }
+        }
+
+        return accumulated;
+    }
+
+    // Fractal Brownian motion: octaves of noise, each twice the frequency and half the amplitude
+    pub fn fbm(&self, point: Vec3, octaves: u32) -> f32 {
+        let mut accumulated = 0.0;
+        let mut point = point;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            accumulated += weight * self.noise(point);
+            weight *= 0.5;
+            point *= 2.0;
+        }
+
+        return accumulated;
+    }
+
+    // Like fbm, but summing the absolute noise, which gives creases where the noise crosses zero
+    pub fn turbulence(&self, point: Vec3, octaves: u32) -> f32 {
+        let mut accumulated = 0.0;
+        let mut point = point;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            accumulated += weight * self.noise(point).abs();
+            weight *= 0.5;
+            point *= 2.0;
+        }
+
+        return accumulated;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Points off the lattice, where the noise isn't zero
+    fn points() -> Vec<Vec3> {
+        return (0..100)
+            .map(|i| Vec3::new(i as f32 * 0.37, i as f32 * 0.61 - 5.0, i as f32 * -0.23))
+            .collect();
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_noise() {
+        let (a, b) = (Perlin::new(7), Perlin::new(7));
+
+        for point in points() {
+            assert_eq!(a.noise(point), b.noise(point));
+            assert_eq!(a.fbm(point, 5), b.fbm(point, 5));
+            assert_eq!(a.turbulence(point, 5), b.turbulence(point, 5));
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_noise() {
+        let (a, b) = (Perlin::new(7), Perlin::new(8));
+
+        let differing = points()
+            .into_iter()
+            .filter(|point| a.noise(*point) != b.noise(*point))
+            .count();
+
+        assert!(differing > 90);
+    }
+}
@@ -0,0 +1,341 @@
+use std::f32::consts::{PI, TAU};
+
+use glam::Vec3;
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{area_pdf, HitRecord, Hittable},
+    material::Material,
+    random,
+    ray::Ray,
+};
+
+// Planar shapes have no thickness, so their bounding boxes are padded to keep them from being flat
+const BOUNDING_BOX_PADDING: f32 = 0.0001;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlanarShape {
+    // origin + a * u + b * v for a and b in [0, 1]
+    Parallelogram,
+    // The half of the parallelogram where a + b <= 1, with corners origin, origin + u and origin + v
+    Triangle,
+    // The ellipse inscribed in the parallelogram spanned by -u..u and -v..v around the origin
+    Disk,
+}
+
+/**
+ * Flat shape spanned by an origin and two edge vectors, in any orientation.
+ * The normal is u × v, and u and v map to the texture coordinates.
+ */
+pub struct Planar {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    shape: PlanarShape,
+    normal: Vec3,
+    // The plane is normal · p = d
+    d: f32,
+    // Turns a point on the plane into its (a, b) coordinates along u and v
+    w: Vec3,
+    material: Box<dyn Material>,
+}
+
+impl Planar {
+    pub fn new(
+        origin: Vec3,
+        u: Vec3,
+        v: Vec3,
+        shape: PlanarShape,
+        material: Box<dyn Material>,
+    ) -> Self {
+        let n = u.cross(v);
+        let normal = n.normalize();
+
+        return Self {
+            origin,
+            u,
+            v,
+            shape,
+            normal,
+            d: normal.dot(origin),
+            w: n / n.dot(n),
+            material,
+        };
+    }
+
+    pub fn quad(origin: Vec3, u: Vec3, v: Vec3, material: Box<dyn Material>) -> Self {
+        return Planar::new(origin, u, v, PlanarShape::Parallelogram, material);
+    }
+
+    pub fn triangle(origin: Vec3, u: Vec3, v: Vec3, material: Box<dyn Material>) -> Self {
+        return Planar::new(origin, u, v, PlanarShape::Triangle, material);
+    }
+
+    pub fn disk(center: Vec3, normal: Vec3, radius: f32, material: Box<dyn Material>) -> Self {
+        let normal = normal.normalize();
+
+        // Any two perpendicular axes in the plane will do, as long as u × v points along the normal
+        let helper = if normal.x.abs() > 0.9 {
+            Vec3::Y
+        } else {
+            Vec3::X
+        };
+        let u = normal.cross(helper).normalize();
+        let v = normal.cross(u);
+
+        return Planar::new(center, radius * u, radius * v, PlanarShape::Disk, material);
+    }
+
+    // Texture coordinates of the point at (a, b) along u and v, or None if it is outside the shape
+    fn interior(&self, a: f32, b: f32) -> Option<(f32, f32)> {
+        return match self.shape {
+            PlanarShape::Parallelogram => {
+                ((0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b)).then_some((a, b))
+            }
+            PlanarShape::Triangle => (a >= 0.0 && b >= 0.0 && a + b <= 1.0).then_some((a, b)),
+            PlanarShape::Disk => {
+                (a * a + b * b <= 1.0).then_some((0.5 * (a + 1.0), 0.5 * (b + 1.0)))
+            }
+        };
+    }
+
+    fn area(&self) -> f32 {
+        let parallelogram = self.u.cross(self.v).length();
+
+        return match self.shape {
+            PlanarShape::Parallelogram => parallelogram,
+            PlanarShape::Triangle => 0.5 * parallelogram,
+            PlanarShape::Disk => PI * parallelogram,
+        };
+    }
+
+    // The (a, b) coordinates along u and v of a point spread uniformly over the shape
+    fn random_coordinates(&self) -> (f32, f32) {
+        let mut rng = random::rng();
+        let (r1, r2): (f32, f32) = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+
+        let (a, b) = match self.shape {
+            PlanarShape::Parallelogram => (r1, r2),
+            // Points in the far half of the parallelogram are folded back into the triangle
+            PlanarShape::Triangle if r1 + r2 > 1.0 => (1.0 - r1, 1.0 - r2),
+            PlanarShape::Triangle => (r1, r2),
+            PlanarShape::Disk => {
+                let radius = r1.sqrt();
+                let angle = TAU * r2;
+                (radius * angle.cos(), radius * angle.sin())
+            }
+        };
+
+        return (a, b);
+    }
+
+    fn corners(&self) -> Vec<Vec3> {
+        let o = self.origin;
+
+        return match self.shape {
+            PlanarShape::Parallelogram => vec![o, o + self.u, o + self.v, o + self.u + self.v],
+            PlanarShape::Triangle => vec![o, o + self.u, o + self.v],
+            PlanarShape::Disk => vec![
+                o - self.u - self.v,
+                o + self.u - self.v,
+                o - self.u + self.v,
+                o + self.u + self.v,
+            ],
+        };
+    }
+}
+
+impl Hittable for Planar {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(ray.direction);
+
+        // The ray runs parallel to the plane
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(ray.origin)) / denominator;
+
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let planar_point = point - self.origin;
+
+        let a = self.w.dot(planar_point.cross(self.v));
+        let b = self.w.dot(self.u.cross(planar_point));
+
+        let (u, v) = self.interior(a, b)?;
+
+        return Some(HitRecord::from_ray(
+            ray,
+            point,
+            self.normal,
+            t,
+            u,
+            v,
+            self.material.as_ref(),
+        ));
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = self
+            .corners()
+            .into_iter()
+            .fold(Aabb::empty(), |bbox, corner| bbox.grow(corner));
+
+        return Some(bbox.padded(BOUNDING_BOX_PADDING));
+    }
+
+    fn is_light(&self) -> bool {
+        return self.material.is_emissive();
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return match self.hit(&Ray::new(origin, direction, time), 0.001, f32::INFINITY) {
+            Some(rec) => area_pdf(direction, rec.t, self.normal, self.area()),
+            None => 0.0,
+        };
+    }
+
+    fn random_toward(&self, origin: Vec3, _time: f32) -> Vec3 {
+        let (a, b) = self.random_coordinates();
+        return self.origin + a * self.u + b * self.v - origin;
+    }
+
+    fn sample_surface(&self, _time: f32) -> Option<(HitRecord<'_>, f32)> {
+        if !self.is_light() {
+            return None;
+        }
+
+        let (a, b) = self.random_coordinates();
+        let (u, v) = self.interior(a, b).unwrap_or((a, b));
+
+        let rec = HitRecord::on_surface(
+            self.origin + a * self.u + b * self.v,
+            self.normal,
+            u,
+            v,
+            self.material.as_ref(),
+        );
+
+        return Some((rec, 1.0 / self.area()));
+    }
+
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        if !self.is_light() {
+            return 0.0;
+        }
+
+        return match self.hit(&Ray::new(origin, direction, time), 0.001, f32::INFINITY) {
+            Some(_) => 1.0 / self.area(),
+            None => 0.0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+
+    fn light() -> Box<dyn Material> {
+        return Box::new(DiffuseLight::from_color(Vec3::ONE));
+    }
+
+    // Straight down from above the point, onto shapes lying in the plane y = 0
+    fn hit_down(shape: &Planar, x: f32, z: f32) -> Option<HitRecord<'_>> {
+        let ray = Ray::new(Vec3::new(x, 2.0, z), Vec3::NEG_Y, 0.0);
+        return shape.hit(&ray, 0.001, f32::INFINITY);
+    }
+
+    fn assert_uv(rec: &HitRecord, u: f32, v: f32) {
+        assert!(
+            (rec.u - u).abs() < 1e-5 && (rec.v - v).abs() < 1e-5,
+            "({}, {}) instead of ({}, {})",
+            rec.u,
+            rec.v,
+            u,
+            v
+        );
+    }
+
+    #[test]
+    fn quads_map_their_edges_to_uvs() {
+        // A slanted parallelogram, with u × v pointing up
+        let quad = Planar::quad(
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Vec3::new(4.0, 0.0, 1.0),
+            light(),
+        );
+
+        let rec = hit_down(&quad, 1.0 + 2.0, 1.0 + 0.5 + 0.5).unwrap();
+        assert_uv(&rec, 0.25, 0.5);
+        assert!((rec.t - 2.0).abs() < 1e-5);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::Y);
+
+        let rec = hit_down(&quad, 5.0, 4.0).unwrap();
+        assert_uv(&rec, 1.0, 1.0);
+
+        // Just past the edges along v and u
+        assert!(hit_down(&quad, 0.9, 1.5).is_none());
+        assert!(hit_down(&quad, 3.0, 4.1).is_none());
+
+        // Parallel rays miss
+        let ray = Ray::new(Vec3::new(-1.0, 0.0, 2.0), Vec3::X, 0.0);
+        assert!(quad.hit(&ray, 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn triangles_are_half_of_the_quad() {
+        let triangle = Planar::triangle(Vec3::ZERO, Vec3::Z, Vec3::X, light());
+
+        assert_uv(&hit_down(&triangle, 0.25, 0.5).unwrap(), 0.5, 0.25);
+        assert_uv(&hit_down(&triangle, 0.5, 0.5).unwrap(), 0.5, 0.5);
+        assert!(hit_down(&triangle, 0.6, 0.5).is_none());
+        assert!(hit_down(&triangle, -0.1, 0.5).is_none());
+    }
+
+    #[test]
+    fn disks_are_round_with_the_center_at_the_middle_of_the_uvs() {
+        let disk = Planar::disk(Vec3::new(1.0, 0.0, 1.0), Vec3::Y, 2.0, light());
+
+        let rec = hit_down(&disk, 1.0, 1.0).unwrap();
+        assert_uv(&rec, 0.5, 0.5);
+        assert_eq!(rec.normal, Vec3::Y);
+
+        // On the rim in every direction, but not in the corners of the square around it
+        for angle in [0.0, 1.0, 2.5, 4.0, 5.5] {
+            let (x, z) = (1.0 + 1.99 * f32::cos(angle), 1.0 + 1.99 * f32::sin(angle));
+            assert!(hit_down(&disk, x, z).is_some());
+        }
+
+        assert!(hit_down(&disk, 2.5, 2.5).is_none());
+        assert!(hit_down(&disk, 3.01, 1.0).is_none());
+    }
+
+    #[test]
+    fn sampled_points_lie_on_the_shape() {
+        random::reseed(1);
+
+        let shapes = [
+            Planar::quad(Vec3::ZERO, Vec3::Z, 2.0 * Vec3::X, light()),
+            Planar::triangle(Vec3::ZERO, Vec3::Z, 2.0 * Vec3::X, light()),
+            Planar::disk(Vec3::ZERO, Vec3::Y, 1.5, light()),
+        ];
+
+        for (shape, area) in shapes.iter().zip([2.0, 1.0, PI * 1.5 * 1.5]) {
+            for _ in 0..1000 {
+                let (rec, pdf) = shape.sample_surface(0.0).unwrap();
+
+                assert!((pdf * area - 1.0).abs() < 1e-5);
+                assert!(rec.point.y.abs() < 1e-6);
+                assert!(hit_down(shape, rec.point.x, rec.point.z).is_some());
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+thread_local! {
+    static THREAD_RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/**
+ * Handle to the random number generator of the current thread.
+ * Unlike rand::thread_rng, it can be reseeded to make renders reproducible.
+ */
+#[derive(Clone, Copy)]
+pub struct Rng;
+
+pub fn rng() -> Rng {
+    return Rng;
+}
+
+// Reseed the generator of the current thread. Every thread has its own generator.
+pub fn reseed(seed: u64) {
+    THREAD_RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        return THREAD_RNG.with(|rng| rng.borrow_mut().next_u32());
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        return THREAD_RNG.with(|rng| rng.borrow_mut().next_u64());
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        THREAD_RNG.with(|rng| rng.borrow_mut().fill_bytes(dest));
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        return THREAD_RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest));
+    }
+}
@@ -1,65 +1,446 @@
+use std::sync::Arc;
+
 use glam::Vec3;
 
+use rand::Rng;
+
 use crate::{
-    hittable::{HitRecord, Hittable},
+    aabb::Aabb,
+    hittable::{area_pdf, HitRecord, Hittable},
+    hittable_list::HittableList,
     material::Material,
+    random,
     ray::Ray,
 };
 
-pub struct RectXY {
-    pub x0: f32,
-    pub x1: f32,
-    pub y0: f32,
-    pub y1: f32,
+// Rectangles have no thickness, so their bounding boxes are padded to keep them from being flat
+const BOUNDING_BOX_PADDING: f32 = 0.0001;
+
+/**
+ * Rectangle perpendicular to one of the axes, in the plane where that axis is k and facing along it.
+ * u runs along the first of the other two axes and v along the second, in x, y, z order.
+ */
+pub struct AxisRect {
+    // Indices of the axes u and v run along, and of the one the rectangle faces
+    u_axis: usize,
+    v_axis: usize,
+    normal_axis: usize,
+    pub u0: f32,
+    pub u1: f32,
+    pub v0: f32,
+    pub v1: f32,
     pub k: f32,
     pub material: Box<dyn Material>,
 }
 
-unsafe impl Send for RectXY {}
-unsafe impl Sync for RectXY {}
+impl AxisRect {
+    // `normal_axis` is 0, 1 or 2 for a rectangle facing along x, y or z
+    pub fn new(
+        normal_axis: usize,
+        (u0, u1): (f32, f32),
+        (v0, v1): (f32, f32),
+        k: f32,
+        material: Box<dyn Material>,
+    ) -> Self {
+        let (u_axis, v_axis) = match normal_axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
 
-impl RectXY {
-    pub fn new(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Box<dyn Material>) -> Self {
-        Self {
-            x0,
-            x1,
-            y0,
-            y1,
+        return Self {
+            u_axis,
+            v_axis,
+            normal_axis,
+            u0,
+            u1,
+            v0,
+            v1,
             k,
             material,
-        }
+        };
+    }
+
+    /**
+     * Rectangle in the plane z = k, facing +Z. u runs along x and v along y.
+     */
+    pub fn xy(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Box<dyn Material>) -> Self {
+        return AxisRect::new(2, (x0, x1), (y0, y1), k, material);
+    }
+
+    /**
+     * Rectangle in the plane y = k, facing +Y. u runs along x and v along z.
+     */
+    pub fn xz(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Box<dyn Material>) -> Self {
+        return AxisRect::new(1, (x0, x1), (z0, z1), k, material);
+    }
+
+    /**
+     * Rectangle in the plane x = k, facing +X. u runs along y and v along z.
+     */
+    pub fn yz(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Box<dyn Material>) -> Self {
+        return AxisRect::new(0, (y0, y1), (z0, z1), k, material);
+    }
+
+    // The point in the plane at the given coordinates along the u and v axes
+    fn point(&self, u: f32, v: f32) -> Vec3 {
+        let mut point = Vec3::ZERO;
+        point[self.u_axis] = u;
+        point[self.v_axis] = v;
+        point[self.normal_axis] = self.k;
+
+        return point;
+    }
+
+    fn normal(&self) -> Vec3 {
+        let mut normal = Vec3::ZERO;
+        normal[self.normal_axis] = 1.0;
+
+        return normal;
+    }
+
+    fn area(&self) -> f32 {
+        return (self.u1 - self.u0) * (self.v1 - self.v0);
     }
 }
 
-impl Hittable for RectXY {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let t = (self.k - ray.origin.z) / ray.direction.z;
+impl Hittable for AxisRect {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let t = (self.k - ray.origin[self.normal_axis]) / ray.direction[self.normal_axis];
 
-        if t < t_min || t > t_max {
+        // Also rejects NaN, for rays running inside the plane
+        if !(t_min..=t_max).contains(&t) {
             return None;
         }
 
-        let x = ray.origin.x + t * ray.direction.x;
-        let y = ray.origin.y + t * ray.direction.y;
+        let point = ray.at(t);
+        let (a, b) = (point[self.u_axis], point[self.v_axis]);
 
-        if x < self.x0 || x > self.x1 || y < self.y0 || y > self.y1 {
+        if a < self.u0 || a > self.u1 || b < self.v0 || b > self.v1 {
             return None;
         }
 
-        let outward_normal = Vec3::new(0.0, 0.0, 1.0);
-
-        let point = ray.at(t);
-        let u = (x - self.x0) / (self.x1 - self.x0);
-        let v = (y - self.y0) / (self.y1 - self.y0);
+        let u = (a - self.u0) / (self.u1 - self.u0);
+        let v = (b - self.v0) / (self.v1 - self.v0);
 
         return Some(HitRecord::from_ray(
             ray,
             point,
-            outward_normal,
+            self.normal(),
             t,
             u,
             v,
-            &self.material,
+            self.material.as_ref(),
         ));
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = Aabb::new(self.point(self.u0, self.v0), self.point(self.u1, self.v1));
+
+        return Some(bbox.padded(BOUNDING_BOX_PADDING));
+    }
+
+    fn is_light(&self) -> bool {
+        return self.material.is_emissive();
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return match self.hit(&Ray::new(origin, direction, time), 0.001, f32::INFINITY) {
+            Some(rec) => area_pdf(direction, rec.t, rec.normal, self.area()),
+            None => 0.0,
+        };
+    }
+
+    fn random_toward(&self, origin: Vec3, _time: f32) -> Vec3 {
+        let mut rng = random::rng();
+        let a = self.u0 + rng.gen_range(0.0..1.0) * (self.u1 - self.u0);
+        let b = self.v0 + rng.gen_range(0.0..1.0) * (self.v1 - self.v0);
+
+        return self.point(a, b) - origin;
+    }
+
+    fn sample_surface(&self, _time: f32) -> Option<(HitRecord<'_>, f32)> {
+        if !self.is_light() {
+            return None;
+        }
+
+        let mut rng = random::rng();
+        let u = rng.gen_range(0.0..1.0);
+        let v = rng.gen_range(0.0..1.0);
+
+        let point = self.point(
+            self.u0 + u * (self.u1 - self.u0),
+            self.v0 + v * (self.v1 - self.v0),
+        );
+        let rec = HitRecord::on_surface(point, self.normal(), u, v, self.material.as_ref());
+
+        return Some((rec, 1.0 / self.area()));
+    }
+
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        if !self.is_light() {
+            return 0.0;
+        }
+
+        return match self.hit(&Ray::new(origin, direction, time), 0.001, f32::INFINITY) {
+            Some(_) => 1.0 / self.area(),
+            None => 0.0,
+        };
+    }
+}
+
+/**
+ * Turns an object inside out, so its outward normal points the other way
+ */
+struct FlipFace {
+    object: Box<dyn Hittable>,
+}
+
+impl Hittable for FlipFace {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut rec = self.object.hit(ray, t_min, t_max)?;
+
+        // The normal already faces the ray, only the side it was hit from changes
+        rec.front_face = !rec.front_face;
+
+        return Some(rec);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return self.object.bounding_box();
+    }
+
+    fn is_light(&self) -> bool {
+        return self.object.is_light();
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return self.object.pdf_value(origin, direction, time);
+    }
+
+    fn random_toward(&self, origin: Vec3, time: f32) -> Vec3 {
+        return self.object.random_toward(origin, time);
+    }
+
+    fn sample_surface(&self, time: f32) -> Option<(HitRecord<'_>, f32)> {
+        let (mut rec, pdf) = self.object.sample_surface(time)?;
+        rec.normal = -rec.normal;
+
+        return Some((rec, pdf));
+    }
+
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return self.object.surface_pdf(origin, direction, time);
+    }
+}
+
+/**
+ * Axis-aligned box between two opposite corners, made of six rectangles facing outwards
+ */
+pub struct BoxShape {
+    min: Vec3,
+    max: Vec3,
+    sides: HittableList,
+}
+
+impl BoxShape {
+    pub fn new(corner_a: Vec3, corner_b: Vec3, material: Box<dyn Material>) -> Self {
+        let min = corner_a.min(corner_b);
+        let max = corner_a.max(corner_b);
+
+        // All sides share the one material
+        let material: Arc<dyn Material> = Arc::from(material);
+        let side_material = || -> Box<dyn Material> { Box::new(Arc::clone(&material)) };
+
+        let mut sides = HittableList::new();
+
+        sides.add(Box::new(AxisRect::xy(
+            min.x,
+            max.x,
+            min.y,
+            max.y,
+            max.z,
+            side_material(),
+        )));
+        sides.add(Box::new(FlipFace {
+            object: Box::new(AxisRect::xy(
+                min.x,
+                max.x,
+                min.y,
+                max.y,
+                min.z,
+                side_material(),
+            )),
+        }));
+
+        sides.add(Box::new(AxisRect::xz(
+            min.x,
+            max.x,
+            min.z,
+            max.z,
+            max.y,
+            side_material(),
+        )));
+        sides.add(Box::new(FlipFace {
+            object: Box::new(AxisRect::xz(
+                min.x,
+                max.x,
+                min.z,
+                max.z,
+                min.y,
+                side_material(),
+            )),
+        }));
+
+        sides.add(Box::new(AxisRect::yz(
+            min.y,
+            max.y,
+            min.z,
+            max.z,
+            max.x,
+            side_material(),
+        )));
+        sides.add(Box::new(FlipFace {
+            object: Box::new(AxisRect::yz(
+                min.y,
+                max.y,
+                min.z,
+                max.z,
+                min.x,
+                side_material(),
+            )),
+        }));
+
+        return Self { min, max, sides };
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        return self.sides.hit(ray, t_min, t_max);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return Some(Aabb::new(self.min, self.max).padded(BOUNDING_BOX_PADDING));
+    }
+
+    fn is_light(&self) -> bool {
+        return self.sides.is_light();
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return self.sides.pdf_value(origin, direction, time);
+    }
+
+    fn random_toward(&self, origin: Vec3, time: f32) -> Vec3 {
+        return self.sides.random_toward(origin, time);
+    }
+
+    fn sample_surface(&self, time: f32) -> Option<(HitRecord<'_>, f32)> {
+        return self.sides.sample_surface(time);
+    }
+
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return self.sides.surface_pdf(origin, direction, time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+
+    fn light() -> Box<dyn Material> {
+        return Box::new(DiffuseLight::from_color(Vec3::ONE));
+    }
+
+    #[test]
+    fn sampled_points_lie_on_the_rect() {
+        random::reseed(1);
+        let rect = AxisRect::xz(-1.0, 2.0, 3.0, 4.0, 5.0, light());
+        let origin = Vec3::new(0.5, 0.0, 0.0);
+
+        for _ in 0..1000 {
+            let direction = rect.random_toward(origin, 0.0);
+            let point = origin + direction;
+
+            assert!((-1.0..=2.0).contains(&point.x) && (3.0..=4.0).contains(&point.z));
+            assert!((point.y - 5.0).abs() < 1e-5);
+            assert!(rect.pdf_value(origin, direction, 0.0) > 0.0);
+        }
+    }
+
+    #[test]
+    fn reversed_bounds_are_sampled_without_panicking() {
+        random::reseed(2);
+        let rect = AxisRect::yz(1.0, -1.0, 2.0, 2.0, 0.0, light());
+
+        for _ in 0..100 {
+            let point = rect.random_toward(Vec3::ZERO, 0.0);
+
+            assert!((-1.0..=1.0).contains(&point.y));
+            assert_eq!(point.z, 2.0);
+        }
+    }
+
+    #[test]
+    fn boxes_face_outwards_on_every_side() {
+        let shape = BoxShape::new(Vec3::new(2.0, 4.0, 8.0), Vec3::ZERO, light());
+        let center = Vec3::new(1.0, 2.0, 4.0);
+
+        for outward in [Vec3::X, Vec3::Y, Vec3::Z, -Vec3::X, -Vec3::Y, -Vec3::Z] {
+            let face_center = center + outward * Vec3::new(1.0, 2.0, 4.0);
+
+            // From outside the front of the face is hit, and from inside the back of it
+            let ray = Ray::new(face_center + outward, -outward, 0.0);
+            let rec = shape.hit(&ray, 0.001, f32::INFINITY).unwrap();
+            assert!(rec.front_face);
+            assert_eq!(rec.normal, outward);
+            assert!((rec.t - 1.0).abs() < 1e-5);
+
+            let ray = Ray::new(center, outward, 0.0);
+            let rec = shape.hit(&ray, 0.001, f32::INFINITY).unwrap();
+            assert!(!rec.front_face);
+            assert_eq!(rec.normal, -outward);
+            assert!(rec.point.abs_diff_eq(face_center, 1e-5));
+        }
+    }
+
+    #[test]
+    fn box_sides_have_uvs_along_their_axes() {
+        let shape = BoxShape::new(Vec3::ZERO, Vec3::new(2.0, 4.0, 8.0), light());
+
+        // Points a quarter and three quarters of the way along the axes u and v run along
+        for (origin, direction, (u, v)) in [
+            (Vec3::new(0.5, 3.0, 9.0), -Vec3::Z, (0.25, 0.75)),
+            (Vec3::new(1.5, -1.0, 2.0), Vec3::Y, (0.75, 0.25)),
+            (Vec3::new(-1.0, 1.0, 6.0), Vec3::X, (0.25, 0.75)),
+        ] {
+            let rec = shape
+                .hit(&Ray::new(origin, direction, 0.0), 0.001, f32::INFINITY)
+                .unwrap();
+
+            assert!((rec.u - u).abs() < 1e-5 && (rec.v - v).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn points_sampled_on_boxes_face_outwards() {
+        random::reseed(3);
+        let shape = BoxShape::new(Vec3::ZERO, Vec3::new(2.0, 4.0, 8.0), light());
+        let center = Vec3::new(1.0, 2.0, 4.0);
+
+        for _ in 0..1000 {
+            let (rec, pdf) = shape.sample_surface(0.0).unwrap();
+
+            // On one of the sides, with the normal pointing away from the middle
+            let half_size = Vec3::new(1.0, 2.0, 4.0);
+            assert_eq!(rec.normal.abs().max_element(), 1.0);
+            assert!(
+                ((rec.point - center).dot(rec.normal) - half_size.dot(rec.normal.abs())).abs()
+                    < 1e-5
+            );
+            assert!(pdf > 0.0);
+        }
+    }
 }
@@ -1,31 +1,147 @@
-use std::f32::consts::TAU;
+use std::error::Error;
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::path::Path;
+use std::sync::Arc;
 
-use glam::Vec3;
+use glam::{Affine3A, Vec3};
 use rand::Rng;
 
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
 use crate::camera::Camera;
+use crate::environment::Background;
+use crate::hittable::Hittable;
 use crate::hittable_list::HittableList;
+use crate::light::Light;
 use crate::material::Dielectric;
 use crate::material::DiffuseLight;
+use crate::material::Isotropic;
 use crate::material::Lambertian;
+use crate::material::Material;
 use crate::material::Metal;
-use crate::rect::RectXY;
-use crate::sphere::Sphere;
+use crate::medium::ConstantMedium;
+use crate::obj::{self, ObjError};
+use crate::planar::Planar;
+use crate::random;
+use crate::rect::{AxisRect, BoxShape};
+use crate::scene_file;
+use crate::sky::Sky;
+use crate::sphere::{MovingSphere, Sphere};
 use crate::texture::CheckerTexture;
+use crate::texture::NoiseTexture;
 use crate::texture::SolidColor;
+use crate::transform::Transform;
+use crate::triangle::TriangleMesh;
 use crate::vec3::Vec3Extension;
-use crate::ASPECT_RATIO;
+use crate::volume::{self, HeterogeneousMedium, SparseGrid, Tracking};
 
 pub struct Scene {
-    pub world: HittableList,
+    pub world: Bvh,
     pub camera: Camera,
-    pub background: Vec3,
+    pub background: Background,
+    // Point, spot and directional lights, which aren't part of the world as they can't be hit
+    pub lights: Vec<Box<dyn Light>>,
 }
 
-pub fn random_scene() -> Scene {
+impl Scene {
+    // Whether there is anything to sample with random_toward_light
+    pub fn has_lights(&self) -> bool {
+        return self.world.is_light() || self.background.is_light();
+    }
+
+    /**
+     * Density of random_toward_light picking `direction` from `origin`, per unit of solid angle.
+     * When the world has lights and the background is sampled too, each is picked half of the time.
+     */
+    pub fn light_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return match (self.world.is_light(), self.background.is_light()) {
+            (true, true) => {
+                0.5 * (self.world.pdf_value(origin, direction, time)
+                    + self.background.pdf_value(direction))
+            }
+            (true, false) => self.world.pdf_value(origin, direction, time),
+            (false, true) => self.background.pdf_value(direction),
+            (false, false) => 0.0,
+        };
+    }
+
+    // A direction from `origin` toward a random light, which doesn't need to be normalized
+    pub fn random_toward_light(&self, origin: Vec3, time: f32) -> Vec3 {
+        let sample_background = match (self.world.is_light(), self.background.is_light()) {
+            (true, true) => random::rng().gen_bool(0.5),
+            (_, background) => background,
+        };
+
+        return match sample_background {
+            true => self.background.random_direction(),
+            false => self.world.random_toward(origin, time),
+        };
+    }
+}
+
+pub const SCENE_NAMES: [&str; 13] = [
+    "random_scene",
+    "sunny_scene",
+    "random_spheres",
+    "simple_light",
+    "glowing_sphere",
+    "triangle_meshes",
+    "perlin_spheres",
+    "cornell_box",
+    "cornell_smoke",
+    "planar_shapes",
+    "forest",
+    "motion_blur",
+    "cloud",
+];
+
+/**
+ * Load a built-in scene by name, or a scene from a file.
+ * Files ending in .obj are loaded as a single model, anything else as a scene description.
+ */
+pub fn load(name_or_path: &str, aspect_ratio: f32) -> Result<Scene, Box<dyn Error>> {
+    match name_or_path {
+        "random_scene" => return Ok(random_scene(aspect_ratio)),
+        "sunny_scene" => return Ok(sunny_scene(aspect_ratio)),
+        "random_spheres" => return Ok(random_spheres(aspect_ratio)),
+        "simple_light" => return Ok(simple_light(aspect_ratio)),
+        "glowing_sphere" => return Ok(glowing_sphere(aspect_ratio)),
+        "triangle_meshes" => return Ok(triangle_meshes(aspect_ratio)),
+        "perlin_spheres" => return Ok(perlin_spheres(aspect_ratio)),
+        "cornell_box" => return Ok(cornell_box(aspect_ratio)),
+        "cornell_smoke" => return Ok(cornell_smoke(aspect_ratio)),
+        "planar_shapes" => return Ok(planar_shapes(aspect_ratio)),
+        "forest" => return Ok(forest(aspect_ratio)),
+        "motion_blur" => return Ok(motion_blur(aspect_ratio)),
+        "cloud" => return Ok(cloud(aspect_ratio)),
+        _ => {}
+    }
+
+    let path = Path::new(name_or_path);
+
+    if !path.exists() {
+        return Err(format!(
+            "'{}' is neither a file nor one of the built-in scenes ({})",
+            name_or_path,
+            SCENE_NAMES.join(", ")
+        )
+        .into());
+    }
+
+    let is_obj = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("obj"));
+
+    return match is_obj {
+        true => Ok(obj_model(path, aspect_ratio)?),
+        false => Ok(scene_file::load_scene(path, aspect_ratio)?),
+    };
+}
+
+pub fn random_scene(aspect_ratio: f32) -> Scene {
     let mut world = HittableList::new();
 
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
 
     let checker_texture = CheckerTexture::new(
         Box::new(SolidColor::new(Vec3::new(0.2, 0.3, 0.1))),
@@ -50,7 +166,7 @@ pub fn random_scene() -> Scene {
             let center = Vec3::new(x, y, z);
 
             if (center - center_clear_dist).length() > 0.9 {
-                let mut rng = rand::thread_rng();
+                let mut rng = random::rng();
 
                 let probability = rng.gen_range(0.0..=1.0);
 
@@ -113,19 +229,28 @@ pub fn random_scene() -> Scene {
         lookat,
         vup,
         fov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         dist_to_focus,
     );
 
     return Scene {
-        world,
+        world: Bvh::new(world),
         camera,
-        background: Vec3::new(0.7, 0.8, 1.0),
+        background: Background::Color(Vec3::new(0.7, 0.8, 1.0)),
+        lights: Vec::new(),
+    };
+}
+
+// The spheres of random_scene outdoors, in the late afternoon sun
+pub fn sunny_scene(aspect_ratio: f32) -> Scene {
+    return Scene {
+        background: Background::Sky(Sky::new(20.0, 120.0, 3.0)),
+        ..random_scene(aspect_ratio)
     };
 }
 
-pub fn random_spheres() -> Scene {
+pub fn random_spheres(aspect_ratio: f32) -> Scene {
     let mut world = HittableList::new();
 
     world.add(Box::new(Sphere::new(
@@ -165,7 +290,7 @@ pub fn random_spheres() -> Scene {
 
     let center_clear_dist = Vec3::new(4.0, 0.2, 0.0);
 
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
 
     for _i in 0..1500 {
         let u = rng.gen_range(0.0..=1.0f32);
@@ -180,7 +305,7 @@ pub fn random_spheres() -> Scene {
         let center = Vec3::new(x, y, z);
 
         if (center - center_clear_dist).length() > 0.9 {
-            let mut rng = rand::thread_rng();
+            let mut rng = random::rng();
 
             let probability = rng.gen_range(0.0..=1.0);
 
@@ -221,19 +346,20 @@ pub fn random_spheres() -> Scene {
         lookat,
         vup,
         fov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         dist_to_focus,
     );
 
     return Scene {
-        world,
+        world: Bvh::new(world),
         camera,
-        background: Vec3::new(0.7, 0.8, 1.0),
+        background: Background::Color(Vec3::new(0.7, 0.8, 1.0)),
+        lights: Vec::new(),
     };
 }
 
-pub fn simple_light() -> Scene {
+pub fn simple_light(aspect_ratio: f32) -> Scene {
     let mut world = HittableList::new();
 
     world.add(Box::new(Sphere::new(
@@ -253,7 +379,7 @@ pub fn simple_light() -> Scene {
     )));
 
     let rect_light_1 = DiffuseLight::from_color(Vec3::new(4.0, 4.0, 4.0));
-    world.add(Box::new(RectXY::new(
+    world.add(Box::new(AxisRect::xy(
         -2.0,
         2.0,
         1.0,
@@ -263,7 +389,7 @@ pub fn simple_light() -> Scene {
     )));
 
     let rect_light_2 = DiffuseLight::from_color(Vec3::new(4.0, 4.0, 4.0));
-    world.add(Box::new(RectXY::new(
+    world.add(Box::new(AxisRect::xy(
         -2.0,
         2.0,
         1.0,
@@ -287,19 +413,20 @@ pub fn simple_light() -> Scene {
         lookat,
         vup,
         fov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         dist_to_focus,
     );
 
     return Scene {
-        world,
+        world: Bvh::new(world),
         camera,
-        background: Vec3::new(0.0, 0.0, 0.0),
+        background: Background::Color(Vec3::new(0.0, 0.0, 0.0)),
+        lights: Vec::new(),
     };
 }
 
-pub fn glowing_sphere() -> Scene {
+pub fn glowing_sphere(aspect_ratio: f32) -> Scene {
     let mut world = HittableList::new();
 
     let checker_texture = CheckerTexture::new(
@@ -358,15 +485,683 @@ pub fn glowing_sphere() -> Scene {
         lookat,
         vup,
         fov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         dist_to_focus,
     );
 
     return Scene {
-        world,
+        world: Bvh::new(world),
         camera,
         // background: Vec3::new(0.7, 0.8, 1.0),
-        background: Vec3::ZERO,
+        background: Background::Color(Vec3::ZERO),
+        lights: Vec::new(),
+    };
+}
+
+pub fn triangle_meshes(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Box::new(Lambertian::new(Box::new(CheckerTexture::new(
+            Box::new(SolidColor::new(Vec3::new(0.2, 0.3, 0.1))),
+            Box::new(SolidColor::new(Vec3::new(0.9, 0.9, 0.9))),
+        )))),
+    )));
+
+    // Octahedron
+    let directions = vec![
+        Vec3::X,
+        Vec3::NEG_X,
+        Vec3::Y,
+        Vec3::NEG_Y,
+        Vec3::Z,
+        Vec3::NEG_Z,
+    ];
+    let indices = vec![
+        [0, 2, 4],
+        [4, 2, 1],
+        [1, 2, 5],
+        [5, 2, 0],
+        [4, 3, 0],
+        [1, 3, 4],
+        [5, 3, 1],
+        [0, 3, 5],
+    ];
+
+    // Flat shaded
+    let flat_mesh = TriangleMesh::new(
+        directions
+            .iter()
+            .map(|d| *d + Vec3::new(0.0, 1.0, -1.5))
+            .collect(),
+        None,
+        None,
+        indices.clone(),
+        Box::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0)),
+    );
+
+    // Smooth shaded, using the vertex directions as normals
+    let smooth_mesh = TriangleMesh::new(
+        directions
+            .iter()
+            .map(|d| *d + Vec3::new(0.0, 1.0, 1.5))
+            .collect(),
+        Some(directions.clone()),
+        None,
+        indices,
+        Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::new(
+            0.4, 0.2, 0.1,
+        ))))),
+    );
+
+    for triangle in flat_mesh.into_triangles() {
+        world.add(Box::new(triangle));
+    }
+
+    for triangle in smooth_mesh.into_triangles() {
+        world.add(Box::new(triangle));
+    }
+
+    // Camera
+    let lookfrom = Vec3::new(10.0, 3.0, 2.0);
+    let lookat = Vec3::new(0.0, 1.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let fov = 30.0;
+    let aperture = 0.05;
+    let dist_to_focus = (lookfrom - lookat).length();
+
+    // Define the Camera
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        vup,
+        fov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+    );
+
+    return Scene {
+        world: Bvh::new(world),
+        camera,
+        background: Background::Color(Vec3::new(0.7, 0.8, 1.0)),
+        lights: Vec::new(),
+    };
+}
+
+pub fn perlin_spheres(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Box::new(Lambertian::new(Box::new(NoiseTexture::marble(1, 4.0)))),
+    )));
+
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 2.0, 0.0),
+        2.0,
+        Box::new(Lambertian::new(Box::new(NoiseTexture::wood(2, 1.5)))),
+    )));
+
+    // Camera
+    let lookfrom = Vec3::new(13.0, 2.0, 3.0);
+    let lookat = Vec3::new(0.0, 1.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let fov = 20.0;
+    let aperture = 0.0;
+    let dist_to_focus = 10.0;
+
+    // Define the Camera
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        vup,
+        fov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+    );
+
+    return Scene {
+        world: Bvh::new(world),
+        camera,
+        background: Background::Color(Vec3::new(0.7, 0.8, 1.0)),
+        lights: Vec::new(),
+    };
+}
+
+pub fn cornell_box(aspect_ratio: f32) -> Scene {
+    let mut world = cornell_room();
+
+    let (tall_box, short_box) = cornell_boxes();
+    world.add(tall_box);
+    world.add(short_box);
+
+    return Scene {
+        world: Bvh::new(world),
+        camera: cornell_camera(aspect_ratio),
+        background: Background::Color(Vec3::new(0.0, 0.0, 0.0)),
+        lights: Vec::new(),
+    };
+}
+
+/**
+ * The Cornell box, with its boxes filled with dark and light smoke
+ */
+pub fn cornell_smoke(aspect_ratio: f32) -> Scene {
+    let mut world = cornell_room();
+
+    let (tall_box, short_box) = cornell_boxes();
+
+    world.add(Box::new(ConstantMedium::new(
+        tall_box,
+        0.01,
+        Box::new(Isotropic::new(Box::new(SolidColor::new(Vec3::ZERO)))),
+    )));
+    world.add(Box::new(ConstantMedium::new(
+        short_box,
+        0.01,
+        Box::new(Isotropic::new(Box::new(SolidColor::new(Vec3::ONE)))),
+    )));
+
+    return Scene {
+        world: Bvh::new(world),
+        camera: cornell_camera(aspect_ratio),
+        background: Background::Color(Vec3::new(0.0, 0.0, 0.0)),
+        lights: Vec::new(),
+    };
+}
+
+fn cornell_white() -> Box<Lambertian> {
+    return Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::new(
+        0.73, 0.73, 0.73,
+    )))));
+}
+
+// The walls and the ceiling light of the Cornell box
+fn cornell_room() -> HittableList {
+    let mut world = HittableList::new();
+
+    let red = Lambertian::new(Box::new(SolidColor::new(Vec3::new(0.65, 0.05, 0.05))));
+    let green = Lambertian::new(Box::new(SolidColor::new(Vec3::new(0.12, 0.45, 0.15))));
+
+    // Walls
+    world.add(Box::new(AxisRect::yz(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        Box::new(green),
+    )));
+    world.add(Box::new(AxisRect::yz(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        0.0,
+        Box::new(red),
+    )));
+    world.add(Box::new(AxisRect::xz(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        0.0,
+        cornell_white(),
+    )));
+    world.add(Box::new(AxisRect::xz(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        cornell_white(),
+    )));
+    world.add(Box::new(AxisRect::xy(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        cornell_white(),
+    )));
+
+    // Ceiling light
+    world.add(Box::new(AxisRect::xz(
+        213.0,
+        343.0,
+        227.0,
+        332.0,
+        554.0,
+        Box::new(DiffuseLight::from_color(Vec3::new(15.0, 15.0, 15.0))),
+    )));
+
+    return world;
+}
+
+// The tall and the short box standing in the Cornell box
+fn cornell_boxes() -> (Box<dyn Hittable>, Box<dyn Hittable>) {
+    let tall_box = BoxShape::new(Vec3::ZERO, Vec3::new(165.0, 330.0, 165.0), cornell_white());
+    let tall_box = Transform::new(
+        Box::new(tall_box),
+        Affine3A::from_translation(Vec3::new(265.0, 0.0, 295.0))
+            * Affine3A::from_rotation_y(15.0f32.to_radians()),
+    );
+
+    let short_box = BoxShape::new(Vec3::ZERO, Vec3::new(165.0, 165.0, 165.0), cornell_white());
+    let short_box = Transform::new(
+        Box::new(short_box),
+        Affine3A::from_translation(Vec3::new(130.0, 0.0, 65.0))
+            * Affine3A::from_rotation_y(-18.0f32.to_radians()),
+    );
+
+    return (Box::new(tall_box), Box::new(short_box));
+}
+
+fn cornell_camera(aspect_ratio: f32) -> Camera {
+    let lookfrom = Vec3::new(278.0, 278.0, -800.0);
+    let lookat = Vec3::new(278.0, 278.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let fov = 40.0;
+    let aperture = 0.0;
+    let dist_to_focus = 10.0;
+
+    return Camera::new(
+        lookfrom,
+        lookat,
+        vup,
+        fov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+    );
+}
+
+pub fn planar_shapes(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+
+    let color = |r, g, b| {
+        Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::new(
+            r, g, b,
+        )))))
+    };
+
+    // Ground
+    world.add(Box::new(Planar::quad(
+        Vec3::new(-10.0, -3.0, -10.0),
+        Vec3::new(20.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 20.0),
+        color(0.73, 0.73, 0.73),
+    )));
+
+    // A tilted parallelogram, a triangle and a disk, none of them aligned with an axis
+    world.add(Box::new(Planar::quad(
+        Vec3::new(-3.5, -2.0, 1.0),
+        Vec3::new(1.5, 0.0, -1.5),
+        Vec3::new(0.5, 3.5, 0.0),
+        color(1.0, 0.2, 0.2),
+    )));
+    world.add(Box::new(Planar::triangle(
+        Vec3::new(-1.0, -2.0, 0.0),
+        Vec3::new(2.5, 0.0, -0.5),
+        Vec3::new(1.0, 3.5, -1.0),
+        color(0.2, 1.0, 0.2),
+    )));
+    world.add(Box::new(Planar::disk(
+        Vec3::new(3.0, 0.0, 0.0),
+        Vec3::new(-1.0, 0.3, 1.0),
+        1.6,
+        Box::new(Metal::new(Vec3::new(0.8, 0.8, 0.9), 0.05)),
+    )));
+
+    // Lights facing down at an angle
+    world.add(Box::new(Planar::quad(
+        Vec3::new(-3.0, 4.0, 3.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.6, -1.8),
+        Box::new(DiffuseLight::from_color(Vec3::new(6.0, 5.0, 4.0))),
+    )));
+    world.add(Box::new(Planar::disk(
+        Vec3::new(3.0, 4.0, 3.0),
+        Vec3::new(-0.5, -1.0, -0.5),
+        0.8,
+        Box::new(DiffuseLight::from_color(Vec3::new(4.0, 5.0, 8.0))),
+    )));
+
+    // Camera
+    let lookfrom = Vec3::new(0.0, 1.0, 9.0);
+    let lookat = Vec3::new(0.0, 0.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let fov = 60.0;
+    let aperture = 0.0;
+    let dist_to_focus = 10.0;
+
+    // Define the Camera
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        vup,
+        fov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+    );
+
+    return Scene {
+        world: Bvh::new(world),
+        camera,
+        background: Background::Color(Vec3::new(0.05, 0.05, 0.08)),
+        lights: Vec::new(),
     };
 }
+
+/**
+ * Ten thousand trees, all instances of the same two shapes
+ */
+pub fn forest(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Planar::quad(
+        Vec3::new(-200.0, 0.0, -200.0),
+        Vec3::new(0.0, 0.0, 400.0),
+        Vec3::new(400.0, 0.0, 0.0),
+        Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::new(
+            0.35, 0.3, 0.2,
+        ))))),
+    )));
+
+    let trunk: Arc<dyn Hittable> = Arc::new(BoxShape::new(
+        Vec3::new(-0.15, 0.0, -0.15),
+        Vec3::new(0.15, 1.0, 0.15),
+        Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::new(
+            0.3, 0.2, 0.1,
+        ))))),
+    ));
+
+    // A cone, with its base at y = 0.8 and its tip at y = 3
+    let segments = 12;
+    let mut positions = vec![Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, 0.8, 0.0)];
+    let mut indices = Vec::new();
+
+    for i in 0..segments {
+        let angle = TAU * i as f32 / segments as f32;
+        positions.push(Vec3::new(angle.cos(), 0.8, angle.sin()));
+
+        let current = 2 + i;
+        let next = 2 + (i + 1) % segments;
+        indices.push([0, next, current]);
+        indices.push([1, current, next]);
+    }
+
+    let crown_mesh = TriangleMesh::new(
+        positions,
+        None,
+        None,
+        indices,
+        Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::new(
+            0.1, 0.4, 0.1,
+        ))))),
+    );
+
+    let mut crown_triangles = HittableList::new();
+    for triangle in crown_mesh.into_triangles() {
+        crown_triangles.add(Box::new(triangle));
+    }
+    let crown: Arc<dyn Hittable> = Arc::new(Bvh::new(crown_triangles));
+
+    // Every crown gets one of a few shades of green, shared between the trees
+    let greens: Vec<Arc<dyn Material>> = [
+        Vec3::new(0.1, 0.4, 0.1),
+        Vec3::new(0.15, 0.45, 0.1),
+        Vec3::new(0.05, 0.3, 0.1),
+        Vec3::new(0.25, 0.45, 0.15),
+    ]
+    .into_iter()
+    .map(|color| {
+        let material: Box<dyn Material> =
+            Box::new(Lambertian::new(Box::new(SolidColor::new(color))));
+        Arc::from(material)
+    })
+    .collect();
+
+    let mut rng = random::rng();
+
+    for i in 0..100 {
+        for j in 0..100 {
+            let position = Vec3::new(
+                3.0 * (i as f32 - 50.0) + rng.gen_range(-1.2..1.2),
+                0.0,
+                3.0 * (j as f32 - 50.0) + rng.gen_range(-1.2..1.2),
+            );
+
+            let size = rng.gen_range(0.7..1.3);
+            let object_to_world = Affine3A::from_translation(position)
+                * Affine3A::from_rotation_y(rng.gen_range(0.0..TAU))
+                * Affine3A::from_scale(Vec3::new(size, size * rng.gen_range(0.8..1.4), size));
+
+            world.add(Box::new(Transform::instance(
+                Arc::clone(&trunk),
+                object_to_world,
+            )));
+
+            let green = &greens[rng.gen_range(0..greens.len())];
+            world.add(Box::new(
+                Transform::instance(Arc::clone(&crown), object_to_world)
+                    .with_material(Arc::clone(green)),
+            ));
+        }
+    }
+
+    // Camera
+    let lookfrom = Vec3::new(-160.0, 40.0, -160.0);
+    let lookat = Vec3::new(0.0, 0.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let fov = 40.0;
+    let aperture = 0.0;
+    let dist_to_focus = 10.0;
+
+    // Define the Camera
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        vup,
+        fov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+    );
+
+    return Scene {
+        world: Bvh::new(world),
+        camera,
+        background: Background::Color(Vec3::new(0.7, 0.8, 1.0)),
+        lights: Vec::new(),
+    };
+}
+
+/**
+ * Bouncing spheres and a tumbling box, seen with the shutter open from time 0 to 1
+ */
+pub fn motion_blur(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Box::new(Lambertian::new(Box::new(CheckerTexture::new(
+            Box::new(SolidColor::new(Vec3::new(0.2, 0.3, 0.1))),
+            Box::new(SolidColor::new(Vec3::new(0.9, 0.9, 0.9))),
+        )))),
+    )));
+
+    let mut rng = random::rng();
+
+    for i in -3..=3 {
+        let center = Vec3::new(1.5 * i as f32, 0.5, rng.gen_range(-1.5..1.5));
+        let bounce = Vec3::new(0.0, rng.gen_range(0.3..1.0), 0.0);
+        let albedo = Vec3::rand() * Vec3::rand();
+
+        world.add(Box::new(MovingSphere::new(
+            center,
+            center + bounce,
+            0.0,
+            1.0,
+            0.5,
+            Box::new(Lambertian::new(Box::new(SolidColor::new(albedo)))),
+        )));
+    }
+
+    // A box that moves across the back while turning a quarter around two axes
+    let cube = BoxShape::new(
+        Vec3::splat(-0.75),
+        Vec3::splat(0.75),
+        Box::new(Metal::new(Vec3::new(0.8, 0.6, 0.2), 0.1)),
+    );
+    let start = Affine3A::from_translation(Vec3::new(-1.5, 1.5, -4.0));
+    let end = Affine3A::from_translation(Vec3::new(1.5, 1.5, -4.0))
+        * Affine3A::from_rotation_y(FRAC_PI_2)
+        * Affine3A::from_rotation_x(FRAC_PI_2);
+    world.add(Box::new(
+        Transform::new(Box::new(cube), start).with_motion(end, 0.0, 1.0),
+    ));
+
+    // Camera
+    let lookfrom = Vec3::new(0.0, 3.0, 12.0);
+    let lookat = Vec3::new(0.0, 0.8, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let fov = 35.0;
+    let aperture = 0.0;
+    let dist_to_focus = 10.0;
+
+    // Define the Camera
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        vup,
+        fov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+    )
+    .with_shutter(0.0, 1.0);
+
+    return Scene {
+        world: Bvh::new(world),
+        camera,
+        background: Background::Color(Vec3::new(0.7, 0.8, 1.0)),
+        lights: Vec::new(),
+    };
+}
+
+/**
+ * A procedural cloud over a field, stored in a sparse voxel grid
+ */
+pub fn cloud(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::new(
+            0.3, 0.45, 0.2,
+        ))))),
+    )));
+
+    let resolution = [128, 64, 128];
+    let grid = SparseGrid::from_fn(resolution, volume::cloud(7, resolution));
+
+    world.add(Box::new(HeterogeneousMedium::new(
+        Aabb::new(Vec3::new(-4.0, 1.5, -4.0), Vec3::new(4.0, 5.5, 4.0)),
+        Box::new(grid),
+        4.0,
+        Tracking::Delta,
+        Box::new(Isotropic::new(Box::new(SolidColor::new(Vec3::splat(0.9))))),
+    )));
+
+    // Camera
+    let lookfrom = Vec3::new(0.0, 2.0, 14.0);
+    let lookat = Vec3::new(0.0, 3.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let fov = 45.0;
+    let aperture = 0.0;
+    let dist_to_focus = 10.0;
+
+    // Define the Camera
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        vup,
+        fov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+    );
+
+    return Scene {
+        world: Bvh::new(world),
+        camera,
+        background: Background::Color(Vec3::new(0.5, 0.7, 1.0)),
+        lights: Vec::new(),
+    };
+}
+
+/**
+ * Place a Wavefront OBJ model on a checkered ground under a sky, with the camera framing the model
+ */
+pub fn obj_model(path: &Path, aspect_ratio: f32) -> Result<Scene, ObjError> {
+    let mut world = obj::load_obj(path)?;
+
+    let model_bbox = world
+        .bounding_box()
+        .unwrap_or(Aabb::new(Vec3::ZERO, Vec3::ZERO));
+    let center = model_bbox.centroid();
+    let size = (model_bbox.max - model_bbox.min).length().max(0.001);
+
+    world.add(Box::new(Sphere::new(
+        Vec3::new(center.x, model_bbox.min.y - 1000.0 * size, center.z),
+        1000.0 * size,
+        Box::new(Lambertian::new(Box::new(CheckerTexture::new(
+            Box::new(SolidColor::new(Vec3::new(0.2, 0.3, 0.1))),
+            Box::new(SolidColor::new(Vec3::new(0.9, 0.9, 0.9))),
+        )))),
+    )));
+
+    // Camera
+    let lookfrom = center + size * Vec3::new(1.2, 0.5, 0.8);
+    let lookat = center;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let fov = 40.0;
+    let aperture = 0.0;
+    let dist_to_focus = (lookfrom - lookat).length();
+
+    // Define the Camera
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        vup,
+        fov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+    );
+
+    return Ok(Scene {
+        world: Bvh::new(world),
+        camera,
+        background: Background::Color(Vec3::new(0.7, 0.8, 1.0)),
+        lights: Vec::new(),
+    });
+}
@@ -0,0 +1,1702 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use glam::{Affine3A, Quat, Vec2, Vec3};
+use serde::Deserialize;
+use toml::Spanned;
+use toml_edit::ImDocument;
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::environment::{Background, EnvironmentMap};
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal};
+use crate::medium::ConstantMedium;
+use crate::obj;
+use crate::planar::Planar;
+use crate::rect::{AxisRect, BoxShape};
+use crate::scene::Scene;
+use crate::sky::Sky;
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{
+    CheckerTexture, ColorRamp, ImageData, ImageTexture, NoisePattern, NoiseTexture, SolidColor,
+    Texture, TextureFilter, TextureWrap,
+};
+use crate::transform::Transform;
+use crate::triangle::TriangleMesh;
+use crate::volume::{
+    self, DenseGrid, DensityGrid, HeterogeneousMedium, RawFormat, SparseGrid, Tracking,
+};
+
+/**
+ * Error while loading a scene file. The message says which line and key caused it.
+ */
+#[derive(Debug)]
+pub struct SceneFileError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}: {}", self.path.display(), self.message);
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+/*
+ * The layout of a scene file. Textures and materials are declared by name,
+ * and every variant is a table keyed by its type, e.g.
+ *
+ *   [materials.ground]
+ *   lambertian = { texture = "checker" }
+ *
+ *   [[objects]]
+ *   sphere = { center = [0, -1000, 0], radius = 1000, material = "ground" }
+ */
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    camera: CameraDescription,
+    // Color seen by rays that leave the scene, black if none of this, an environment or a sky is set
+    background: Option<[f32; 3]>,
+    environment: Option<EnvironmentDescription>,
+    sky: Option<SkyDescription>,
+    #[serde(default)]
+    textures: HashMap<String, TextureDescription>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDescription>,
+    // Objects that aren't part of the scene themselves, but can be placed many times with `instance`
+    #[serde(default)]
+    prototypes: HashMap<String, ObjectDescription>,
+    #[serde(default)]
+    objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    lights: Vec<LightDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    lookfrom: [f32; 3],
+    lookat: [f32; 3],
+    #[serde(default = "default_vup")]
+    vup: [f32; 3],
+    vfov: f32,
+    aspect_ratio: Option<f32>,
+    #[serde(default)]
+    aperture: f32,
+    focus_distance: Option<f32>,
+    // Times the shutter opens and closes, for motion blur
+    #[serde(default)]
+    shutter: [f32; 2],
+}
+
+fn default_vup() -> [f32; 3] {
+    return [0.0, 1.0, 0.0];
+}
+
+// Light from all directions, read from an equirectangular HDR or EXR image relative to the scene file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDescription {
+    path: Spanned<String>,
+    // Degrees around the x, y and z axes, applied in that order like the rotation of a transform
+    #[serde(default)]
+    rotate: [f32; 3],
+    #[serde(default = "default_intensity")]
+    intensity: f32,
+}
+
+fn default_intensity() -> f32 {
+    return 1.0;
+}
+
+// Daylight from a physical model of the sky, with the sun as a light
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkyDescription {
+    // Degrees above the horizon
+    elevation: f32,
+    // Degrees from -Z toward +X
+    #[serde(default)]
+    azimuth: f32,
+    #[serde(default = "default_turbidity")]
+    turbidity: f32,
+    // Angular radius of the sun in degrees, the real one if not set
+    sun_radius: Option<f32>,
+    #[serde(default = "default_intensity")]
+    intensity: f32,
+}
+
+fn default_turbidity() -> f32 {
+    return 3.0;
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    Solid {
+        color: [f32; 3],
+    },
+    Checker {
+        odd: Spanned<String>,
+        even: Spanned<String>,
+    },
+    // Image file, relative to the scene file
+    Image {
+        path: Spanned<String>,
+        #[serde(default = "default_texture_filter")]
+        filter: TextureFilter,
+        #[serde(default = "default_texture_wrap")]
+        wrap: TextureWrap,
+        // Whether 8 bit images hold sRGB encoded colors, rather than linear data
+        #[serde(default = "default_srgb")]
+        srgb: bool,
+    },
+    // Perlin noise. Without a ramp, a default one for the pattern is used.
+    Noise {
+        pattern: NoisePattern,
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+        #[serde(default = "default_noise_octaves")]
+        octaves: u32,
+        ramp: Option<Vec<(f32, [f32; 3])>>,
+    },
+}
+
+fn default_noise_scale() -> f32 {
+    return 1.0;
+}
+
+fn default_noise_octaves() -> u32 {
+    return 7;
+}
+
+fn default_texture_filter() -> TextureFilter {
+    return TextureFilter::Bilinear;
+}
+
+fn default_texture_wrap() -> TextureWrap {
+    return TextureWrap::Repeat;
+}
+
+fn default_srgb() -> bool {
+    return true;
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian {
+        albedo: Option<[f32; 3]>,
+        texture: Option<Spanned<String>>,
+    },
+    Metal {
+        albedo: [f32; 3],
+        #[serde(default)]
+        fuzz: f32,
+    },
+    Dielectric {
+        index_of_refraction: f32,
+    },
+    DiffuseLight {
+        color: Option<[f32; 3]>,
+        texture: Option<Spanned<String>>,
+    },
+    // Scatters in all directions, for volumes
+    Isotropic {
+        albedo: Option<[f32; 3]>,
+        texture: Option<Spanned<String>>,
+    },
+}
+
+// Lights that aren't objects in the scene, like the lights of the same name in src/light.rs
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum LightDescription {
+    Point {
+        position: [f32; 3],
+        color: [f32; 3],
+    },
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        color: [f32; 3],
+        // Half angle of the cone in degrees
+        angle: f32,
+        // Degrees at the edge of the cone over which the light fades out
+        #[serde(default)]
+        falloff: f32,
+    },
+    // Light from far away, shining in the given direction everywhere
+    Directional {
+        direction: [f32; 3],
+        color: [f32; 3],
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDescription {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+        material: Spanned<String>,
+    },
+    // Sphere moving from center0 at time0 to center1 at time1
+    MovingSphere {
+        center0: [f32; 3],
+        center1: [f32; 3],
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "default_time1")]
+        time1: f32,
+        radius: f32,
+        material: Spanned<String>,
+    },
+    RectXy {
+        x0: f32,
+        x1: f32,
+        y0: f32,
+        y1: f32,
+        k: f32,
+        material: Spanned<String>,
+    },
+    RectXz {
+        x0: f32,
+        x1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+        material: Spanned<String>,
+    },
+    RectYz {
+        y0: f32,
+        y1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+        material: Spanned<String>,
+    },
+    // Parallelogram with corners origin, origin + u, origin + v and origin + u + v
+    Quad {
+        origin: [f32; 3],
+        u: [f32; 3],
+        v: [f32; 3],
+        material: Spanned<String>,
+    },
+    // Triangle with corners origin, origin + u and origin + v
+    Triangle {
+        origin: [f32; 3],
+        u: [f32; 3],
+        v: [f32; 3],
+        material: Spanned<String>,
+    },
+    Disk {
+        center: [f32; 3],
+        normal: [f32; 3],
+        radius: f32,
+        material: Spanned<String>,
+    },
+    // Axis-aligned box between two opposite corners
+    #[serde(rename = "box")]
+    BoxShape {
+        min: [f32; 3],
+        max: [f32; 3],
+        material: Spanned<String>,
+    },
+    Mesh {
+        positions: Vec<[f32; 3]>,
+        indices: Vec<[usize; 3]>,
+        normals: Option<Vec<[f32; 3]>>,
+        uvs: Option<Vec<[f32; 2]>>,
+        material: Spanned<String>,
+    },
+    // Wavefront OBJ file, relative to the scene file. Materials come from its MTL files.
+    Obj {
+        path: Spanned<String>,
+    },
+    // Another object, scaled first, then rotated about x, y and z in turn (in degrees), then translated
+    Transform {
+        #[serde(default)]
+        translate: [f32; 3],
+        #[serde(default)]
+        rotate: [f32; 3],
+        #[serde(default = "default_scale")]
+        scale: [f32; 3],
+        object: Box<ObjectDescription>,
+        motion: Option<MotionDescription>,
+    },
+    // Smoke or fog of uniform density, filling a closed object. The material of the boundary
+    // object isn't used, the volume scatters light with its own material instead (normally isotropic).
+    ConstantMedium {
+        boundary: Box<ObjectDescription>,
+        density: f32,
+        material: Spanned<String>,
+    },
+    // Smoke or clouds with a density that varies through the box from min to max, given by a grid
+    // of voxels. The material scatters light inside (normally isotropic).
+    Volume {
+        min: [f32; 3],
+        max: [f32; 3],
+        grid: GridDescription,
+        // Multiplies the densities of the grid
+        #[serde(default = "default_volume_density")]
+        density: f32,
+        #[serde(default = "default_grid_storage")]
+        storage: GridStorage,
+        #[serde(default = "default_tracking")]
+        tracking: Tracking,
+        material: Spanned<String>,
+    },
+    // A copy of a prototype, placed like a transform. All copies share the geometry of the prototype.
+    // A material replaces the materials of the prototype for this copy.
+    Instance {
+        prototype: Spanned<String>,
+        #[serde(default)]
+        translate: [f32; 3],
+        #[serde(default)]
+        rotate: [f32; 3],
+        #[serde(default = "default_scale")]
+        scale: [f32; 3],
+        material: Option<Spanned<String>>,
+        motion: Option<MotionDescription>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum GridDescription {
+    // Headerless file of voxels, x varying fastest, relative to the scene file
+    Raw {
+        path: Spanned<String>,
+        resolution: [usize; 3],
+        #[serde(default = "default_raw_format")]
+        format: RawFormat,
+    },
+    // A procedural cloud
+    Cloud {
+        resolution: [usize; 3],
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum GridStorage {
+    Dense,
+    // Leaves out empty blocks of voxels
+    Sparse,
+}
+
+fn default_volume_density() -> f32 {
+    return 1.0;
+}
+
+fn default_grid_storage() -> GridStorage {
+    return GridStorage::Dense;
+}
+
+fn default_tracking() -> Tracking {
+    return Tracking::Delta;
+}
+
+fn default_raw_format() -> RawFormat {
+    return RawFormat::F32;
+}
+
+// The pose a transform moves to. It starts out in its own pose at time0, and is in this one at time1.
+// Anything left out stays the same. Rotations take the shortest way, so a motion turns by at most half a turn.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MotionDescription {
+    translate: Option<[f32; 3]>,
+    rotate: Option<[f32; 3]>,
+    scale: Option<[f32; 3]>,
+    #[serde(default)]
+    time0: f32,
+    #[serde(default = "default_time1")]
+    time1: f32,
+}
+
+fn default_time1() -> f32 {
+    return 1.0;
+}
+
+fn default_scale() -> [f32; 3] {
+    return [1.0, 1.0, 1.0];
+}
+
+/**
+ * Load a scene from a TOML scene description.
+ * The aspect ratio is used for the camera unless the file specifies one.
+ */
+pub fn load_scene(path: &Path, aspect_ratio: f32) -> Result<Scene, SceneFileError> {
+    let source = fs::read_to_string(path).map_err(|err| SceneFileError {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })?;
+
+    let description: SceneDescription = toml::from_str(&source).map_err(|err| SceneFileError {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })?;
+
+    // Values lose their place in the file once deserialized, so errors found later look it up in here
+    let document = ImDocument::parse(source.as_str()).map_err(|err| SceneFileError {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })?;
+
+    let builder = SceneBuilder {
+        path,
+        source: &source,
+        document: &document,
+        description: &description,
+        aspect_ratio,
+        images: RefCell::new(HashMap::new()),
+        prototypes: RefCell::new(HashMap::new()),
+        visiting_prototypes: RefCell::new(Vec::new()),
+        shared_materials: RefCell::new(HashMap::new()),
+    };
+
+    return builder.build();
+}
+
+struct SceneBuilder<'a> {
+    path: &'a Path,
+    source: &'a str,
+    document: &'a ImDocument<&'a str>,
+    description: &'a SceneDescription,
+    // Used when the scene file doesn't set its own
+    aspect_ratio: f32,
+    // Images already loaded, so textures referenced by several materials are only loaded once
+    images: RefCell<HashMap<(PathBuf, bool), Arc<ImageData>>>,
+    prototypes: RefCell<HashMap<String, Arc<dyn Hittable>>>,
+    // The prototypes currently being built, to catch prototypes that contain instances of themselves
+    visiting_prototypes: RefCell<Vec<String>>,
+    // Materials used to override the materials of instances
+    shared_materials: RefCell<HashMap<String, Arc<dyn Material>>>,
+}
+
+impl<'a> SceneBuilder<'a> {
+    fn build(&self) -> Result<Scene, SceneFileError> {
+        let mut world = HittableList::new();
+
+        for (index, object) in self.description.objects.iter().enumerate() {
+            self.object(object, &format!("objects[{}]", index), &mut world)?;
+        }
+
+        let mut lights = Vec::new();
+
+        for (index, light) in self.description.lights.iter().enumerate() {
+            lights.push(self.light(light, &format!("lights[{}]", index))?);
+        }
+
+        return Ok(Scene {
+            world: Bvh::new(world),
+            camera: self.camera()?,
+            background: self.background()?,
+            lights,
+        });
+    }
+
+    fn background(&self) -> Result<Background, SceneFileError> {
+        let description = self.description;
+
+        let set = [
+            ("background", description.background.is_some()),
+            ("environment", description.environment.is_some()),
+            ("sky", description.sky.is_some()),
+        ];
+        let mut set_keys = set
+            .iter()
+            .filter(|(_, is_set)| *is_set)
+            .map(|(key, _)| *key);
+
+        if let (Some(first), Some(second)) = (set_keys.next(), set_keys.next()) {
+            return Err(self.error_at_key(
+                second,
+                &format!(
+                    "set only one of background, environment and sky, {} is already set",
+                    first
+                ),
+            ));
+        }
+
+        if let Some(environment) = &description.environment {
+            let image = self.image(&environment.path, true, "environment.path")?;
+            let [x, y, z] = environment.rotate.map(f32::to_radians);
+            let rotation =
+                Quat::from_rotation_z(z) * Quat::from_rotation_y(y) * Quat::from_rotation_x(x);
+
+            let map = EnvironmentMap::new(image)
+                .with_rotation(rotation)
+                .with_intensity(environment.intensity);
+
+            return Ok(Background::Environment(map));
+        }
+
+        if let Some(sky) = &description.sky {
+            if !(0.0..=90.0).contains(&sky.elevation) {
+                return Err(self.error_at_key("sky.elevation", "must be between 0 and 90 degrees"));
+            }
+
+            // The range the model was fitted for
+            if !(2.0..=10.0).contains(&sky.turbidity) {
+                return Err(self.error_at_key("sky.turbidity", "must be between 2 and 10"));
+            }
+
+            let mut model =
+                Sky::new(sky.elevation, sky.azimuth, sky.turbidity).with_intensity(sky.intensity);
+
+            if let Some(radius) = sky.sun_radius {
+                if radius <= 0.0 {
+                    return Err(self.error_at_key("sky.sun_radius", "the radius must be positive"));
+                }
+
+                model = model.with_sun_radius(radius);
+            }
+
+            return Ok(Background::Sky(model));
+        }
+
+        return Ok(Background::Color(Vec3::from(
+            description.background.unwrap_or_default(),
+        )));
+    }
+
+    fn light(&self, light: &LightDescription, key: &str) -> Result<Box<dyn Light>, SceneFileError> {
+        return match light {
+            LightDescription::Point { position, color } => Ok(Box::new(PointLight::new(
+                Vec3::from(*position),
+                Vec3::from(*color),
+            ))),
+            LightDescription::Spot {
+                position,
+                direction,
+                color,
+                angle,
+                falloff,
+            } => {
+                if Vec3::from(*direction) == Vec3::ZERO {
+                    return Err(self.error_at_key(
+                        &format!("{}.spot.direction", key),
+                        "the direction is zero",
+                    ));
+                }
+
+                if !(*angle > 0.0 && *angle < 180.0) {
+                    return Err(self.error_at_key(
+                        &format!("{}.spot.angle", key),
+                        "must be between 0 and 180 degrees",
+                    ));
+                }
+
+                if !(0.0..=*angle).contains(falloff) {
+                    return Err(self.error_at_key(
+                        &format!("{}.spot.falloff", key),
+                        "must be between 0 and the angle",
+                    ));
+                }
+
+                let spot = SpotLight::new(
+                    Vec3::from(*position),
+                    Vec3::from(*direction),
+                    Vec3::from(*color),
+                    *angle,
+                )
+                .with_falloff(*falloff);
+
+                Ok(Box::new(spot))
+            }
+            LightDescription::Directional { direction, color } => {
+                if Vec3::from(*direction) == Vec3::ZERO {
+                    return Err(self.error_at_key(
+                        &format!("{}.directional.direction", key),
+                        "the direction is zero",
+                    ));
+                }
+
+                Ok(Box::new(DirectionalLight::new(
+                    Vec3::from(*direction),
+                    Vec3::from(*color),
+                )))
+            }
+        };
+    }
+
+    // Rectangles span from the lower bound to the upper one, reversed bounds would leave them empty
+    fn check_bounds(
+        &self,
+        key: &str,
+        axis: &str,
+        min: f32,
+        max: f32,
+    ) -> Result<(), SceneFileError> {
+        if min > max {
+            return Err(self.error_at_key(
+                &format!("{}.{}1", key, axis),
+                &format!("must not be less than {}0", axis),
+            ));
+        }
+
+        return Ok(());
+    }
+
+    // Negative radii make hollow spheres, but a zero radius divides by zero
+    fn check_radius(&self, key: &str, radius: f32) -> Result<(), SceneFileError> {
+        if radius == 0.0 {
+            return Err(self.error_at_key(&format!("{}.radius", key), "must not be 0"));
+        }
+
+        return Ok(());
+    }
+
+    // Build an object and add it to the list. Some descriptions, like OBJ files, add several.
+    fn object(
+        &self,
+        object: &ObjectDescription,
+        key: &str,
+        list: &mut HittableList,
+    ) -> Result<(), SceneFileError> {
+        match object {
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                let key = format!("{}.sphere", key);
+                self.check_radius(&key, *radius)?;
+
+                list.add(Box::new(Sphere::new(
+                    Vec3::from(*center),
+                    *radius,
+                    self.material(material, &format!("{}.material", key))?,
+                )))
+            }
+            ObjectDescription::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => {
+                let key = format!("{}.moving_sphere", key);
+                self.check_radius(&key, *radius)?;
+
+                list.add(Box::new(MovingSphere::new(
+                    Vec3::from(*center0),
+                    Vec3::from(*center1),
+                    *time0,
+                    *time1,
+                    *radius,
+                    self.material(material, &format!("{}.material", key))?,
+                )))
+            }
+            ObjectDescription::RectXy {
+                x0,
+                x1,
+                y0,
+                y1,
+                k,
+                material,
+            } => {
+                let key = format!("{}.rect_xy", key);
+                self.check_bounds(&key, "x", *x0, *x1)?;
+                self.check_bounds(&key, "y", *y0, *y1)?;
+
+                list.add(Box::new(AxisRect::xy(
+                    *x0,
+                    *x1,
+                    *y0,
+                    *y1,
+                    *k,
+                    self.material(material, &format!("{}.material", key))?,
+                )))
+            }
+            ObjectDescription::RectXz {
+                x0,
+                x1,
+                z0,
+                z1,
+                k,
+                material,
+            } => {
+                let key = format!("{}.rect_xz", key);
+                self.check_bounds(&key, "x", *x0, *x1)?;
+                self.check_bounds(&key, "z", *z0, *z1)?;
+
+                list.add(Box::new(AxisRect::xz(
+                    *x0,
+                    *x1,
+                    *z0,
+                    *z1,
+                    *k,
+                    self.material(material, &format!("{}.material", key))?,
+                )))
+            }
+            ObjectDescription::RectYz {
+                y0,
+                y1,
+                z0,
+                z1,
+                k,
+                material,
+            } => {
+                let key = format!("{}.rect_yz", key);
+                self.check_bounds(&key, "y", *y0, *y1)?;
+                self.check_bounds(&key, "z", *z0, *z1)?;
+
+                list.add(Box::new(AxisRect::yz(
+                    *y0,
+                    *y1,
+                    *z0,
+                    *z1,
+                    *k,
+                    self.material(material, &format!("{}.material", key))?,
+                )))
+            }
+            ObjectDescription::Quad {
+                origin,
+                u,
+                v,
+                material,
+            } => {
+                let (u, v) = (Vec3::from(*u), Vec3::from(*v));
+
+                if u.cross(v).length_squared() == 0.0 {
+                    return Err(self.error_at_key(
+                        &format!("{}.quad", key),
+                        "the edges u and v must not be parallel or zero",
+                    ));
+                }
+
+                list.add(Box::new(Planar::quad(
+                    Vec3::from(*origin),
+                    u,
+                    v,
+                    self.material(material, &format!("{}.quad.material", key))?,
+                )))
+            }
+            ObjectDescription::Triangle {
+                origin,
+                u,
+                v,
+                material,
+            } => {
+                let (u, v) = (Vec3::from(*u), Vec3::from(*v));
+
+                if u.cross(v).length_squared() == 0.0 {
+                    return Err(self.error_at_key(
+                        &format!("{}.triangle", key),
+                        "the edges u and v must not be parallel or zero",
+                    ));
+                }
+
+                list.add(Box::new(Planar::triangle(
+                    Vec3::from(*origin),
+                    u,
+                    v,
+                    self.material(material, &format!("{}.triangle.material", key))?,
+                )))
+            }
+            ObjectDescription::Disk {
+                center,
+                normal,
+                radius,
+                material,
+            } => {
+                let key = format!("{}.disk", key);
+
+                if Vec3::from(*normal).length_squared() == 0.0 {
+                    return Err(self.error_at_key(&format!("{}.normal", key), "the normal is zero"));
+                }
+
+                if *radius <= 0.0 {
+                    return Err(self
+                        .error_at_key(&format!("{}.radius", key), "the radius must be positive"));
+                }
+
+                list.add(Box::new(Planar::disk(
+                    Vec3::from(*center),
+                    Vec3::from(*normal),
+                    *radius,
+                    self.material(material, &format!("{}.material", key))?,
+                )))
+            }
+            ObjectDescription::BoxShape { min, max, material } => {
+                list.add(Box::new(BoxShape::new(
+                    Vec3::from(*min),
+                    Vec3::from(*max),
+                    self.material(material, &format!("{}.box.material", key))?,
+                )))
+            }
+            ObjectDescription::Mesh {
+                positions,
+                indices,
+                normals,
+                uvs,
+                material,
+            } => {
+                let key = format!("{}.mesh", key);
+
+                let vertex_count = positions.len();
+                if let Some(triangle) = indices
+                    .iter()
+                    .find(|t| t.iter().any(|&i| i >= vertex_count))
+                {
+                    return Err(self.error_at_key(
+                        &format!("{}.indices", key),
+                        &format!(
+                            "triangle {:?} references a vertex out of range ({} positions)",
+                            triangle, vertex_count
+                        ),
+                    ));
+                }
+
+                if normals.as_ref().is_some_and(|n| n.len() != vertex_count) {
+                    return Err(self.error_at_key(
+                        &format!("{}.normals", key),
+                        "must have one normal per position",
+                    ));
+                }
+
+                if uvs.as_ref().is_some_and(|uv| uv.len() != vertex_count) {
+                    return Err(
+                        self.error_at_key(&format!("{}.uvs", key), "must have one uv per position")
+                    );
+                }
+
+                let mesh = TriangleMesh::new(
+                    positions.iter().map(|p| Vec3::from(*p)).collect(),
+                    normals
+                        .as_ref()
+                        .map(|normals| normals.iter().map(|n| Vec3::from(*n)).collect()),
+                    uvs.as_ref()
+                        .map(|uvs| uvs.iter().map(|uv| Vec2::from(*uv)).collect()),
+                    indices.clone(),
+                    self.material(material, &format!("{}.material", key))?,
+                );
+
+                for triangle in mesh.into_triangles() {
+                    list.add(Box::new(triangle));
+                }
+            }
+            ObjectDescription::Obj { path } => {
+                let obj_path = self.relative_path(path.get_ref());
+
+                let model = obj::load_obj(&obj_path).map_err(|err| {
+                    self.error_at(path.span(), &format!("{}.obj.path", key), &err.to_string())
+                })?;
+
+                for object in model.into_objects() {
+                    list.add(object);
+                }
+            }
+            ObjectDescription::Transform {
+                translate,
+                rotate,
+                scale,
+                object,
+                motion,
+            } => {
+                let key = format!("{}.transform", key);
+                let object_to_world = self.object_to_world(*translate, *rotate, *scale, &key)?;
+
+                let mut objects = HittableList::new();
+                self.object(object, &format!("{}.object", key), &mut objects)?;
+
+                let transform = Transform::new(Box::new(Bvh::new(objects)), object_to_world);
+
+                list.add(Box::new(self.motion(
+                    transform,
+                    (*translate, *rotate, *scale),
+                    motion,
+                    &key,
+                )?));
+            }
+            ObjectDescription::ConstantMedium {
+                boundary,
+                density,
+                material,
+            } => {
+                let key = format!("{}.constant_medium", key);
+
+                if *density <= 0.0 {
+                    return Err(self.error_at_key(
+                        &format!("{}.density", key),
+                        "the density must be positive",
+                    ));
+                }
+
+                let mut objects = HittableList::new();
+                self.object(boundary, &format!("{}.boundary", key), &mut objects)?;
+
+                list.add(Box::new(ConstantMedium::new(
+                    Box::new(Bvh::new(objects)),
+                    *density,
+                    self.material(material, &format!("{}.material", key))?,
+                )));
+            }
+            ObjectDescription::Volume {
+                min,
+                max,
+                grid,
+                density,
+                storage,
+                tracking,
+                material,
+            } => {
+                let key = format!("{}.volume", key);
+                let (min, max) = (Vec3::from(*min), Vec3::from(*max));
+
+                if min.cmpge(max).any() {
+                    return Err(self.error_at_key(
+                        &format!("{}.max", key),
+                        "must be greater than min on every axis",
+                    ));
+                }
+
+                if *density < 0.0 {
+                    return Err(self.error_at_key(
+                        &format!("{}.density", key),
+                        "the density can't be negative",
+                    ));
+                }
+
+                list.add(Box::new(HeterogeneousMedium::new(
+                    Aabb::new(min, max),
+                    self.density_grid(grid, *storage, &format!("{}.grid", key))?,
+                    *density,
+                    *tracking,
+                    self.material(material, &format!("{}.material", key))?,
+                )));
+            }
+            ObjectDescription::Instance {
+                prototype,
+                translate,
+                rotate,
+                scale,
+                material,
+                motion,
+            } => {
+                let key = format!("{}.instance", key);
+                let object_to_world = self.object_to_world(*translate, *rotate, *scale, &key)?;
+
+                let mut instance = Transform::instance(
+                    self.prototype(prototype, &format!("{}.prototype", key))?,
+                    object_to_world,
+                );
+
+                if let Some(material) = material {
+                    instance = instance.with_material(
+                        self.shared_material(material, &format!("{}.material", key))?,
+                    );
+                }
+
+                list.add(Box::new(self.motion(
+                    instance,
+                    (*translate, *rotate, *scale),
+                    motion,
+                    &key,
+                )?));
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Scale, then rotate about x, y and z in turn (in degrees), then translate
+    fn object_to_world(
+        &self,
+        translate: [f32; 3],
+        rotate: [f32; 3],
+        scale: [f32; 3],
+        key: &str,
+    ) -> Result<Affine3A, SceneFileError> {
+        if scale.contains(&0.0) {
+            return Err(self.error_at_key(&format!("{}.scale", key), "can't scale by zero"));
+        }
+
+        let [x, y, z] = rotate.map(f32::to_radians);
+
+        return Ok(Affine3A::from_translation(Vec3::from(translate))
+            * Affine3A::from_rotation_z(z)
+            * Affine3A::from_rotation_y(y)
+            * Affine3A::from_rotation_x(x)
+            * Affine3A::from_scale(Vec3::from(scale)));
+    }
+
+    fn density_grid(
+        &self,
+        grid: &GridDescription,
+        storage: GridStorage,
+        key: &str,
+    ) -> Result<Box<dyn DensityGrid>, SceneFileError> {
+        let resolution = match grid {
+            GridDescription::Raw { resolution, .. } => resolution,
+            GridDescription::Cloud { resolution, .. } => resolution,
+        };
+
+        if resolution.contains(&0) {
+            return Err(self.error_at_key(
+                &format!("{}.resolution", key),
+                "the grid needs at least one voxel on every axis",
+            ));
+        }
+
+        let resolution = *resolution;
+
+        let density: Box<dyn Fn(usize, usize, usize) -> f32> = match grid {
+            GridDescription::Raw { path, format, .. } => {
+                let values =
+                    volume::load_raw(&self.relative_path(path.get_ref()), resolution, *format)
+                        .map_err(|err| {
+                            self.error_at(
+                                path.span(),
+                                &format!("{}.raw.path", key),
+                                &format!("could not load voxels: {}", err),
+                            )
+                        })?;
+
+                let [nx, ny, _] = resolution;
+                Box::new(move |x, y, z| values[x + nx * (y + ny * z)])
+            }
+            GridDescription::Cloud { seed, .. } => Box::new(volume::cloud(*seed, resolution)),
+        };
+
+        return Ok(match storage {
+            GridStorage::Dense => Box::new(DenseGrid::from_fn(resolution, density)),
+            GridStorage::Sparse => Box::new(SparseGrid::from_fn(resolution, density)),
+        });
+    }
+
+    // Make a transform move, if it has a motion. `start` is its own translation, rotation and scale.
+    fn motion(
+        &self,
+        transform: Transform,
+        start: ([f32; 3], [f32; 3], [f32; 3]),
+        motion: &Option<MotionDescription>,
+        key: &str,
+    ) -> Result<Transform, SceneFileError> {
+        let motion = match motion {
+            Some(motion) => motion,
+            None => return Ok(transform),
+        };
+
+        let (translate, rotate, scale) = start;
+
+        let end = self.object_to_world(
+            motion.translate.unwrap_or(translate),
+            motion.rotate.unwrap_or(rotate),
+            motion.scale.unwrap_or(scale),
+            &format!("{}.motion", key),
+        )?;
+
+        return Ok(transform.with_motion(end, motion.time0, motion.time1));
+    }
+
+    // Prototypes are built the first time they are used, and shared by all their instances after that
+    fn prototype(
+        &self,
+        name: &Spanned<String>,
+        key: &str,
+    ) -> Result<Arc<dyn Hittable>, SceneFileError> {
+        if let Some(prototype) = self.prototypes.borrow().get(name.get_ref()) {
+            return Ok(Arc::clone(prototype));
+        }
+
+        let description = match self.description.prototypes.get(name.get_ref()) {
+            Some(description) => description,
+            None => {
+                return Err(self.error_at(
+                    name.span(),
+                    key,
+                    &format!("unknown prototype '{}'", name.get_ref()),
+                ))
+            }
+        };
+
+        if self.visiting_prototypes.borrow().contains(name.get_ref()) {
+            return Err(self.error_at(
+                name.span(),
+                key,
+                &format!("prototype '{}' contains itself", name.get_ref()),
+            ));
+        }
+
+        self.visiting_prototypes
+            .borrow_mut()
+            .push(name.get_ref().clone());
+
+        let mut objects = HittableList::new();
+        let result = self.object(
+            description,
+            &format!("prototypes.{}", name.get_ref()),
+            &mut objects,
+        );
+
+        self.visiting_prototypes.borrow_mut().pop();
+        result?;
+
+        let prototype: Arc<dyn Hittable> = Arc::new(Bvh::new(objects));
+
+        self.prototypes
+            .borrow_mut()
+            .insert(name.get_ref().clone(), Arc::clone(&prototype));
+
+        return Ok(prototype);
+    }
+
+    fn camera(&self) -> Result<Camera, SceneFileError> {
+        let camera = &self.description.camera;
+
+        if !(camera.vfov > 0.0 && camera.vfov < 180.0) {
+            return Err(self.error_at_key("camera.vfov", "must be between 0 and 180 degrees"));
+        }
+
+        let lookfrom = Vec3::from(camera.lookfrom);
+        let lookat = Vec3::from(camera.lookat);
+
+        return Ok(Camera::new(
+            lookfrom,
+            lookat,
+            Vec3::from(camera.vup),
+            camera.vfov,
+            camera.aspect_ratio.unwrap_or(self.aspect_ratio),
+            camera.aperture,
+            camera
+                .focus_distance
+                .unwrap_or_else(|| (lookfrom - lookat).length()),
+        )
+        .with_shutter(camera.shutter[0], camera.shutter[1]));
+    }
+
+    fn material(
+        &self,
+        name: &Spanned<String>,
+        key: &str,
+    ) -> Result<Box<dyn Material>, SceneFileError> {
+        let description = match self.description.materials.get(name.get_ref()) {
+            Some(description) => description,
+            None => {
+                return Err(self.error_at(
+                    name.span(),
+                    key,
+                    &format!("unknown material '{}'", name.get_ref()),
+                ))
+            }
+        };
+
+        let material_key = format!("materials.{}", name.get_ref());
+
+        let material: Box<dyn Material> = match description {
+            MaterialDescription::Lambertian { albedo, texture } => {
+                Box::new(Lambertian::new(self.color_or_texture(
+                    *albedo,
+                    texture,
+                    &format!("{}.lambertian", material_key),
+                    "albedo",
+                )?))
+            }
+            MaterialDescription::Metal { albedo, fuzz } => {
+                if *fuzz < 0.0 {
+                    return Err(self.error_at_key(
+                        &format!("{}.metal.fuzz", material_key),
+                        "must not be negative",
+                    ));
+                }
+
+                Box::new(Metal::new(Vec3::from(*albedo), *fuzz))
+            }
+            MaterialDescription::Dielectric {
+                index_of_refraction,
+            } => {
+                if *index_of_refraction <= 0.0 || index_of_refraction.is_nan() {
+                    return Err(self.error_at_key(
+                        &format!("{}.dielectric.index_of_refraction", material_key),
+                        "must be greater than 0",
+                    ));
+                }
+
+                Box::new(Dielectric::new(*index_of_refraction))
+            }
+            MaterialDescription::DiffuseLight { color, texture } => {
+                Box::new(DiffuseLight::new(self.color_or_texture(
+                    *color,
+                    texture,
+                    &format!("{}.diffuse_light", material_key),
+                    "color",
+                )?))
+            }
+            MaterialDescription::Isotropic { albedo, texture } => {
+                Box::new(Isotropic::new(self.color_or_texture(
+                    *albedo,
+                    texture,
+                    &format!("{}.isotropic", material_key),
+                    "albedo",
+                )?))
+            }
+        };
+
+        return Ok(material);
+    }
+
+    // Materials can either be given a plain color, or refer to a texture by name
+    fn color_or_texture(
+        &self,
+        color: Option<[f32; 3]>,
+        texture: &Option<Spanned<String>>,
+        key: &str,
+        color_key: &str,
+    ) -> Result<Box<dyn Texture>, SceneFileError> {
+        return match (color, texture) {
+            (Some(color), None) => Ok(Box::new(SolidColor::new(Vec3::from(color)))),
+            (None, Some(texture)) => {
+                self.texture(texture, &format!("{}.texture", key), &mut Vec::new())
+            }
+            _ => Err(self.error_at_key(
+                key,
+                &format!("expected exactly one of `{}` or `texture`", color_key),
+            )),
+        };
+    }
+
+    // Like `material`, but every use of the same name gets the same material
+    fn shared_material(
+        &self,
+        name: &Spanned<String>,
+        key: &str,
+    ) -> Result<Arc<dyn Material>, SceneFileError> {
+        if let Some(material) = self.shared_materials.borrow().get(name.get_ref()) {
+            return Ok(Arc::clone(material));
+        }
+
+        let material: Arc<dyn Material> = Arc::from(self.material(name, key)?);
+
+        self.shared_materials
+            .borrow_mut()
+            .insert(name.get_ref().clone(), Arc::clone(&material));
+
+        return Ok(material);
+    }
+
+    // `visiting` holds the textures currently being built, to catch textures that refer to themselves
+    fn texture(
+        &self,
+        name: &Spanned<String>,
+        key: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<Box<dyn Texture>, SceneFileError> {
+        let description = match self.description.textures.get(name.get_ref()) {
+            Some(description) => description,
+            None => {
+                return Err(self.error_at(
+                    name.span(),
+                    key,
+                    &format!("unknown texture '{}'", name.get_ref()),
+                ))
+            }
+        };
+
+        if visiting.contains(name.get_ref()) {
+            return Err(self.error_at(
+                name.span(),
+                key,
+                &format!("texture '{}' refers to itself", name.get_ref()),
+            ));
+        }
+
+        visiting.push(name.get_ref().clone());
+
+        let texture_key = format!("textures.{}", name.get_ref());
+
+        let texture: Box<dyn Texture> = match description {
+            TextureDescription::Solid { color } => Box::new(SolidColor::new(Vec3::from(*color))),
+            TextureDescription::Checker { odd, even } => Box::new(CheckerTexture::new(
+                self.texture(odd, &format!("{}.checker.odd", texture_key), visiting)?,
+                self.texture(even, &format!("{}.checker.even", texture_key), visiting)?,
+            )),
+            TextureDescription::Image {
+                path,
+                filter,
+                wrap,
+                srgb,
+            } => {
+                let image = self.image(path, *srgb, &format!("{}.image.path", texture_key))?;
+                Box::new(ImageTexture::new(image, *filter, *wrap))
+            }
+            TextureDescription::Noise {
+                pattern,
+                seed,
+                scale,
+                octaves,
+                ramp,
+            } => {
+                let ramp = match ramp {
+                    Some(stops) => ColorRamp::new(
+                        stops
+                            .iter()
+                            .map(|(position, color)| (*position, Vec3::from(*color)))
+                            .collect(),
+                    ),
+                    None => NoiseTexture::default_ramp(*pattern),
+                };
+
+                Box::new(NoiseTexture::new(*seed, *pattern, *scale, *octaves, ramp))
+            }
+        };
+
+        visiting.pop();
+
+        return Ok(texture);
+    }
+
+    fn image(
+        &self,
+        path: &Spanned<String>,
+        srgb: bool,
+        key: &str,
+    ) -> Result<Arc<ImageData>, SceneFileError> {
+        let image_path = self.relative_path(path.get_ref());
+        let cache_key = (image_path.clone(), srgb);
+
+        if let Some(image) = self.images.borrow().get(&cache_key) {
+            return Ok(Arc::clone(image));
+        }
+
+        let image = ImageData::load(&image_path, srgb).map_err(|err| {
+            self.error_at(path.span(), key, &format!("could not load image: {}", err))
+        })?;
+
+        let image = Arc::new(image);
+        self.images
+            .borrow_mut()
+            .insert(cache_key, Arc::clone(&image));
+
+        return Ok(image);
+    }
+
+    fn relative_path(&self, path: &str) -> PathBuf {
+        let directory = self.path.parent().unwrap_or_else(|| Path::new(""));
+        return directory.join(path);
+    }
+
+    fn error_at(&self, span: Range<usize>, key: &str, message: &str) -> SceneFileError {
+        let before = &self.source[..span.start.min(self.source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+
+        return SceneFileError {
+            path: self.path.to_path_buf(),
+            message: format!(
+                "error at line {}, column {} (`{}`): {}",
+                line, column, key, message
+            ),
+        };
+    }
+
+    // Point at the value of the key, or at the closest table around it for keys that aren't in the file
+    fn error_at_key(&self, key: &str, message: &str) -> SceneFileError {
+        return match self.key_span(key) {
+            Some(span) => self.error_at(span, key, message),
+            None => SceneFileError {
+                path: self.path.to_path_buf(),
+                message: format!("`{}`: {}", key, message),
+            },
+        };
+    }
+
+    // Where the value of a key like `objects[2].sphere.radius` is in the file
+    fn key_span(&self, key: &str) -> Option<Range<usize>> {
+        let mut item = self.document.as_item();
+        let mut span = None;
+
+        for part in key.split(['.', '[']) {
+            let next = match part.strip_suffix(']') {
+                Some(index) => index
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| item.get(index)),
+                None => item.get(part),
+            };
+
+            let Some(next) = next else {
+                break;
+            };
+
+            item = next;
+            span = item.span().or(span);
+        }
+
+        return span;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    const CAMERA: &str = "\
+[camera]
+lookfrom = [0.0, 1.0, 5.0]
+lookat = [0.0, 1.0, 0.0]
+vfov = 40.0
+";
+
+    fn load(name: &str, source: &str) -> Result<Scene, SceneFileError> {
+        let path = std::env::temp_dir().join(format!(
+            "raytracer_scene_{}_{}.toml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, format!("{}{}", CAMERA, source)).unwrap();
+
+        let result = load_scene(&path, 1.0);
+        fs::remove_file(&path).unwrap();
+
+        return result;
+    }
+
+    fn error_message(name: &str, source: &str) -> String {
+        return match load(name, source) {
+            Ok(_) => panic!("the scene loaded without errors"),
+            Err(error) => error.message,
+        };
+    }
+
+    #[test]
+    fn example_scenes_load() {
+        for entry in fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes")).unwrap() {
+            let path = entry.unwrap().path();
+
+            if let Err(error) = load_scene(&path, 1.5) {
+                panic!("{}", error);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_names_point_at_the_line_and_column() {
+        let source = "
+[materials.red]
+lambertian = { albedo = [0.8, 0.1, 0.1] }
+
+[[objects]]
+sphere = { center = [0.0, 1.0, 0.0], radius = 1.0, material = \"red\" }
+
+[[objects]]
+sphere = { center = [0.0, -100.0, 0.0], radius = 100.0, material = \"blue\" }
+";
+
+        // The camera takes up the first 4 lines
+        assert_eq!(
+            error_message("unknown_material", source),
+            "error at line 13, column 68 (`objects[1].sphere.material`): unknown material 'blue'"
+        );
+
+        let source = "
+[textures.tiles]
+checker = { odd = \"tiles\", even = \"tiles\" }
+
+[materials.floor]
+lambertian = { texture = \"tiles\" }
+
+[[objects]]
+sphere = { center = [0.0, -100.0, 0.0], radius = 100.0, material = \"floor\" }
+";
+
+        assert_eq!(
+            error_message("texture_cycle", source),
+            "error at line 7, column 19 (`textures.tiles.checker.odd`): texture 'tiles' refers to itself"
+        );
+    }
+
+    #[test]
+    fn invalid_values_name_their_key() {
+        let source = "
+[materials.light]
+diffuse_light = { color = [4.0, 4.0, 4.0] }
+
+[[objects]]
+rect_xz = { x0 = -1.0, x1 = 1.0, z0 = 2.0, z1 = -2.0, k = 3.0, material = \"light\" }
+";
+
+        assert_eq!(
+            error_message("reversed_rect", source),
+            "error at line 10, column 49 (`objects[0].rect_xz.z1`): must not be less than z0"
+        );
+
+        let source = "
+[materials.brushed]
+metal = { albedo = [0.8, 0.8, 0.8], fuzz = -0.2 }
+
+[[objects]]
+sphere = { center = [0.0, 1.0, 0.0], radius = 1.0, material = \"brushed\" }
+";
+
+        assert_eq!(
+            error_message("negative_fuzz", source),
+            "error at line 7, column 44 (`materials.brushed.metal.fuzz`): must not be negative"
+        );
+
+        let source = "
+[materials.gray]
+lambertian = { albedo = [0.5, 0.5, 0.5] }
+
+[[objects]]
+mesh = { positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]], indices = [[0, 1, 3]], material = \"gray\" }
+";
+
+        assert_eq!(
+            error_message("mesh_indices", source),
+            "error at line 10, column 85 (`objects[0].mesh.indices`): triangle [0, 1, 3] references a vertex out of range (3 positions)"
+        );
+
+        let source = "
+[materials.gray]
+lambertian = { albedo = [0.5, 0.5, 0.5], texture = \"tiles\" }
+
+[[objects]]
+sphere = { center = [0.0, 1.0, 0.0], radius = 1.0, material = \"gray\" }
+";
+
+        assert_eq!(
+            error_message("albedo_and_texture", source),
+            "error at line 7, column 14 (`materials.gray.lambertian`): expected exactly one of `albedo` or `texture`"
+        );
+
+        let source = "
+[materials.glass]
+dielectric = { index_of_refraction = 0.0 }
+
+[[objects]]
+sphere = { center = [0.0, 1.0, 0.0], radius = 1.0, material = \"glass\" }
+";
+
+        assert_eq!(
+            error_message("zero_index", source),
+            "error at line 7, column 38 (`materials.glass.dielectric.index_of_refraction`): must be greater than 0"
+        );
+
+        let source = "
+[materials.red]
+lambertian = { albedo = [0.8, 0.1, 0.1] }
+
+[[objects]]
+moving_sphere = { center0 = [0.0, 1.0, 0.0], center1 = [0.0, 2.0, 0.0], time0 = 0.0, time1 = 1.0, radius = 0.0, material = \"red\" }
+";
+
+        assert_eq!(
+            error_message("zero_radius", source),
+            "error at line 10, column 108 (`objects[0].moving_sphere.radius`): must not be 0"
+        );
+    }
+
+    #[test]
+    fn field_of_view_must_be_between_0_and_180_degrees() {
+        for vfov in ["0.0", "180.0", "-30.0", "nan"] {
+            let path = std::env::temp_dir()
+                .join(format!("raytracer_scene_vfov_{}.toml", std::process::id()));
+            let source = CAMERA.replace("vfov = 40.0", &format!("vfov = {}", vfov));
+            fs::write(&path, source).unwrap();
+
+            let result = load_scene(&path, 1.0);
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(
+                result.err().unwrap().message,
+                "error at line 4, column 8 (`camera.vfov`): must be between 0 and 180 degrees"
+            );
+        }
+    }
+
+    #[test]
+    fn syntax_errors_and_unknown_fields_are_reported() {
+        let message = error_message(
+            "syntax",
+            "[[objects]]\nsphere = { center = [0.0, 1.0, 0.0]\n",
+        );
+        assert!(message.contains("line 6"), "{}", message);
+
+        let message = error_message(
+            "unknown_field",
+            "[[objects]]\nsphere = { radius = 1.0, colour = \"red\" }\n",
+        );
+        assert!(message.contains("line 6, column 26"), "{}", message);
+        assert!(
+            message.contains("unexpected keys in table: colour"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn instances_place_copies_of_prototypes() {
+        let source = "
+[materials.red]
+lambertian = { albedo = [0.8, 0.1, 0.1] }
+
+[prototypes.ball]
+sphere = { center = [0.0, 0.0, 0.0], radius = 1.0, material = \"red\" }
+
+[[objects]]
+instance = { prototype = \"ball\", translate = [-3.0, 0.0, 0.0] }
+
+[[objects]]
+instance = { prototype = \"ball\", translate = [3.0, 0.0, 0.0], scale = [2.0, 2.0, 2.0] }
+";
+
+        let scene = load("instances", source).unwrap();
+
+        // The prototype itself isn't in the scene, only its copies
+        for (x, radius) in [(-3.0, Some(1.0)), (0.0, None), (3.0, Some(2.0))] {
+            let ray = Ray::new(Vec3::new(x, 0.0, 10.0), Vec3::NEG_Z, 0.0);
+            let hit = scene.world.hit(&ray, 0.001, f32::INFINITY);
+
+            assert_eq!(hit.map(|rec| 10.0 - rec.t), radius);
+        }
+    }
+
+    #[test]
+    fn prototypes_must_exist_and_not_contain_themselves() {
+        let source = "
+[[objects]]
+instance = { prototype = \"ball\" }
+";
+
+        assert_eq!(
+            error_message("unknown_prototype", source),
+            "error at line 7, column 26 (`objects[0].instance.prototype`): unknown prototype 'ball'"
+        );
+
+        let source = "
+[prototypes.nested]
+instance = { prototype = \"nested\" }
+
+[[objects]]
+instance = { prototype = \"nested\" }
+";
+
+        assert_eq!(
+            error_message("nested_prototype", source),
+            "error at line 7, column 26 (`prototypes.nested.instance.prototype`): prototype 'nested' contains itself"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::integrator::IntegratorKind;
+use crate::material::Lobe;
+use crate::output::{ExrPrecision, ImageFormat};
+use crate::tonemap::{PostProcess, ToneMapping};
+
+const DEFAULT_ASPECT_RATIO: f32 = 16.0 / 9.0;
+
+// Command line arguments. Doc comments on the fields become the --help text
+#[derive(Parser)]
+#[command(about = "A path tracing renderer")]
+pub struct Cli {
+    /// Width of the image in pixels
+    #[arg(long, default_value_t = 1920)]
+    width: i32,
+
+    /// Height of the image in pixels. Defaults to a 16:9 image
+    #[arg(long)]
+    height: Option<i32>,
+
+    /// Number of samples per pixel
+    #[arg(long, default_value_t = 2000)]
+    spp: i32,
+
+    /// Maximum amount of light bounces per ray
+    #[arg(long, default_value_t = 200)]
+    max_depth: i32,
+
+    /// Maximum amount of bounces off diffuse surfaces and inside volumes. Defaults to --max-depth
+    #[arg(long)]
+    max_diffuse_depth: Option<i32>,
+
+    /// Maximum amount of mirror-like and glossy reflections. Defaults to --max-depth
+    #[arg(long)]
+    max_specular_depth: Option<i32>,
+
+    /// Maximum amount of refractions into and out of transparent materials. Defaults to --max-depth
+    #[arg(long)]
+    max_transmission_depth: Option<i32>,
+
+    /// Number of bounces after which paths carrying little light are randomly ended (Russian roulette)
+    #[arg(long, default_value_t = 3)]
+    roulette_depth: i32,
+
+    /// Algorithm used to find the light arriving at the camera
+    #[arg(long, value_enum, default_value_t = IntegratorKind::Path)]
+    integrator: IntegratorKind,
+
+    /// How far away objects still block the sky for --integrator ambient-occlusion. Defaults to any distance
+    #[arg(long)]
+    ao_distance: Option<f32>,
+
+    /// Name of a built-in scene, or path to a scene file (.toml) or model (.obj)
+    #[arg(long, default_value = "simple_light")]
+    scene: String,
+
+    /// File to write the image to. The format is picked from the extension (.png, .ppm, .exr or .pfm)
+    #[arg(long, default_value = "image.png")]
+    output: PathBuf,
+
+    /// Exposure adjustment in stops (EV), applied before tone mapping
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f32,
+
+    /// Tone mapping curve used for 8 bit images
+    #[arg(long, value_enum, default_value_t = ToneMapping::None)]
+    tone_mapping: ToneMapping,
+
+    /// Precision of the values in OpenEXR images
+    #[arg(long, value_enum, default_value_t = ExrPrecision::Half)]
+    exr_precision: ExrPrecision,
+
+    /// Number of threads to render with. Defaults to one per core
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// Seed for the random number generators, to make renders reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/**
+ * Settings for a render, as resolved from the command line
+ */
+pub struct RenderSettings {
+    pub image_width: i32,
+    pub image_height: i32,
+    pub samples_per_pixel: i32,
+    pub max_depth: Bounces,
+    pub roulette_depth: i32,
+    pub integrator: IntegratorKind,
+    pub ao_distance: f32,
+    pub scene: String,
+    pub output: PathBuf,
+    pub output_format: ImageFormat,
+    pub post_process: PostProcess,
+    pub threads: Option<usize>,
+    pub seed: Option<u64>,
+}
+
+impl RenderSettings {
+    pub fn from_args() -> Result<Self, String> {
+        return RenderSettings::from_cli(Cli::parse());
+    }
+
+    fn from_cli(cli: Cli) -> Result<Self, String> {
+        let image_height = cli
+            .height
+            .unwrap_or((cli.width as f32 / DEFAULT_ASPECT_RATIO) as i32);
+
+        if cli.width < 2 || image_height < 2 {
+            return Err(format!(
+                "the image must be at least 2x2 pixels, got {}x{}",
+                cli.width, image_height
+            ));
+        }
+
+        if cli.spp < 1 {
+            return Err("there must be at least one sample per pixel".to_string());
+        }
+
+        let max_depth = Bounces {
+            total: cli.max_depth,
+            diffuse: cli.max_diffuse_depth.unwrap_or(cli.max_depth),
+            specular: cli.max_specular_depth.unwrap_or(cli.max_depth),
+            transmission: cli.max_transmission_depth.unwrap_or(cli.max_depth),
+        };
+
+        if max_depth.total < 0
+            || max_depth.diffuse < 0
+            || max_depth.specular < 0
+            || max_depth.transmission < 0
+            || cli.roulette_depth < 0
+        {
+            return Err("the depths can't be negative".to_string());
+        }
+
+        // Check the output path up front, so a long render isn't wasted on a bad file name
+        let output_format = ImageFormat::from_path(&cli.output, cli.exr_precision)?;
+
+        if cli.threads == Some(0) {
+            return Err("there must be at least one thread".to_string());
+        }
+
+        return Ok(Self {
+            image_width: cli.width,
+            image_height,
+            samples_per_pixel: cli.spp,
+            max_depth,
+            roulette_depth: cli.roulette_depth,
+            integrator: cli.integrator,
+            ao_distance: cli.ao_distance.unwrap_or(f32::INFINITY),
+            scene: cli.scene,
+            output: cli.output,
+            output_format,
+            post_process: PostProcess {
+                exposure: cli.exposure,
+                tone_mapping: cli.tone_mapping,
+            },
+            threads: cli.threads,
+            seed: cli.seed,
+        });
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        return self.image_width as f32 / self.image_height as f32;
+    }
+
+    // The average color of multiple samples per pixel is computed, so this factor decides what to divide the cumulative sum of all the samples with
+    pub fn color_scale(&self) -> f32 {
+        return 1.0 / self.samples_per_pixel as f32;
+    }
+}
+
+/**
+ * Number of times a path has bounced, in total and off each lobe. Also used for the limits on them
+ */
+#[derive(Clone, Copy, Default)]
+pub struct Bounces {
+    pub total: i32,
+    pub diffuse: i32,
+    pub specular: i32,
+    pub transmission: i32,
+}
+
+impl Bounces {
+    // Count a bounce off the given lobe
+    pub fn add(&mut self, lobe: Lobe) {
+        self.total += 1;
+
+        match lobe {
+            Lobe::Diffuse => self.diffuse += 1,
+            Lobe::Specular => self.specular += 1,
+            Lobe::Transmission => self.transmission += 1,
+        }
+    }
+
+    // Whether any of the counts is over its limit
+    pub fn exceed(&self, limits: &Bounces) -> bool {
+        return self.total > limits.total
+            || self.diffuse > limits.diffuse
+            || self.specular > limits.specular
+            || self.transmission > limits.transmission;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(arguments: &[&str]) -> Result<RenderSettings, String> {
+        let cli = Cli::try_parse_from([&["raytracer"], arguments].concat()).unwrap();
+        return RenderSettings::from_cli(cli);
+    }
+
+    fn error(arguments: &[&str]) -> String {
+        return match settings(arguments) {
+            Ok(_) => panic!("{:?} was accepted", arguments),
+            Err(message) => message,
+        };
+    }
+
+    #[test]
+    fn defaults_make_a_16_by_9_png() {
+        let defaults = settings(&[]).unwrap();
+
+        assert_eq!((defaults.image_width, defaults.image_height), (1920, 1080));
+        assert_eq!(defaults.samples_per_pixel, 2000);
+        assert_eq!(defaults.output, PathBuf::from("image.png"));
+        assert!(matches!(defaults.output_format, ImageFormat::Png));
+        assert_eq!(defaults.seed, None);
+
+        let square = settings(&["--width", "640", "--height", "640"]).unwrap();
+        assert_eq!(square.aspect_ratio(), 1.0);
+    }
+
+    #[test]
+    fn depths_per_lobe_default_to_the_max_depth() {
+        let settings = settings(&["--max-depth", "12", "--max-specular-depth", "3"]).unwrap();
+        let depth = settings.max_depth;
+
+        assert_eq!(
+            (
+                depth.total,
+                depth.diffuse,
+                depth.specular,
+                depth.transmission
+            ),
+            (12, 12, 3, 12)
+        );
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert_eq!(
+            error(&["--width", "1"]),
+            "the image must be at least 2x2 pixels, got 1x0"
+        );
+        assert_eq!(
+            error(&["--spp", "0"]),
+            "there must be at least one sample per pixel"
+        );
+        assert_eq!(
+            error(&["--max-transmission-depth=-1"]),
+            "the depths can't be negative"
+        );
+        assert_eq!(
+            error(&["--threads", "0"]),
+            "there must be at least one thread"
+        );
+        assert!(error(&["--output", "image.gif"]).starts_with("can't tell the image format"));
+
+        // Values of the wrong type don't get past the parser
+        assert!(Cli::try_parse_from(["raytracer", "--spp", "many"]).is_err());
+    }
+}
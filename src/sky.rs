@@ -0,0 +1,321 @@
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use glam::Vec3;
+
+use crate::vec3::Vec3Extension;
+
+// Luminance of 1 kcd/m² in the units of the renderer, chosen so a white surface in full sun comes out around 1
+const KILOCANDELA: f32 = 0.04;
+
+// Radiance of the sun before it passes through the atmosphere, in kcd/m²
+const SUN_RADIANCE: f32 = 2.1e6;
+
+// Angular radius of the sun as seen from the earth, in degrees
+const SUN_RADIUS: f32 = 0.265;
+
+// Wavelengths in micrometers the red, green and blue parts of the sunlight are attenuated at
+const WAVELENGTHS: [f32; 3] = [0.68, 0.55, 0.44];
+
+/**
+ * Daylight from the analytic model of A. J. Preetham, P. Shirley and B. Smits, "A Practical Analytic Model
+ * for Daylight" (1999), with the sun as a small bright disk.
+ *
+ * The sky is found by chance like a background color, only the sun is sampled as a light. There is no ground
+ * in the model, below the horizon the sky continues with the colors it has at the horizon.
+ */
+pub struct Sky {
+    // Unit vector pointing toward the middle of the sun
+    sun_direction: Vec3,
+    // Distributions of luminance Y and chromaticity x and y over the sky
+    perez: [Perez; 3],
+    // Y, x and y at the zenith, divided by the distributions at the zenith so they can be scaled directly
+    zenith: [f32; 3],
+    // Light falling on a surface facing the sun, which is spread over the disk whatever its size
+    sun_irradiance: Vec3,
+    // 1 - cos of the angular radius of the sun
+    sun_one_minus_cos: f32,
+    intensity: f32,
+}
+
+impl Sky {
+    /**
+     * The sun is placed by its elevation above the horizon and its azimuth, both in degrees. At an azimuth of
+     * zero it is toward -Z, and it moves toward +X as the azimuth increases. The turbidity is how hazy the
+     * air is, from 2 for a very clear sky to 10 for a hazy one.
+     */
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Self {
+        // The model is only fitted for the sun above the horizon
+        let elevation = elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+
+        let theta_sun = FRAC_PI_2 - elevation;
+        let t = turbidity;
+
+        let perez = [
+            Perez::new(
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ),
+            Perez::new(
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ),
+            Perez::new(
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ),
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let thetas = Vec3::new(theta_sun.powi(3), theta_sun.powi(2), theta_sun);
+        let turbidities = Vec3::new(t * t, t, 1.0);
+        let zenith_x = turbidities.dot(Vec3::new(
+            thetas.dot(Vec3::new(0.00166, -0.00375, 0.00209)),
+            thetas.dot(Vec3::new(-0.02903, 0.06377, -0.03202)) + 0.00394,
+            thetas.dot(Vec3::new(0.11693, -0.21196, 0.06052)) + 0.25886,
+        ));
+        let zenith_y = turbidities.dot(Vec3::new(
+            thetas.dot(Vec3::new(0.00275, -0.00610, 0.00317)),
+            thetas.dot(Vec3::new(-0.04214, 0.08970, -0.04153)) + 0.00516,
+            thetas.dot(Vec3::new(0.15346, -0.26756, 0.06670)) + 0.26688,
+        ));
+
+        let zenith_values = [zenith_luminance.max(0.0), zenith_x, zenith_y];
+        let zenith = std::array::from_fn(|i| {
+            zenith_values[i] / perez[i].value(1.0, theta_sun.cos(), theta_sun)
+        });
+
+        let mut sky = Self {
+            sun_direction,
+            perez,
+            zenith,
+            sun_irradiance: sun_transmittance(theta_sun, t) * SUN_RADIANCE * KILOCANDELA,
+            sun_one_minus_cos: 0.0,
+            intensity: 1.0,
+        };
+        sky = sky.with_sun_radius(SUN_RADIUS);
+        sky.sun_irradiance *= sky.sun_solid_angle();
+
+        return sky;
+    }
+
+    /**
+     * Change the angular radius of the sun, in degrees. A bigger sun gives softer shadows, but the same
+     * amount of light.
+     */
+    pub fn with_sun_radius(mut self, radius: f32) -> Self {
+        let radius = radius.to_radians().clamp(1e-4, FRAC_PI_2);
+        // 1 - cos θ = 2 sin²(θ/2), which doesn't round to zero for the real sun
+        self.sun_one_minus_cos = 2.0 * (radius / 2.0).sin().powi(2);
+        return self;
+    }
+
+    // Scale the brightness of both the sky and the sun
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        return self;
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize();
+
+        let cos_gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let cos_theta = direction.y.max(0.001);
+
+        let [luminance, x, y] = std::array::from_fn(|i| {
+            self.zenith[i] * self.perez[i].value(cos_theta, cos_gamma, gamma)
+        });
+
+        let mut radiance = xyy_to_rgb(x, y, luminance * KILOCANDELA);
+
+        if self.in_sun(direction) {
+            radiance += self.sun_irradiance / self.sun_solid_angle();
+        }
+
+        return self.intensity * radiance;
+    }
+
+    // Density of random_direction picking `direction`, which is uniform over the disk of the sun
+    pub fn pdf_value(&self, direction: Vec3) -> f32 {
+        return match self.in_sun(direction.normalize()) {
+            true => 1.0 / self.sun_solid_angle(),
+            false => 0.0,
+        };
+    }
+
+    pub fn random_direction(&self) -> Vec3 {
+        return self.sun_direction.random_in_cone(self.sun_one_minus_cos);
+    }
+
+    fn sun_solid_angle(&self) -> f32 {
+        return TAU * self.sun_one_minus_cos;
+    }
+
+    // Compared through the sine, as the cosine of such a small angle can hardly be told apart from 1
+    fn in_sun(&self, direction: Vec3) -> bool {
+        let sin_squared_max = self.sun_one_minus_cos * (2.0 - self.sun_one_minus_cos);
+
+        return direction.dot(self.sun_direction) > 0.0
+            && direction.cross(self.sun_direction).length_squared() <= sin_squared_max;
+    }
+}
+
+/**
+ * The Perez formula for how a quantity varies over the sky, by the angle θ from the zenith and the angle γ
+ * from the sun:
+ *
+ *   F(θ, γ) = (1 + A e^(B / cos θ)) (1 + C e^(D γ) + E cos² γ)
+ */
+struct Perez {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+}
+
+impl Perez {
+    fn new(a: f32, b: f32, c: f32, d: f32, e: f32) -> Self {
+        return Self { a, b, c, d, e };
+    }
+
+    fn value(&self, cos_theta: f32, cos_gamma: f32, gamma: f32) -> f32 {
+        return (1.0 + self.a * (self.b / cos_theta).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma);
+    }
+}
+
+/**
+ * How much of the sunlight in each of the red, green and blue parts of the spectrum makes it through the
+ * atmosphere, from the scattering by molecules (Rayleigh) and by haze (aerosols) in the model's appendix
+ */
+fn sun_transmittance(theta_sun: f32, turbidity: f32) -> Vec3 {
+    // How much air the light passes through compared to straight down, which grows quickly at the horizon
+    let relative_mass =
+        1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun.to_degrees()).powf(-1.253));
+
+    let beta = 0.04608 * turbidity - 0.04586;
+    let alpha = 1.3;
+
+    return Vec3::from(WAVELENGTHS.map(|lambda| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * relative_mass).exp();
+        let aerosol = (-beta * lambda.powf(-alpha) * relative_mass).exp();
+
+        return rayleigh * aerosol;
+    }));
+}
+
+// Convert a color from its chromaticity and luminance to linear sRGB
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::ZERO;
+    }
+
+    let xyz = Vec3::new(x / y, 1.0, (1.0 - x - y) / y) * luminance;
+
+    let rgb = Vec3::new(
+        Vec3::new(3.2406, -1.5372, -0.4986).dot(xyz),
+        Vec3::new(-0.9689, 1.8758, 0.0415).dot(xyz),
+        Vec3::new(0.0557, -0.2040, 1.0570).dot(xyz),
+    );
+
+    return rgb.max(Vec3::ZERO);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    /**
+     * Integrate a function of the direction over the cap of directions within max_angle of the axis,
+     * with the midpoint rule in polar coordinates around it
+     */
+    fn integrate_cap(axis: Vec3, max_angle: f32, function: impl Fn(Vec3) -> f32) -> f32 {
+        let (x, y) = axis.any_orthonormal_pair();
+        let (theta_steps, phi_steps) = (2000, 64);
+        let (d_theta, d_phi) = (max_angle / theta_steps as f32, TAU / phi_steps as f32);
+
+        let mut sum = 0.0f64;
+        for i in 0..theta_steps {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..phi_steps {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let direction = theta.sin() * (phi.cos() * x + phi.sin() * y) + theta.cos() * axis;
+
+                sum += (function(direction) * theta.sin() * d_theta * d_phi) as f64;
+            }
+        }
+
+        return sum as f32;
+    }
+
+    #[test]
+    fn sun_densities_integrate_to_one_over_the_disk() {
+        for radius in [0.265, 2.0, 10.0] {
+            let sky = Sky::new(30.0, 40.0, 3.0).with_sun_radius(radius);
+            let integral =
+                integrate_cap(sky.sun_direction, 2.0 * radius.to_radians(), |direction| {
+                    sky.pdf_value(direction)
+                });
+
+            assert!(
+                (integral - 1.0).abs() < 0.01,
+                "{} for radius {}",
+                integral,
+                radius
+            );
+        }
+    }
+
+    #[test]
+    fn sampled_directions_land_on_the_sun() {
+        random::reseed(1);
+        let sky = Sky::new(60.0, -20.0, 2.5);
+
+        for _ in 0..1000 {
+            let direction = sky.random_direction();
+
+            assert!(sky.pdf_value(direction) > 0.0);
+            assert!(sky.radiance(direction).min_element() > sky.radiance(-direction).max_element());
+        }
+    }
+
+    #[test]
+    fn bigger_suns_give_the_same_light() {
+        random::reseed(2);
+
+        // The light from the sun, estimated from samples of its disk
+        let sunlight = |radius: f32| {
+            let sky = Sky::new(45.0, 0.0, 3.0).with_sun_radius(radius);
+            let count = 10_000;
+            let mut sum = Vec3::ZERO;
+            for _ in 0..count {
+                let direction = sky.random_direction();
+                sum += sky.radiance(direction) / sky.pdf_value(direction);
+            }
+
+            return sum / count as f32;
+        };
+
+        let (small, big) = (sunlight(0.265), sunlight(3.0));
+        assert!(((big - small) / small).abs().max_element() < 0.01);
+    }
+}
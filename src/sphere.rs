@@ -1,10 +1,12 @@
 use std::f32::consts::PI;
 use std::f32::consts::TAU;
 
+use crate::aabb::Aabb;
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::vec3::Vec3Extension;
 use glam::Vec3;
 
 pub struct Sphere {
@@ -13,42 +15,46 @@ pub struct Sphere {
     pub material: Box<dyn Material>,
 }
 
-unsafe impl Send for Sphere {}
-unsafe impl Sync for Sphere {}
-
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.length_squared() as f32;
-        let half_b = oc.dot(ray.direction);
-        let c = oc.length_squared() as f32 - self.radius * self.radius;
-
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
-            return None;
-        }
-
-        let sqrtd = discriminant.sqrt();
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        return hit_sphere(
+            self.center,
+            self.radius,
+            self.material.as_ref(),
+            ray,
+            t_min,
+            t_max,
+        );
+    }
 
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrtd) / a;
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::splat(self.radius.abs());
+        return Some(Aabb::new(self.center - radius, self.center + radius));
+    }
 
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
-        }
+    fn is_light(&self) -> bool {
+        return self.material.is_emissive();
+    }
 
-        let hit_point = ray.at(root);
-        let outward_normal = (hit_point - self.center) / self.radius;
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, _time: f32) -> f32 {
+        return cone_pdf(self.center, self.radius, origin, direction);
+    }
 
-        let (u, v) = Sphere::get_uv(outward_normal);
+    fn random_toward(&self, origin: Vec3, _time: f32) -> Vec3 {
+        return random_in_cone(self.center, self.radius, origin);
+    }
 
-        let hit_record =
-            HitRecord::from_ray(ray, hit_point, outward_normal, root, u, v, &self.material);
+    fn sample_surface(&self, _time: f32) -> Option<(HitRecord<'_>, f32)> {
+        return self
+            .is_light()
+            .then(|| random_on_sphere(self.center, self.radius, self.material.as_ref()));
+    }
 
-        return Some(hit_record);
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return match self.hit(&Ray::new(origin, direction, time), 0.001, f32::INFINITY) {
+            Some(_) if self.is_light() => sphere_area_pdf(self.radius),
+            _ => 0.0,
+        };
     }
 }
 
@@ -60,13 +66,289 @@ impl Sphere {
             material,
         };
     }
+}
+
+/**
+ * Sphere moving in a straight line, from center0 at time0 to center1 at time1
+ */
+pub struct MovingSphere {
+    center0: Vec3,
+    center1: Vec3,
+    time0: f32,
+    time1: f32,
+    radius: f32,
+    pub material: Box<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3,
+        center1: Vec3,
+        time0: f32,
+        time1: f32,
+        radius: f32,
+        material: Box<dyn Material>,
+    ) -> Self {
+        return Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        };
+    }
+
+    // The sphere waits at center0 before time0 and at center1 after time1
+    pub fn center(&self, time: f32) -> Vec3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+
+        let fraction = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        return self.center0 + fraction * (self.center1 - self.center0);
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        return hit_sphere(
+            self.center(ray.time),
+            self.radius,
+            self.material.as_ref(),
+            ray,
+            t_min,
+            t_max,
+        );
+    }
+
+    // Encloses the sphere for the whole time it moves from center0 to center1
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::splat(self.radius.abs());
+
+        let box0 = Aabb::new(self.center0 - radius, self.center0 + radius);
+        let box1 = Aabb::new(self.center1 - radius, self.center1 + radius);
+
+        return Some(box0.surrounding(&box1));
+    }
+
+    fn is_light(&self) -> bool {
+        return self.material.is_emissive();
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return cone_pdf(self.center(time), self.radius, origin, direction);
+    }
+
+    fn random_toward(&self, origin: Vec3, time: f32) -> Vec3 {
+        return random_in_cone(self.center(time), self.radius, origin);
+    }
+
+    fn sample_surface(&self, time: f32) -> Option<(HitRecord<'_>, f32)> {
+        return self
+            .is_light()
+            .then(|| random_on_sphere(self.center(time), self.radius, self.material.as_ref()));
+    }
+
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        return match self.hit(&Ray::new(origin, direction, time), 0.001, f32::INFINITY) {
+            Some(_) if self.is_light() => sphere_area_pdf(self.radius),
+            _ => 0.0,
+        };
+    }
+}
+
+fn hit_sphere<'a>(
+    center: Vec3,
+    radius: f32,
+    material: &'a dyn Material,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    let oc = ray.origin - center;
+    let a = ray.direction.length_squared();
+    let half_b = oc.dot(ray.direction);
+    let c = oc.length_squared() - radius * radius;
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+
+    // Find the nearest root that lies in the acceptable range.
+    let mut root = (-half_b - sqrtd) / a;
+
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrtd) / a;
+        if root < t_min || t_max < root {
+            return None;
+        }
+    }
+
+    let hit_point = ray.at(root);
+    let outward_normal = (hit_point - center) / radius;
+
+    let (u, v) = get_uv(outward_normal);
+
+    let hit_record = HitRecord::from_ray(ray, hit_point, outward_normal, root, u, v, material);
+
+    return Some(hit_record);
+}
+
+/**
+ * Seen from outside, a sphere covers a cone of directions around its center. Returns the cosine of
+ * the half angle of that cone and 1 minus it, or None if `origin` is inside the sphere
+ */
+fn cone_angle(center: Vec3, radius: f32, origin: Vec3) -> Option<(f32, f32)> {
+    let ratio = radius * radius / (center - origin).length_squared();
+
+    if ratio >= 1.0 {
+        return None;
+    }
+
+    let cos_theta_max = (1.0 - ratio).sqrt();
+
+    // Written this way so small, far away spheres don't round down to a cone of zero width
+    return Some((cos_theta_max, ratio / (1.0 + cos_theta_max)));
+}
+
+/**
+ * Directions in the cone are sampled uniformly, so the density is one over its solid angle. From inside,
+ * the sphere is in every direction, which are all sampled alike
+ */
+fn cone_pdf(center: Vec3, radius: f32, origin: Vec3, direction: Vec3) -> f32 {
+    let Some((cos_theta_max, one_minus_cos)) = cone_angle(center, radius, origin) else {
+        return 1.0 / (2.0 * TAU);
+    };
+
+    let cosine = direction.normalize().dot((center - origin).normalize());
+
+    if cosine < cos_theta_max {
+        return 0.0;
+    }
+
+    return 1.0 / (TAU * one_minus_cos);
+}
+
+fn random_in_cone(center: Vec3, radius: f32, origin: Vec3) -> Vec3 {
+    let Some((_, one_minus_cos)) = cone_angle(center, radius, origin) else {
+        return Vec3::random_unit_vector();
+    };
+
+    return (center - origin).normalize().random_in_cone(one_minus_cos);
+}
 
-    fn get_uv(point: Vec3) -> (f32, f32) {
-        let theta = (-point.y).acos();
-        let phi = (-point.z).atan2(point.x) + PI;
+// A point spread uniformly over the sphere, with the density 1 / 4πr²
+fn random_on_sphere(center: Vec3, radius: f32, material: &dyn Material) -> (HitRecord<'_>, f32) {
+    let direction = Vec3::random_unit_vector();
+    // Hollow spheres have a negative radius, which turns the outward normal inward
+    let outward_normal = direction * radius.signum();
+    let (u, v) = get_uv(outward_normal);
+
+    let rec = HitRecord::on_surface(center + radius * direction, outward_normal, u, v, material);
+
+    return (rec, sphere_area_pdf(radius));
+}
 
-        let u = phi / (TAU);
-        let v = theta / PI;
-        return (u, v);
+// One over the area of the sphere
+fn sphere_area_pdf(radius: f32) -> f32 {
+    return 1.0 / (2.0 * TAU * radius * radius);
+}
+
+// Texture coordinates of a point on the unit sphere
+fn get_uv(point: Vec3) -> (f32, f32) {
+    let theta = (-point.y).acos();
+    let phi = (-point.z).atan2(point.x) + PI;
+
+    let u = phi / (TAU);
+    let v = theta / PI;
+    return (u, v);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::random;
+
+    fn lamp(center: Vec3, radius: f32) -> Sphere {
+        return Sphere::new(
+            center,
+            radius,
+            Box::new(DiffuseLight::from_color(Vec3::ONE)),
+        );
+    }
+
+    /**
+     * Estimate the integral of pdf_value over all directions, by averaging it over uniformly random
+     * directions and multiplying by the area of the unit sphere
+     */
+    fn pdf_integral(sphere: &Sphere, origin: Vec3) -> f32 {
+        let count = 200_000;
+        let sum: f64 = (0..count)
+            .map(|_| sphere.pdf_value(origin, Vec3::random_unit_vector(), 0.0) as f64)
+            .sum();
+
+        return (2.0 * TAU as f64 * sum / count as f64) as f32;
+    }
+
+    #[test]
+    fn light_densities_integrate_to_one_from_outside_and_inside() {
+        random::reseed(1);
+        let sphere = lamp(Vec3::new(1.0, 2.0, 3.0), 1.5);
+
+        for origin in [
+            Vec3::ZERO,
+            Vec3::new(1.5, 2.0, 3.0),
+            Vec3::new(1.0, 2.0, 3.0),
+        ] {
+            assert!((pdf_integral(&sphere, origin) - 1.0).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn sampled_directions_have_a_density() {
+        random::reseed(2);
+        let sphere = lamp(Vec3::new(1.0, 2.0, 3.0), 1.5);
+
+        // From outside the directions point at the sphere, from inside anywhere
+        for origin in [Vec3::ZERO, Vec3::new(1.5, 2.0, 3.0)] {
+            for _ in 0..1000 {
+                let direction = sphere.random_toward(origin, 0.0);
+
+                assert!(sphere.pdf_value(origin, direction, 0.0) > 0.0);
+                assert!(sphere
+                    .hit(&Ray::new(origin, direction, 0.0), 0.001, f32::INFINITY)
+                    .is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn moving_spheres_stay_inside_their_bounds() {
+        let sphere = MovingSphere::new(
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(3.0, 2.0, 0.0),
+            1.0,
+            3.0,
+            0.5,
+            Box::new(DiffuseLight::from_color(Vec3::ONE)),
+        );
+        let bbox = sphere.bounding_box().unwrap();
+
+        assert_eq!(sphere.center(0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(sphere.center(2.0), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(sphere.center(4.0), Vec3::new(3.0, 2.0, 0.0));
+
+        for step in 0..=100 {
+            let center = sphere.center(step as f32 / 25.0);
+            let radius = Vec3::splat(0.5);
+
+            assert!((center - radius).cmpge(bbox.min).all());
+            assert!((center + radius).cmple(bbox.max).all());
+        }
     }
 }
@@ -1,6 +1,15 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
 use glam::Vec3;
+use image::DynamicImage;
+use serde::Deserialize;
+
+use crate::perlin::Perlin;
+use crate::tonemap::srgb_to_linear;
 
-pub trait Texture {
+pub trait Texture: Send + Sync {
     fn value(&self, u: f32, v: f32, point: &Vec3) -> Vec3;
 }
 
@@ -41,3 +50,403 @@ impl Texture for CheckerTexture {
         }
     }
 }
+
+/**
+ * Decoded image with linear RGB values, stored row by row from the top
+ */
+pub struct ImageData {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+}
+
+impl ImageData {
+    /**
+     * Load a PNG, JPEG, HDR or EXR image. 8 bit images are decoded from sRGB unless `srgb` is false,
+     * while floating point images (like HDR and EXR) are always assumed to be linear.
+     */
+    pub fn load(path: &Path, srgb: bool) -> Result<Self, Box<dyn Error>> {
+        let is_exr = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
+
+        if is_exr {
+            return ImageData::load_exr(path);
+        }
+
+        let image = image::open(path)?;
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+
+        let is_float = matches!(
+            image,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+
+        let pixels = match is_float {
+            true => image
+                .into_rgb32f()
+                .pixels()
+                .map(|pixel| Vec3::new(pixel[0], pixel[1], pixel[2]))
+                .collect(),
+            false => {
+                let decode = |value: u8| {
+                    let value = value as f32 / 255.0;
+                    return if srgb { srgb_to_linear(value) } else { value };
+                };
+
+                image
+                    .into_rgb8()
+                    .pixels()
+                    .map(|pixel| Vec3::new(decode(pixel[0]), decode(pixel[1]), decode(pixel[2])))
+                    .collect()
+            }
+        };
+
+        return Ok(Self {
+            width,
+            height,
+            pixels,
+        });
+    }
+
+    // EXR images are read with the same crate that writes them, which handles 16 and 32 bit channels
+    fn load_exr(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            path,
+            |resolution, _| ImageData {
+                width: resolution.width(),
+                height: resolution.height(),
+                pixels: vec![Vec3::ZERO; resolution.area()],
+            },
+            |image: &mut ImageData, position, (r, g, b, _): (f32, f32, f32, f32)| {
+                image.pixels[position.y() * image.width + position.x()] = Vec3::new(r, g, b);
+            },
+        )?;
+
+        return Ok(image.layer_data.channel_data.pixels);
+    }
+
+    pub fn width(&self) -> usize {
+        return self.width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.height;
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        return self.pixels[y * self.width + x];
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+}
+
+// What to do with texture coordinates outside of [0, 1]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureWrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl TextureWrap {
+    // Map a (possibly out of bounds) pixel coordinate into [0, size)
+    fn apply(&self, index: i64, size: usize) -> usize {
+        let size = size as i64;
+
+        let wrapped = match self {
+            TextureWrap::Repeat => index.rem_euclid(size),
+            TextureWrap::Clamp => index.clamp(0, size - 1),
+            TextureWrap::Mirror => {
+                let period = index.rem_euclid(2 * size);
+                if period >= size {
+                    2 * size - 1 - period
+                } else {
+                    period
+                }
+            }
+        };
+
+        return wrapped as usize;
+    }
+}
+
+pub struct ImageTexture {
+    image: Arc<ImageData>,
+    filter: TextureFilter,
+    wrap: TextureWrap,
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<ImageData>, filter: TextureFilter, wrap: TextureWrap) -> Self {
+        return Self {
+            image,
+            filter,
+            wrap,
+        };
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Vec3 {
+        let x = self.wrap.apply(x, self.image.width());
+        let y = self.wrap.apply(y, self.image.height());
+
+        return self.image.pixel(x, y);
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _point: &Vec3) -> Vec3 {
+        let width = self.image.width();
+        let height = self.image.height();
+
+        if width == 0 || height == 0 {
+            // Cyan, to make missing texture data easy to spot
+            return Vec3::new(0.0, 1.0, 1.0);
+        }
+
+        // Image rows go from top to bottom, while v goes from bottom to top
+        let x = u * width as f32;
+        let y = (1.0 - v) * height as f32;
+
+        match self.filter {
+            TextureFilter::Nearest => {
+                return self.texel(x.floor() as i64, y.floor() as i64);
+            }
+            TextureFilter::Bilinear => {
+                // Pixel centers are at half coordinates
+                let x = x - 0.5;
+                let y = y - 0.5;
+
+                let x0 = x.floor();
+                let y0 = y.floor();
+                let tx = x - x0;
+                let ty = y - y0;
+
+                // Casting saturates for huge or infinite coordinates, so the neighbors must not overflow
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let (x1, y1) = (x0.saturating_add(1), y0.saturating_add(1));
+
+                let top = self.texel(x0, y0).lerp(self.texel(x1, y0), tx);
+                let bottom = self.texel(x0, y1).lerp(self.texel(x1, y1), tx);
+
+                return top.lerp(bottom, ty);
+            }
+        }
+    }
+}
+
+/**
+ * Piecewise linear gradient between colors at positions in [0, 1]
+ */
+pub struct ColorRamp {
+    stops: Vec<(f32, Vec3)>,
+}
+
+impl ColorRamp {
+    pub fn new(mut stops: Vec<(f32, Vec3)>) -> Self {
+        stops.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        return Self { stops };
+    }
+
+    pub fn between(from: Vec3, to: Vec3) -> Self {
+        return ColorRamp::new(vec![(0.0, from), (1.0, to)]);
+    }
+
+    pub fn sample(&self, t: f32) -> Vec3 {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Vec3::ZERO,
+        };
+
+        if t <= first.0 {
+            return first.1;
+        }
+
+        for window in self.stops.windows(2) {
+            let (start, start_color) = window[0];
+            let (end, end_color) = window[1];
+
+            if t <= end {
+                let span = end - start;
+                let local = if span > 0.0 { (t - start) / span } else { 1.0 };
+                return start_color.lerp(end_color, local);
+            }
+        }
+
+        return last.1;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoisePattern {
+    // Plain fractal noise
+    Fbm,
+    // Sum of absolute noise octaves
+    Turbulence,
+    // Sine stripes disturbed by turbulence
+    Marble,
+    // Concentric rings around the y axis, disturbed by noise
+    Wood,
+}
+
+/**
+ * Procedural texture based on Perlin noise. The noise value is mapped to a color through a ramp.
+ */
+pub struct NoiseTexture {
+    perlin: Perlin,
+    pattern: NoisePattern,
+    scale: f32,
+    octaves: u32,
+    ramp: ColorRamp,
+}
+
+impl NoiseTexture {
+    pub fn new(
+        seed: u64,
+        pattern: NoisePattern,
+        scale: f32,
+        octaves: u32,
+        ramp: ColorRamp,
+    ) -> Self {
+        return Self {
+            perlin: Perlin::new(seed),
+            pattern,
+            scale,
+            octaves,
+            ramp,
+        };
+    }
+
+    pub fn marble(seed: u64, scale: f32) -> Self {
+        return NoiseTexture::new(
+            seed,
+            NoisePattern::Marble,
+            scale,
+            7,
+            NoiseTexture::default_ramp(NoisePattern::Marble),
+        );
+    }
+
+    pub fn wood(seed: u64, scale: f32) -> Self {
+        return NoiseTexture::new(
+            seed,
+            NoisePattern::Wood,
+            scale,
+            4,
+            NoiseTexture::default_ramp(NoisePattern::Wood),
+        );
+    }
+
+    pub fn default_ramp(pattern: NoisePattern) -> ColorRamp {
+        return match pattern {
+            NoisePattern::Fbm | NoisePattern::Turbulence => {
+                ColorRamp::between(Vec3::ZERO, Vec3::ONE)
+            }
+            NoisePattern::Marble => {
+                ColorRamp::between(Vec3::new(0.1, 0.1, 0.12), Vec3::new(0.95, 0.95, 0.92))
+            }
+            NoisePattern::Wood => {
+                ColorRamp::between(Vec3::new(0.45, 0.25, 0.1), Vec3::new(0.75, 0.5, 0.28))
+            }
+        };
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, point: &Vec3) -> Vec3 {
+        let p = self.scale * *point;
+
+        let t = match self.pattern {
+            NoisePattern::Fbm => 0.5 * (1.0 + self.perlin.fbm(p, self.octaves)),
+            NoisePattern::Turbulence => self.perlin.turbulence(p, self.octaves),
+            NoisePattern::Marble => {
+                0.5 * (1.0 + (p.z + 10.0 * self.perlin.turbulence(p, self.octaves)).sin())
+            }
+            NoisePattern::Wood => {
+                let distance = Vec3::new(p.x, 0.0, p.z).length();
+                let rings = 4.0 * (distance + 0.5 * self.perlin.fbm(p, self.octaves));
+                rings - rings.floor()
+            }
+        };
+
+        return self.ramp.sample(t.clamp(0.0, 1.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2 × 2 image with a different primary color in three corners
+    fn texture(filter: TextureFilter, wrap: TextureWrap) -> ImageTexture {
+        let image = ImageData {
+            width: 2,
+            height: 2,
+            pixels: vec![Vec3::X, Vec3::Y, Vec3::Z, Vec3::ZERO],
+        };
+
+        return ImageTexture::new(Arc::new(image), filter, wrap);
+    }
+
+    #[test]
+    fn wrap_modes_map_pixels_into_the_image() {
+        let wrapped = |wrap: TextureWrap| [-5, -1, 0, 3, 4, 9].map(|index| wrap.apply(index, 4));
+
+        assert_eq!(wrapped(TextureWrap::Repeat), [3, 3, 0, 3, 0, 1]);
+        assert_eq!(wrapped(TextureWrap::Clamp), [0, 0, 0, 3, 3, 3]);
+        assert_eq!(wrapped(TextureWrap::Mirror), [3, 0, 0, 3, 3, 1]);
+    }
+
+    #[test]
+    fn bilinear_filtering_blends_pixel_centers() {
+        let texture = texture(TextureFilter::Bilinear, TextureWrap::Clamp);
+        let point = Vec3::ZERO;
+
+        // v goes up while rows go down, so the first row is at the top
+        assert_eq!(texture.value(0.25, 0.75, &point), Vec3::X);
+        assert_eq!(texture.value(0.75, 0.25, &point), Vec3::ZERO);
+        assert_eq!(texture.value(0.5, 0.75, &point), Vec3::new(0.5, 0.5, 0.0));
+        assert_eq!(texture.value(0.5, 0.5, &point), Vec3::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn huge_texture_coordinates_do_not_overflow() {
+        let point = Vec3::ZERO;
+
+        for filter in [TextureFilter::Nearest, TextureFilter::Bilinear] {
+            for wrap in [TextureWrap::Repeat, TextureWrap::Clamp, TextureWrap::Mirror] {
+                let texture = texture(filter, wrap);
+
+                for uv in [1e30, -1e30, f32::MAX, f32::INFINITY, f32::NEG_INFINITY] {
+                    texture.value(uv, 0.5, &point);
+                    texture.value(0.5, uv, &point);
+                }
+
+                assert!(texture.value(1e30, -1e30, &point).is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn noise_textures_follow_their_seed() {
+        let points = (0..100).map(|i| Vec3::new(i as f32 * 0.37, 0.5, i as f32 * -0.23));
+        let values = |seed: u64| {
+            let texture = NoiseTexture::marble(seed, 4.0);
+            return points
+                .clone()
+                .map(|point| texture.value(0.0, 0.0, &point))
+                .collect::<Vec<Vec3>>();
+        };
+
+        assert_eq!(values(3), values(3));
+        assert_ne!(values(3), values(4));
+    }
+}
@@ -0,0 +1,221 @@
+use glam::{Mat3, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ToneMapping {
+    // Clip everything above 1
+    None,
+    // Luminance based Reinhard operator
+    Reinhard,
+    // Stephen Hill's fit of the ACES reference and output transforms
+    Aces,
+    // Polynomial approximation of the AgX sigmoid
+    Agx,
+}
+
+/**
+ * Turns linear scene radiance into display values, ready to be quantized by an 8 bit encoder
+ */
+pub struct PostProcess {
+    // Exposure adjustment in stops
+    pub exposure: f32,
+    pub tone_mapping: ToneMapping,
+}
+
+impl PostProcess {
+    pub fn apply(&self, radiance: Vec3) -> Vec3 {
+        let exposed = radiance * 2.0f32.powf(self.exposure);
+
+        let mapped = match self.tone_mapping {
+            ToneMapping::None => exposed,
+            ToneMapping::Reinhard => reinhard(exposed),
+            ToneMapping::Aces => aces_fitted(exposed),
+            ToneMapping::Agx => agx(exposed),
+        };
+
+        let mapped = mapped.clamp(Vec3::ZERO, Vec3::ONE);
+
+        return Vec3::new(
+            linear_to_srgb(mapped.x),
+            linear_to_srgb(mapped.y),
+            linear_to_srgb(mapped.z),
+        );
+    }
+}
+
+// The piecewise sRGB transfer function (linear values to encoded values)
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        return 12.92 * value;
+    }
+
+    return 1.055 * value.powf(1.0 / 2.4) - 0.055;
+}
+
+// The inverse of linear_to_srgb
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        return value / 12.92;
+    }
+
+    return ((value + 0.055) / 1.055).powf(2.4);
+}
+
+pub fn luminance(color: Vec3) -> f32 {
+    return color.dot(Vec3::new(0.2126, 0.7152, 0.0722));
+}
+
+fn reinhard(color: Vec3) -> Vec3 {
+    let l = luminance(color);
+
+    if l <= 0.0 {
+        return Vec3::ZERO;
+    }
+
+    return color * (1.0 / (1.0 + l));
+}
+
+fn aces_fitted(color: Vec3) -> Vec3 {
+    // sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+    let input = Mat3::from_cols(
+        Vec3::new(0.59719, 0.07600, 0.02840),
+        Vec3::new(0.35458, 0.90834, 0.13383),
+        Vec3::new(0.04823, 0.01566, 0.83777),
+    );
+
+    // ODT_SAT => XYZ => D60_2_D65 => sRGB
+    let output = Mat3::from_cols(
+        Vec3::new(1.60475, -0.10208, -0.00327),
+        Vec3::new(-0.53108, 1.10813, -0.07276),
+        Vec3::new(-0.07367, -0.00605, 1.07602),
+    );
+
+    let v = input * color;
+
+    // Fit of the RRT and ODT curves
+    let a = v * (v + 0.0245786) - 9.0537e-05;
+    let b = v * (0.983729 * v + 0.432951) + 0.238081;
+
+    return output * (a / b);
+}
+
+fn agx(color: Vec3) -> Vec3 {
+    let inset = Mat3::from_cols(
+        Vec3::new(0.8424791, 0.04232824, 0.04237565),
+        Vec3::new(0.0784336, 0.8784686, 0.0784336),
+        Vec3::new(0.07922374, 0.07916613, 0.879143),
+    );
+
+    let outset = Mat3::from_cols(
+        Vec3::new(1.196879, -0.05289685, -0.05297164),
+        Vec3::new(-0.09802088, 1.151903, -0.09804345),
+        Vec3::new(-0.09902974, -0.09896118, 1.151074),
+    );
+
+    // Encode into the log2 range the curve was fitted for
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    let encoded = (inset * color.max(Vec3::splat(1e-10)))
+        .to_array()
+        .map(|channel| ((channel.log2() - min_ev) / (max_ev - min_ev)).clamp(0.0, 1.0));
+
+    let curved = Vec3::from(encoded.map(agx_contrast));
+
+    // The curve produces display encoded values, so undo the display gamma to get back to linear
+    let display = (outset * curved).max(Vec3::ZERO);
+    return Vec3::new(
+        display.x.powf(2.2),
+        display.y.powf(2.2),
+        display.z.powf(2.2),
+    );
+}
+
+// 6th order polynomial approximation of the AgX sigmoid
+fn agx_contrast(x: f32) -> f32 {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - 0.00232;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trips_every_8_bit_value() {
+        for code in 0..=255u8 {
+            let encoded = code as f32 / 255.0;
+            let round_trip = linear_to_srgb(srgb_to_linear(encoded));
+
+            assert!((round_trip - encoded).abs() < 1e-5);
+            assert_eq!((round_trip * 255.0).round() as u8, code);
+        }
+    }
+
+    #[test]
+    fn srgb_curve_is_continuous_and_hits_known_values() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+        assert!((linear_to_srgb(0.5) - 0.7354).abs() < 1e-4);
+        assert!((srgb_to_linear(0.5) - 0.2140).abs() < 1e-4);
+
+        // The linear and power segments meet where the pieces switch
+        let (below, above) = (linear_to_srgb(0.0031308), linear_to_srgb(0.0031309));
+        assert!((below - above).abs() < 1e-5);
+        let (below, above) = (srgb_to_linear(0.04045), srgb_to_linear(0.04046));
+        assert!((below - above).abs() < 1e-5);
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let brighter = PostProcess {
+            exposure: 1.0,
+            tone_mapping: ToneMapping::None,
+        };
+
+        let color = Vec3::new(0.05, 0.1, 0.2);
+        let expected = Vec3::new(0.1, 0.2, 0.4).to_array().map(linear_to_srgb);
+        assert!(
+            (brighter.apply(color) - Vec3::from(expected))
+                .abs()
+                .max_element()
+                < 1e-6
+        );
+
+        // Without tone mapping, anything too bright is clipped
+        assert!(
+            (brighter.apply(Vec3::splat(3.0)) - Vec3::ONE)
+                .abs()
+                .max_element()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn tone_mapping_keeps_black_and_order_of_brightness() {
+        for tone_mapping in [ToneMapping::Reinhard, ToneMapping::Aces, ToneMapping::Agx] {
+            let post = PostProcess {
+                exposure: 0.0,
+                tone_mapping,
+            };
+
+            assert!(post.apply(Vec3::ZERO).max_element() < 0.02);
+
+            let mut previous = post.apply(Vec3::ZERO);
+            for step in 1..=60 {
+                let mapped = post.apply(Vec3::splat(0.01 * 1.2f32.powi(step)));
+
+                assert!(mapped.min_element() >= 0.0 && mapped.max_element() <= 1.0);
+                assert!(luminance(mapped) >= luminance(previous));
+                previous = mapped;
+            }
+
+            // Very bright grays end up close to white
+            assert!(previous.min_element() > 0.9);
+        }
+    }
+}
@@ -0,0 +1,576 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use glam::{Affine3A, BVec3, Mat3, Quat, Vec3};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+};
+
+// Number of poses the bounding box of a moving object is computed from
+const MOTION_BOUNDS_STEPS: usize = 32;
+
+/**
+ * Places an object in the world with an affine transform (any mix of translation, rotation and scale).
+ * Rays are moved into the space of the object, and hits are moved back out into world space.
+ *
+ * The object can be shared, so one heavy object (like a mesh with its own BVH) can be placed many times
+ * for the cost of a matrix each.
+ */
+pub struct Transform {
+    object: Arc<dyn Hittable>,
+    matrices: Matrices,
+    // Set when the object moves to another pose while the shutter is open
+    motion: Option<Motion>,
+    bbox: Option<Aabb>,
+    // Used instead of the materials of the object when set
+    material: Option<Arc<dyn Material>>,
+}
+
+#[derive(Clone)]
+struct Matrices {
+    object_to_world: Affine3A,
+    world_to_object: Affine3A,
+    // Normals transform with the inverse transpose, so they stay perpendicular under non-uniform scale
+    normal_to_world: Mat3,
+}
+
+impl Matrices {
+    fn new(object_to_world: Affine3A) -> Self {
+        let world_to_object = object_to_world.inverse();
+
+        return Self {
+            object_to_world,
+            world_to_object,
+            normal_to_world: Mat3::from(world_to_object.matrix3).transpose(),
+        };
+    }
+}
+
+/**
+ * Moves from one pose at time0 to another at time1. The poses are split into scale, rotation and
+ * translation, which are interpolated separately so rotations turn rather than shrink. Shear is lost.
+ */
+struct Motion {
+    start: (Vec3, Quat, Vec3),
+    end: (Vec3, Quat, Vec3),
+    time0: f32,
+    time1: f32,
+    // The pose after time1, which doesn't need interpolating
+    end_matrices: Matrices,
+}
+
+impl Motion {
+    // How far the object is from the first pose to the second at the given time, from 0 to 1
+    fn fraction(&self, time: f32) -> f32 {
+        return ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+    }
+
+    fn at_fraction(&self, fraction: f32) -> Affine3A {
+        let (scale0, rotation0, translation0) = self.start;
+        let (scale1, rotation1, translation1) = self.end;
+
+        return Affine3A::from_scale_rotation_translation(
+            scale0.lerp(scale1, fraction),
+            rotation0.slerp(rotation1, fraction),
+            translation0.lerp(translation1, fraction),
+        );
+    }
+}
+
+impl Transform {
+    pub fn new(object: Box<dyn Hittable>, object_to_world: Affine3A) -> Self {
+        return Transform::instance(Arc::from(object), object_to_world);
+    }
+
+    // Place a shared object
+    pub fn instance(object: Arc<dyn Hittable>, object_to_world: Affine3A) -> Self {
+        let bbox = object
+            .bounding_box()
+            .map(|bbox| transformed_bounding_box(&bbox, object_to_world));
+
+        return Self {
+            object,
+            matrices: Matrices::new(object_to_world),
+            motion: None,
+            bbox,
+            material: None,
+        };
+    }
+
+    // Replace the materials of the object. The material can be shared between instances as well.
+    pub fn with_material(mut self, material: Arc<dyn Material>) -> Self {
+        self.material = Some(material);
+        return self;
+    }
+
+    /**
+     * Move the object from its pose at time0 to `end` at time1. It waits in the first pose before time0,
+     * and in the second after time1
+     */
+    pub fn with_motion(mut self, end: Affine3A, time0: f32, time1: f32) -> Self {
+        // Poses are only interpolated for objects that actually move
+        if end == self.matrices.object_to_world || time1 <= time0 {
+            return self;
+        }
+
+        let motion = Motion {
+            start: self
+                .matrices
+                .object_to_world
+                .to_scale_rotation_translation(),
+            end: end.to_scale_rotation_translation(),
+            time0,
+            time1,
+            end_matrices: Matrices::new(end),
+        };
+
+        // Rotating corners bulge out between the poses, which the padding makes up for
+        self.bbox = self.object.bounding_box().map(|bbox| {
+            let swept = (0..=MOTION_BOUNDS_STEPS).fold(Aabb::empty(), |swept, step| {
+                let pose = motion.at_fraction(step as f32 / MOTION_BOUNDS_STEPS as f32);
+                swept.surrounding(&transformed_bounding_box(&bbox, pose))
+            });
+
+            let padding = Vec3::splat(0.01 * (swept.max - swept.min).length());
+            Aabb::new(swept.min - padding, swept.max + padding)
+        });
+
+        self.motion = Some(motion);
+        return self;
+    }
+
+    // The pose of the object at the given time. Only poses in between the two are computed
+    fn matrices(&self, time: f32) -> Cow<'_, Matrices> {
+        let Some(motion) = &self.motion else {
+            return Cow::Borrowed(&self.matrices);
+        };
+
+        return match motion.fraction(time) {
+            fraction if fraction <= 0.0 => Cow::Borrowed(&self.matrices),
+            fraction if fraction >= 1.0 => Cow::Borrowed(&motion.end_matrices),
+            fraction => Cow::Owned(Matrices::new(motion.at_fraction(fraction))),
+        };
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let matrices = self.matrices(ray.time);
+
+        // The direction isn't normalized, so t means the same in both spaces
+        let object_ray = Ray::new(
+            matrices.world_to_object.transform_point3(ray.origin),
+            matrices.world_to_object.transform_vector3(ray.direction),
+            ray.time,
+        );
+
+        let mut rec = self.object.hit(&object_ray, t_min, t_max)?;
+
+        // The normal keeps facing against the ray, so front_face is still correct
+        rec.point = matrices.object_to_world.transform_point3(rec.point);
+        rec.normal = (matrices.normal_to_world * rec.normal).normalize();
+
+        if let Some(material) = &self.material {
+            rec.material = material.as_ref();
+        }
+
+        return Some(rec);
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let matrices = self.matrices(ray.time);
+
+        let object_ray = Ray::new(
+            matrices.world_to_object.transform_point3(ray.origin),
+            matrices.world_to_object.transform_vector3(ray.direction),
+            ray.time,
+        );
+
+        return self.object.transmittance(&object_ray, t_min, t_max);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return self.bbox;
+    }
+
+    // Replacing the materials can't add lights the object doesn't sample, only take them away
+    fn is_light(&self) -> bool {
+        return self.object.is_light()
+            && self
+                .material
+                .as_ref()
+                .is_none_or(|material| material.is_emissive());
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        let matrices = self.matrices(time);
+        let linear = Mat3::from(matrices.world_to_object.matrix3);
+
+        let object_direction = linear * direction.normalize();
+        let pdf = self.object.pdf_value(
+            matrices.world_to_object.transform_point3(origin),
+            object_direction,
+            time,
+        );
+
+        // Scaling stretches solid angles unevenly, by the determinant over the cubed length of the
+        // direction once it is in object space
+        return pdf * linear.determinant().abs() / object_direction.length().powi(3);
+    }
+
+    fn random_toward(&self, origin: Vec3, time: f32) -> Vec3 {
+        let matrices = self.matrices(time);
+        let toward = self
+            .object
+            .random_toward(matrices.world_to_object.transform_point3(origin), time);
+
+        return matrices.object_to_world.transform_vector3(toward);
+    }
+
+    fn sample_surface(&self, time: f32) -> Option<(HitRecord<'_>, f32)> {
+        if !self.is_light() {
+            return None;
+        }
+
+        let matrices = self.matrices(time);
+        let (mut rec, pdf) = self.object.sample_surface(time)?;
+
+        // Areas grow by the determinant, less the part of it that stretches along the normal
+        let normal = matrices.normal_to_world * rec.normal;
+        let area_scale = Mat3::from(matrices.object_to_world.matrix3)
+            .determinant()
+            .abs()
+            * normal.length();
+
+        rec.point = matrices.object_to_world.transform_point3(rec.point);
+        rec.normal = normal.normalize();
+
+        if let Some(material) = &self.material {
+            rec.material = material.as_ref();
+        }
+
+        return Some((rec, pdf / area_scale));
+    }
+
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        if !self.is_light() {
+            return 0.0;
+        }
+
+        let matrices = self.matrices(time);
+        let object_origin = matrices.world_to_object.transform_point3(origin);
+        let object_direction = matrices.world_to_object.transform_vector3(direction);
+
+        let object_ray = Ray::new(object_origin, object_direction, time);
+        let Some(rec) = self.object.hit(&object_ray, 0.001, f32::INFINITY) else {
+            return 0.0;
+        };
+
+        // The same change of area as for the points sample_surface picks
+        let area_scale = Mat3::from(matrices.object_to_world.matrix3)
+            .determinant()
+            .abs()
+            * (matrices.normal_to_world * rec.normal).length();
+
+        return self
+            .object
+            .surface_pdf(object_origin, object_direction, time)
+            / area_scale;
+    }
+}
+
+fn transformed_bounding_box(bbox: &Aabb, object_to_world: Affine3A) -> Aabb {
+    let mut result = Aabb::empty();
+
+    for corner in 0..8 {
+        let use_max = BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0);
+        let point = Vec3::select(use_max, bbox.max, bbox.min);
+
+        result = result.grow(object_to_world.transform_point3(point));
+    }
+
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{PI, TAU};
+
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::random;
+    use crate::rect::{AxisRect, BoxShape};
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::vec3::Vec3Extension;
+
+    fn lamp() -> Sphere {
+        return Sphere::new(
+            Vec3::ZERO,
+            1.0,
+            Box::new(DiffuseLight::from_color(Vec3::ONE)),
+        );
+    }
+
+    // Stretched along x, turned and moved away from the origin
+    fn stretched() -> Affine3A {
+        return Affine3A::from_scale_rotation_translation(
+            Vec3::new(2.0, 1.0, 1.0),
+            Quat::from_rotation_z(0.5),
+            Vec3::new(1.0, 2.0, 3.0),
+        );
+    }
+
+    #[test]
+    fn normals_follow_the_inverse_transpose_under_non_uniform_scale() {
+        let ellipsoid = Transform::new(
+            Box::new(lamp()),
+            Affine3A::from_scale(Vec3::new(2.0, 1.0, 1.0)),
+        );
+
+        let origin = Vec3::new(4.0, 3.0, 2.0);
+        for target in [
+            Vec3::new(1.5, 0.2, 0.1),
+            Vec3::new(0.5, 0.5, -0.3),
+            Vec3::new(-0.8, 0.1, 0.6),
+        ] {
+            let ray = Ray::new(origin, target - origin, 0.0);
+            let rec = ellipsoid.hit(&ray, 0.001, f32::INFINITY).unwrap();
+
+            // The gradient of x²/4 + y² + z² = 1
+            let p = rec.point;
+            let expected = Vec3::new(p.x / 4.0, p.y, p.z).normalize();
+
+            assert!((p.x * p.x / 4.0 + p.y * p.y + p.z * p.z - 1.0).abs() < 1e-4);
+            assert!(rec.normal.abs_diff_eq(expected, 1e-4));
+        }
+    }
+
+    #[test]
+    fn hits_are_at_the_same_distance_as_on_the_object() {
+        let object_to_world = stretched();
+        let transformed = Transform::new(Box::new(lamp()), object_to_world);
+        let sphere = lamp();
+
+        let origin = Vec3::new(-4.0, 1.0, 5.0);
+        for target in [
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(2.0, 2.5, 3.2),
+            Vec3::new(0.5, 1.5, 2.5),
+        ] {
+            let direction = target - origin;
+            let world = transformed
+                .hit(&Ray::new(origin, direction, 0.0), 0.001, f32::INFINITY)
+                .unwrap();
+
+            let inverse = object_to_world.inverse();
+            let object_ray = Ray::new(
+                inverse.transform_point3(origin),
+                inverse.transform_vector3(direction),
+                0.0,
+            );
+            let object = sphere.hit(&object_ray, 0.001, f32::INFINITY).unwrap();
+
+            assert!((world.t - object.t).abs() < 1e-4);
+            assert!(world.point.abs_diff_eq(origin + world.t * direction, 1e-4));
+        }
+    }
+
+    #[test]
+    fn light_densities_integrate_to_one() {
+        random::reseed(1);
+        let ellipsoid = Transform::new(Box::new(lamp()), stretched());
+
+        // From outside, and from inside where the sphere samples every direction
+        for origin in [Vec3::new(1.0, 2.0, 4.3), Vec3::new(1.0, 2.0, 3.0)] {
+            let count = 200_000;
+            let sum: f64 = (0..count)
+                .map(|_| ellipsoid.pdf_value(origin, Vec3::random_unit_vector(), 0.0) as f64)
+                .sum();
+
+            let integral = 4.0 * PI as f64 * sum / count as f64;
+            assert!((integral - 1.0).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn surface_densities_integrate_to_one() {
+        random::reseed(2);
+
+        // A sheared rectangle becomes a parallelogram, sampled evenly
+        let shear = Affine3A::from_cols_array(&[
+            2.0, 0.0, 0.0, 0.5, 1.0, 0.0, 0.3, 0.2, 1.0, 0.0, 0.0, 0.0,
+        ]);
+        let light = Box::new(DiffuseLight::from_color(Vec3::ONE));
+        let parallelogram = Transform::new(
+            Box::new(AxisRect::xy(0.0, 1.0, 0.0, 3.0, 0.0, light)),
+            shear,
+        );
+        let area = shear
+            .transform_vector3(Vec3::X)
+            .cross(shear.transform_vector3(3.0 * Vec3::Y))
+            .length();
+
+        for _ in 0..100 {
+            let (rec, pdf) = parallelogram.sample_surface(0.0).unwrap();
+            assert!((pdf * area - 1.0).abs() < 1e-4);
+
+            let origin = Vec3::new(0.0, 0.0, 5.0);
+            let direction = rec.point - origin;
+            let surface_pdf = parallelogram.surface_pdf(origin, direction, 0.0);
+            assert!((surface_pdf * area - 1.0).abs() < 1e-4);
+        }
+
+        // A sphere stretched into a prolate spheroid isn't sampled evenly, but the inverse densities
+        // still add up to its area
+        let spheroid = Transform::new(
+            Box::new(lamp()),
+            Affine3A::from_scale(Vec3::new(2.0, 1.0, 1.0)),
+        );
+        let eccentricity = (1.0 - 1.0 / 4.0f32).sqrt();
+        let area = TAU * (1.0 + 2.0 / eccentricity * eccentricity.asin());
+
+        let count = 200_000;
+        let sum: f64 = (0..count)
+            .map(|_| 1.0 / spheroid.sample_surface(0.0).unwrap().1 as f64)
+            .sum();
+
+        assert!((sum / count as f64 / area as f64 - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn poses_outside_the_shutter_match_the_still_object() {
+        let end = stretched();
+        let moving =
+            Transform::new(Box::new(lamp()), Affine3A::IDENTITY).with_motion(end, 0.0, 1.0);
+        let still = Transform::new(Box::new(lamp()), end);
+
+        let origin = Vec3::new(-4.0, 1.0, 5.0);
+        let direction = Vec3::new(1.0, 2.0, 3.0) - origin;
+        let after = moving
+            .hit(&Ray::new(origin, direction, 2.0), 0.001, f32::INFINITY)
+            .unwrap();
+        let expected = still
+            .hit(&Ray::new(origin, direction, 2.0), 0.001, f32::INFINITY)
+            .unwrap();
+
+        assert_eq!(after.t, expected.t);
+        assert_eq!(after.normal, expected.normal);
+
+        // Ending where it started is no motion at all
+        let resting = Transform::new(Box::new(lamp()), end).with_motion(end, 0.0, 1.0);
+        assert!(resting.motion.is_none());
+    }
+
+    #[test]
+    fn instances_share_one_object() {
+        let sphere: Arc<dyn Hittable> = Arc::new(lamp());
+        let instances: Vec<Transform> = [-3.0, 0.0, 3.0]
+            .into_iter()
+            .map(|x| {
+                Transform::instance(
+                    Arc::clone(&sphere),
+                    Affine3A::from_translation(Vec3::new(x, 0.0, 0.0)),
+                )
+            })
+            .collect();
+
+        assert_eq!(Arc::strong_count(&sphere), 4);
+
+        // Each copy is in its own place, and only there
+        for (index, instance) in instances.iter().enumerate() {
+            for x in [-3.0, 0.0, 3.0] {
+                let ray = Ray::new(Vec3::new(x, 0.0, 5.0), Vec3::NEG_Z, 0.0);
+                let expected = x == -3.0 + 3.0 * index as f32;
+
+                assert_eq!(instance.hit(&ray, 0.001, f32::INFINITY).is_some(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn instances_can_replace_the_material() {
+        let sphere: Arc<dyn Hittable> = Arc::new(lamp());
+        let gray: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(SolidColor::new(Vec3::splat(0.5)))));
+
+        let glowing = Transform::instance(Arc::clone(&sphere), Affine3A::IDENTITY);
+        let painted = Transform::instance(Arc::clone(&sphere), Affine3A::IDENTITY)
+            .with_material(Arc::clone(&gray));
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::NEG_Z, 0.0);
+        let rec = painted.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(!rec.material.is_emissive());
+        assert!(glowing
+            .hit(&ray, 0.001, f32::INFINITY)
+            .unwrap()
+            .material
+            .is_emissive());
+
+        // A painted over light isn't sampled as one
+        assert!(glowing.is_light());
+        assert!(!painted.is_light());
+        assert!(painted.sample_surface(0.0).is_none());
+    }
+
+    #[test]
+    fn moving_bounds_cover_the_whole_shutter() {
+        // A long thin bar, turning so its ends swing out furthest in between the poses the bounds are
+        // computed from
+        let corners = [Vec3::new(-3.0, -0.1, -0.1), Vec3::new(3.0, 0.1, 0.1)];
+        let bar = BoxShape::new(
+            corners[0],
+            corners[1],
+            Box::new(DiffuseLight::from_color(Vec3::ONE)),
+        );
+        let end = Affine3A::from_rotation_translation(
+            Quat::from_rotation_y(2.0),
+            Vec3::new(4.0, 1.0, 0.0),
+        );
+        let moving = Transform::new(Box::new(bar), Affine3A::IDENTITY).with_motion(end, 0.0, 1.0);
+        let bbox = moving.bounding_box().unwrap();
+        let motion = moving.motion.as_ref().unwrap();
+
+        for step in 0..=1000 {
+            let pose = motion.at_fraction(step as f32 / 1000.0);
+
+            for corner in 0..8 {
+                let corner = Vec3::new(
+                    corners[corner & 1].x,
+                    corners[(corner >> 1) & 1].y,
+                    corners[corner >> 2].z,
+                );
+                let point = pose.transform_point3(corner);
+
+                assert!(point.cmpge(bbox.min).all() && point.cmple(bbox.max).all());
+            }
+        }
+    }
+
+    #[test]
+    fn moving_objects_are_in_between_poses_while_the_shutter_is_open() {
+        let moving = Transform::new(Box::new(lamp()), Affine3A::IDENTITY).with_motion(
+            Affine3A::from_translation(Vec3::new(4.0, 0.0, 0.0)),
+            1.0,
+            2.0,
+        );
+
+        for (time, x) in [
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (1.25, 1.0),
+            (1.5, 2.0),
+            (2.0, 4.0),
+            (3.0, 4.0),
+        ] {
+            let ray = Ray::new(Vec3::new(x, 0.0, 5.0), Vec3::NEG_Z, time);
+            let rec = moving.hit(&ray, 0.001, f32::INFINITY).unwrap();
+
+            assert!((rec.t - 4.0).abs() < 1e-4, "{} at time {}", rec.t, time);
+        }
+    }
+}
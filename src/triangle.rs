@@ -0,0 +1,344 @@
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3};
+use rand::Rng;
+
+use crate::aabb::Aabb;
+use crate::hittable::HitRecord;
+use crate::hittable::{area_pdf, Hittable};
+use crate::material::Material;
+use crate::random;
+use crate::ray::Ray;
+
+/**
+ * Indexed triangle mesh. Vertex data is stored once and shared by all the triangles referencing it.
+ * Normals and uvs, if present, are indexed the same way as the positions.
+ */
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub uvs: Option<Vec<Vec2>>,
+    pub indices: Vec<[usize; 3]>,
+    pub material: Box<dyn Material>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<Vec2>>,
+        indices: Vec<[usize; 3]>,
+        material: Box<dyn Material>,
+    ) -> Self {
+        return Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material,
+        };
+    }
+
+    // Split the mesh into one hittable per triangle, all sharing the vertex data
+    pub fn into_triangles(self) -> Vec<Triangle> {
+        let count = self.indices.len();
+        let mesh = Arc::new(self);
+
+        return (0..count)
+            .map(|index| Triangle::new(Arc::clone(&mesh), index))
+            .collect();
+    }
+}
+
+pub struct Triangle {
+    mesh: Arc<TriangleMesh>,
+    index: usize,
+}
+
+impl Triangle {
+    pub fn new(mesh: Arc<TriangleMesh>, index: usize) -> Self {
+        return Self { mesh, index };
+    }
+
+    fn vertices(&self) -> (Vec3, Vec3, Vec3) {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let positions = &self.mesh.positions;
+
+        return (positions[i0], positions[i1], positions[i2]);
+    }
+}
+
+impl Hittable for Triangle {
+    // Möller–Trumbore ray-triangle intersection
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (p0, p1, p2) = self.vertices();
+
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+
+        let pvec = ray.direction.cross(edge2);
+        let determinant = edge1.dot(pvec);
+
+        // The ray is parallel to the triangle
+        if determinant.abs() < 1e-8 {
+            return None;
+        }
+
+        let inv_determinant = 1.0 / determinant;
+
+        let tvec = ray.origin - p0;
+        let b1 = tvec.dot(pvec) * inv_determinant;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec = tvec.cross(edge1);
+        let b2 = ray.direction.dot(qvec) * inv_determinant;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(qvec) * inv_determinant;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+
+        // Use the texture coordinates of the mesh if it has any, otherwise the barycentric coordinates
+        let (u, v) = match &self.mesh.uvs {
+            Some(uvs) => {
+                let uv = b0 * uvs[i0] + b1 * uvs[i1] + b2 * uvs[i2];
+                (uv.x, uv.y)
+            }
+            None => (b1, b2),
+        };
+
+        let geometric_normal = edge1.cross(edge2).normalize();
+
+        let mut hit_record = HitRecord::from_ray(
+            ray,
+            ray.at(t),
+            geometric_normal,
+            t,
+            u,
+            v,
+            self.mesh.material.as_ref(),
+        );
+
+        // Smooth shading. Which side was hit is still decided by the geometric normal.
+        if let Some(normals) = &self.mesh.normals {
+            let mut shading_normal =
+                (b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]).normalize_or_zero();
+
+            if shading_normal != Vec3::ZERO {
+                if shading_normal.dot(geometric_normal) < 0.0 {
+                    shading_normal = -shading_normal;
+                }
+
+                hit_record.normal = match hit_record.front_face {
+                    true => shading_normal,
+                    false => -shading_normal,
+                };
+            }
+        }
+
+        return Some(hit_record);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (p0, p1, p2) = self.vertices();
+
+        let bbox = Aabb::new(p0.min(p1).min(p2), p0.max(p1).max(p2));
+
+        return Some(bbox.padded(0.0001));
+    }
+
+    fn is_light(&self) -> bool {
+        return self.mesh.material.is_emissive();
+    }
+
+    // Smooth shading bends the normal in the hit record, so the density uses the geometric normal
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        let Some(rec) = self.hit(&Ray::new(origin, direction, time), 0.001, f32::INFINITY) else {
+            return 0.0;
+        };
+
+        let (p0, p1, p2) = self.vertices();
+        let n = (p1 - p0).cross(p2 - p0);
+
+        return area_pdf(direction, rec.t, n.normalize(), 0.5 * n.length());
+    }
+
+    fn random_toward(&self, origin: Vec3, _time: f32) -> Vec3 {
+        let (p0, p1, p2) = self.vertices();
+        let (b1, b2) = random_barycentric();
+
+        return p0 + b1 * (p1 - p0) + b2 * (p2 - p0) - origin;
+    }
+
+    // Smooth shading only bends the normals of hit records, the light leaves the flat triangle
+    fn sample_surface(&self, _time: f32) -> Option<(HitRecord<'_>, f32)> {
+        if !self.is_light() {
+            return None;
+        }
+
+        let (p0, p1, p2) = self.vertices();
+        let (b1, b2) = random_barycentric();
+        let n = (p1 - p0).cross(p2 - p0);
+
+        let (u, v) = match &self.mesh.uvs {
+            Some(uvs) => {
+                let [i0, i1, i2] = self.mesh.indices[self.index];
+                let uv = (1.0 - b1 - b2) * uvs[i0] + b1 * uvs[i1] + b2 * uvs[i2];
+                (uv.x, uv.y)
+            }
+            None => (b1, b2),
+        };
+
+        let rec = HitRecord::on_surface(
+            p0 + b1 * (p1 - p0) + b2 * (p2 - p0),
+            n.normalize(),
+            u,
+            v,
+            self.mesh.material.as_ref(),
+        );
+
+        return Some((rec, 2.0 / n.length()));
+    }
+
+    fn surface_pdf(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        if !self.is_light()
+            || self
+                .hit(&Ray::new(origin, direction, time), 0.001, f32::INFINITY)
+                .is_none()
+        {
+            return 0.0;
+        }
+
+        let (p0, p1, p2) = self.vertices();
+        return 2.0 / (p1 - p0).cross(p2 - p0).length();
+    }
+}
+
+// Barycentric coordinates of a point spread uniformly over a triangle
+fn random_barycentric() -> (f32, f32) {
+    // Folding the far half of the parallelogram back keeps the points uniform over the triangle
+    let mut rng = random::rng();
+    let (mut b1, mut b2) = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+    if b1 + b2 > 1.0 {
+        (b1, b2) = (1.0 - b1, 1.0 - b2);
+    }
+
+    return (b1, b2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    // The triangle (0, 0, 0), (1, 0, 0), (0, 1, 0), facing +Z
+    fn triangle(normals: Option<Vec<Vec3>>, uvs: Option<Vec<Vec2>>) -> Triangle {
+        let mesh = TriangleMesh::new(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            normals,
+            uvs,
+            vec![[0, 1, 2]],
+            Box::new(Lambertian::new(Box::new(SolidColor::new(Vec3::ONE)))),
+        );
+
+        return mesh.into_triangles().pop().unwrap();
+    }
+
+    // Straight down onto the plane of the triangle, from z = 1
+    fn hit_down(triangle: &Triangle, x: f32, y: f32) -> Option<HitRecord<'_>> {
+        let ray = Ray::new(Vec3::new(x, y, 1.0), Vec3::NEG_Z, 0.0);
+        return triangle.hit(&ray, 0.001, f32::INFINITY);
+    }
+
+    #[test]
+    fn rays_hit_inside_and_on_the_edges() {
+        let triangle = triangle(None, None);
+
+        let rec = hit_down(&triangle, 0.25, 0.5).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-6);
+        assert!(rec.point.abs_diff_eq(Vec3::new(0.25, 0.5, 0.0), 1e-6));
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::Z);
+
+        // Edges and corners count as inside
+        for (x, y) in [(0.5, 0.0), (0.0, 0.5), (0.5, 0.5), (0.0, 0.0), (1.0, 0.0)] {
+            assert!(hit_down(&triangle, x, y).is_some(), "{} {}", x, y);
+        }
+
+        for (x, y) in [(0.5, -0.01), (-0.01, 0.5), (0.51, 0.5), (2.0, 2.0)] {
+            assert!(hit_down(&triangle, x, y).is_none(), "{} {}", x, y);
+        }
+
+        // Hits from behind face the ray, and hits out of the range of t are left out
+        let ray = Ray::new(Vec3::new(0.25, 0.25, -2.0), Vec3::Z, 0.0);
+        let rec = triangle.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, Vec3::NEG_Z);
+        assert!(triangle.hit(&ray, 0.001, 1.9).is_none());
+        assert!(triangle.hit(&ray, 2.1, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn rays_parallel_to_the_triangle_miss() {
+        let triangle = triangle(None, None);
+
+        // Both above the triangle and in its plane, straight through it
+        for origin in [Vec3::new(-1.0, 0.25, 1.0), Vec3::new(-1.0, 0.25, 0.0)] {
+            let ray = Ray::new(origin, Vec3::X, 0.0);
+            assert!(triangle.hit(&ray, 0.001, f32::INFINITY).is_none());
+        }
+    }
+
+    #[test]
+    fn uvs_are_barycentric_without_mesh_uvs() {
+        let plain = triangle(None, None);
+        let rec = hit_down(&plain, 0.25, 0.5).unwrap();
+        assert!((rec.u - 0.25).abs() < 1e-6 && (rec.v - 0.5).abs() < 1e-6);
+
+        // With uvs, they are blended with the same weights
+        let uvs = vec![
+            Vec2::new(0.5, 0.5),
+            Vec2::new(1.0, 0.5),
+            Vec2::new(0.5, 1.0),
+        ];
+        let mapped = triangle(None, Some(uvs));
+        let rec = hit_down(&mapped, 0.25, 0.5).unwrap();
+        assert!((rec.u - 0.625).abs() < 1e-6 && (rec.v - 0.75).abs() < 1e-6);
+    }
+
+    #[test]
+    fn smooth_normals_are_blended_and_kept_on_the_geometric_side() {
+        let tilted = vec![
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0).normalize(),
+            Vec3::new(0.0, 1.0, 1.0).normalize(),
+        ];
+        let smooth = triangle(Some(tilted.clone()), None);
+        let rec = hit_down(&smooth, 0.25, 0.5).unwrap();
+        let expected = (0.25 * tilted[0] + 0.25 * tilted[1] + 0.5 * tilted[2]).normalize();
+        assert!(rec.normal.abs_diff_eq(expected, 1e-6));
+
+        // Normals pointing into the back are flipped to the side of the geometric normal
+        let backwards = tilted.iter().map(|normal| -*normal).collect();
+        let triangle = triangle(Some(backwards), None);
+
+        let rec = hit_down(&triangle, 0.25, 0.5).unwrap();
+        assert!(rec.front_face);
+        assert!(rec.normal.abs_diff_eq(expected, 1e-6));
+
+        // Seen from behind, the normal still faces the ray
+        let ray = Ray::new(Vec3::new(0.25, 0.5, -1.0), Vec3::Z, 0.0);
+        let rec = triangle.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(!rec.front_face);
+        assert!(rec.normal.abs_diff_eq(-expected, 1e-6));
+    }
+}
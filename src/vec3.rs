@@ -1,5 +1,9 @@
+use std::f32::consts::TAU;
+
 use glam::Vec3;
-use rand::{thread_rng, Rng};
+use rand::Rng;
+
+use crate::random;
 
 pub trait Vec3Extension {
     fn rand() -> Vec3;
@@ -7,6 +11,7 @@ pub trait Vec3Extension {
     fn random_in_unit_sphere() -> Vec3;
     fn random_in_unit_disk() -> Vec3;
     fn random_unit_vector() -> Vec3;
+    fn random_in_cone(self, one_minus_cos: f32) -> Vec3;
     fn is_near_zero(&self) -> bool;
     fn reflect_in(self, n: Vec3) -> Vec3;
     fn refract_off(self, n: Vec3, etai_over_etat: f32) -> Vec3;
@@ -14,7 +19,7 @@ pub trait Vec3Extension {
 
 impl Vec3Extension for Vec3 {
     fn rand_range(min: f32, max: f32) -> Vec3 {
-        let mut rng = thread_rng();
+        let mut rng = random::rng();
 
         return Vec3::new(
             rng.gen_range(min..=max),
@@ -40,7 +45,7 @@ impl Vec3Extension for Vec3 {
     }
 
     fn random_in_unit_disk() -> Vec3 {
-        let mut rng = thread_rng();
+        let mut rng = random::rng();
 
         loop {
             let p = Vec3::new(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0), 0.0);
@@ -57,10 +62,26 @@ impl Vec3Extension for Vec3 {
         return Vec3::random_in_unit_sphere().normalize();
     }
 
+    /**
+     * Uniformly random direction within the cone around this unit vector whose half angle has the given
+     * 1 - cos. Working with 1 - cos keeps the tiny cones of far away lights, like the sun, from rounding
+     * down to a single direction.
+     */
+    fn random_in_cone(self, one_minus_cos: f32) -> Vec3 {
+        let (u, v) = self.any_orthonormal_pair();
+
+        let mut rng = random::rng();
+        let phi = TAU * rng.gen_range(0.0..1.0);
+        let one_minus_z = rng.gen_range(0.0..1.0) * one_minus_cos;
+        let sin_theta = (one_minus_z * (2.0 - one_minus_z)).max(0.0).sqrt();
+
+        return sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + (1.0 - one_minus_z) * self;
+    }
+
     // Return true if the vector is close to zero in all dimensions.
     fn is_near_zero(&self) -> bool {
         let s = 0.00000001;
-        return (self.x < s) && (self.y < s) && (self.z < s);
+        return (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s);
     }
 
     fn reflect_in(self, n: Vec3) -> Vec3 {
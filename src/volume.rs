@@ -0,0 +1,408 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use glam::Vec3;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    perlin::Perlin,
+    random,
+    ray::Ray,
+};
+
+/**
+ * Densities on a regular 3D grid of voxels, x varying fastest
+ */
+pub trait DensityGrid: Send + Sync {
+    fn resolution(&self) -> [usize; 3];
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32;
+
+    // The highest density of any voxel
+    fn max_density(&self) -> f32;
+}
+
+/**
+ * Stores every voxel
+ */
+pub struct DenseGrid {
+    resolution: [usize; 3],
+    values: Vec<f32>,
+    max_density: f32,
+}
+
+impl DenseGrid {
+    pub fn from_fn(resolution: [usize; 3], density: impl Fn(usize, usize, usize) -> f32) -> Self {
+        let [nx, ny, nz] = resolution;
+        let mut values = Vec::with_capacity(nx * ny * nz);
+
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    values.push(density(x, y, z).max(0.0));
+                }
+            }
+        }
+
+        let max_density = values.iter().copied().fold(0.0, f32::max);
+
+        return Self {
+            resolution,
+            values,
+            max_density,
+        };
+    }
+}
+
+impl DensityGrid for DenseGrid {
+    fn resolution(&self) -> [usize; 3] {
+        return self.resolution;
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        let [nx, ny, _] = self.resolution;
+        return self.values[x + nx * (y + ny * z)];
+    }
+
+    fn max_density(&self) -> f32 {
+        return self.max_density;
+    }
+}
+
+// Sparse grids are split into blocks of BLOCK_SIZE³ voxels
+const BLOCK_SIZE: usize = 8;
+const BLOCK_VOXELS: usize = BLOCK_SIZE * BLOCK_SIZE * BLOCK_SIZE;
+
+// Marks blocks without any density, which aren't stored
+const EMPTY_BLOCK: u32 = u32::MAX;
+
+/**
+ * Only stores the blocks of voxels that have some density, so mostly empty volumes (like clouds and
+ * smoke plumes) take a fraction of the memory of a dense grid
+ */
+pub struct SparseGrid {
+    resolution: [usize; 3],
+    blocks_resolution: [usize; 3],
+    // Index into `blocks` for every block in the grid, or EMPTY_BLOCK
+    block_indices: Vec<u32>,
+    blocks: Vec<[f32; BLOCK_VOXELS]>,
+    max_density: f32,
+}
+
+impl SparseGrid {
+    pub fn from_fn(resolution: [usize; 3], density: impl Fn(usize, usize, usize) -> f32) -> Self {
+        let blocks_resolution = resolution.map(|n| n.div_ceil(BLOCK_SIZE));
+        let [bx_count, by_count, bz_count] = blocks_resolution;
+
+        let mut block_indices = Vec::with_capacity(bx_count * by_count * bz_count);
+        let mut blocks = Vec::new();
+        let mut max_density: f32 = 0.0;
+
+        for bz in 0..bz_count {
+            for by in 0..by_count {
+                for bx in 0..bx_count {
+                    let mut block = [0.0; BLOCK_VOXELS];
+                    let mut block_max: f32 = 0.0;
+
+                    for (i, value) in block.iter_mut().enumerate() {
+                        let x = bx * BLOCK_SIZE + i % BLOCK_SIZE;
+                        let y = by * BLOCK_SIZE + (i / BLOCK_SIZE) % BLOCK_SIZE;
+                        let z = bz * BLOCK_SIZE + i / (BLOCK_SIZE * BLOCK_SIZE);
+
+                        // Blocks at the far edges stick out of the grid
+                        if x < resolution[0] && y < resolution[1] && z < resolution[2] {
+                            *value = density(x, y, z).max(0.0);
+                            block_max = block_max.max(*value);
+                        }
+                    }
+
+                    if block_max > 0.0 {
+                        block_indices.push(blocks.len() as u32);
+                        blocks.push(block);
+                        max_density = max_density.max(block_max);
+                    } else {
+                        block_indices.push(EMPTY_BLOCK);
+                    }
+                }
+            }
+        }
+
+        return Self {
+            resolution,
+            blocks_resolution,
+            block_indices,
+            blocks,
+            max_density,
+        };
+    }
+}
+
+impl DensityGrid for SparseGrid {
+    fn resolution(&self) -> [usize; 3] {
+        return self.resolution;
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        let [bx_count, by_count, _] = self.blocks_resolution;
+        let block = (x / BLOCK_SIZE) + bx_count * ((y / BLOCK_SIZE) + by_count * (z / BLOCK_SIZE));
+
+        let index = self.block_indices[block];
+
+        if index == EMPTY_BLOCK {
+            return 0.0;
+        }
+
+        let i = (x % BLOCK_SIZE) + BLOCK_SIZE * ((y % BLOCK_SIZE) + BLOCK_SIZE * (z % BLOCK_SIZE));
+        return self.blocks[index as usize][i];
+    }
+
+    fn max_density(&self) -> f32 {
+        return self.max_density;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RawFormat {
+    // Little endian 32 bit floats
+    F32,
+    // Bytes, where 255 is a density of 1
+    U8,
+}
+
+/**
+ * Read densities from a headerless file of voxels, x varying fastest, then y, then z
+ */
+pub fn load_raw(path: &Path, resolution: [usize; 3], format: RawFormat) -> io::Result<Vec<f32>> {
+    let bytes = fs::read(path)?;
+    let voxel_count = resolution.iter().product::<usize>();
+
+    let bytes_per_voxel = match format {
+        RawFormat::F32 => 4,
+        RawFormat::U8 => 1,
+    };
+
+    if bytes.len() != voxel_count * bytes_per_voxel {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "expected {} bytes for {}x{}x{} voxels, but the file has {}",
+                voxel_count * bytes_per_voxel,
+                resolution[0],
+                resolution[1],
+                resolution[2],
+                bytes.len()
+            ),
+        ));
+    }
+
+    return Ok(match format {
+        RawFormat::F32 => bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect(),
+        RawFormat::U8 => bytes.iter().map(|&byte| byte as f32 / 255.0).collect(),
+    });
+}
+
+/**
+ * A puffy cloud filling most of the grid: a squashed ball with its edge broken up by noise
+ */
+pub fn cloud(seed: u64, resolution: [usize; 3]) -> impl Fn(usize, usize, usize) -> f32 {
+    let perlin = Perlin::new(seed);
+
+    return move |x, y, z| {
+        // Position in [-1, 1] on every axis
+        let p = Vec3::new(
+            (x as f32 + 0.5) / resolution[0] as f32,
+            (y as f32 + 0.5) / resolution[1] as f32,
+            (z as f32 + 0.5) / resolution[2] as f32,
+        ) * 2.0
+            - Vec3::ONE;
+
+        let shape = 1.0 - (p * Vec3::new(1.0, 1.2, 1.0)).length();
+        let noise = perlin.fbm(3.0 * p, 5);
+
+        return (2.0 * (shape + 0.6 * noise) - 0.2).clamp(0.0, 1.0);
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tracking {
+    // Walk the ray with steps sized for the densest voxel, and collide at each step with the chance
+    // of the local density over the highest one. Shadow rays are either blocked or not.
+    Delta,
+    // Collide the same way, but have shadow rays carry the fraction of light that ratio tracking
+    // estimates gets through, which is less noisy than blocking them or not
+    Ratio,
+}
+
+/**
+ * Volume with a density that varies from place to place, given by a grid of voxels spanning the bounds.
+ * The material decides how light scatters inside, normally `Isotropic`.
+ */
+pub struct HeterogeneousMedium {
+    bounds: Aabb,
+    grid: Box<dyn DensityGrid>,
+    // Multiplies the densities of the grid
+    density_scale: f32,
+    tracking: Tracking,
+    phase_function: Box<dyn Material>,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(
+        bounds: Aabb,
+        grid: Box<dyn DensityGrid>,
+        density_scale: f32,
+        tracking: Tracking,
+        phase_function: Box<dyn Material>,
+    ) -> Self {
+        return Self {
+            bounds,
+            grid,
+            density_scale,
+            tracking,
+            phase_function,
+        };
+    }
+
+    // Density at a point, interpolated between the centers of the voxels around it
+    fn density(&self, point: Vec3) -> f32 {
+        let resolution = self.grid.resolution();
+        let size = self.bounds.max - self.bounds.min;
+
+        let grid_point = (point - self.bounds.min) / size
+            * Vec3::new(
+                resolution[0] as f32,
+                resolution[1] as f32,
+                resolution[2] as f32,
+            )
+            - Vec3::splat(0.5);
+
+        let base = grid_point.floor();
+        let fraction = grid_point - base;
+
+        let mut density = 0.0;
+
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let index = [0, 1, 2].map(|axis| base[axis] as i64 + offset[axis] as i64);
+
+            // Voxels outside the grid are empty
+            if (0..3).any(|axis| index[axis] < 0 || index[axis] >= resolution[axis] as i64) {
+                continue;
+            }
+
+            let weight = (0..3)
+                .map(|axis| match offset[axis] {
+                    1 => fraction[axis],
+                    _ => 1.0 - fraction[axis],
+                })
+                .product::<f32>();
+
+            density += weight
+                * self
+                    .grid
+                    .voxel(index[0] as usize, index[1] as usize, index[2] as usize);
+        }
+
+        return self.density_scale * density;
+    }
+
+    fn majorant(&self) -> f32 {
+        return self.density_scale * self.grid.max_density();
+    }
+
+    // Delta tracking. The distance to the first real collision between t_min and t_max, if there is one
+    fn sample_collision(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        let majorant = self.majorant();
+        let ray_length = ray.direction.length();
+        let mut rng = random::rng();
+
+        let mut t = t_min;
+
+        loop {
+            t -= (1.0 - rng.gen_range(0.0..1.0f32)).ln() / (majorant * ray_length);
+
+            if t >= t_max {
+                return None;
+            }
+
+            if rng.gen_range(0.0..1.0) * majorant < self.density(ray.at(t)) {
+                return Some(t);
+            }
+        }
+    }
+
+    // Ratio tracking. An unbiased estimate of the fraction of light passing between t_min and t_max
+    fn ratio_tracking(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let majorant = self.majorant();
+        let ray_length = ray.direction.length();
+        let mut rng = random::rng();
+
+        let mut t = t_min;
+        let mut transmittance = 1.0;
+
+        loop {
+            t -= (1.0 - rng.gen_range(0.0..1.0f32)).ln() / (majorant * ray_length);
+
+            if t >= t_max {
+                return transmittance;
+            }
+
+            transmittance *= 1.0 - self.density(ray.at(t)) / majorant;
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        if self.majorant() <= 0.0 {
+            return None;
+        }
+
+        let (t_enter, t_exit) = self.bounds.intersect(ray, t_min.max(0.0), t_max)?;
+
+        let t = self.sample_collision(ray, t_enter, t_exit)?;
+
+        // There is no surface inside a volume, so the normal and the side are arbitrary
+        return Some(HitRecord {
+            point: ray.at(t),
+            normal: Vec3::X,
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            material: self.phase_function.as_ref(),
+        });
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        if self.tracking == Tracking::Delta {
+            return match self.hit(ray, t_min, t_max) {
+                Some(_) => 0.0,
+                None => 1.0,
+            };
+        }
+
+        if self.majorant() <= 0.0 {
+            return 1.0;
+        }
+
+        return match self.bounds.intersect(ray, t_min.max(0.0), t_max) {
+            Some((t_enter, t_exit)) => self.ratio_tracking(ray, t_enter, t_exit),
+            None => 1.0,
+        };
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return Some(self.bounds);
+    }
+}